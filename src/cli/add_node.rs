@@ -0,0 +1,69 @@
+use std::{path::PathBuf, time::Duration};
+
+use structopt::StructOpt;
+
+use crate::{
+    cluster::{membership::add_node, runtime::READY_TIMEOUT},
+    error::RrError,
+    local::{
+        cluster::current_cluster,
+        lock::{lock_state, LOCK_TIMEOUT},
+    },
+};
+
+use super::{
+    cmd::Executable,
+    output::{node_document, print_json, OutputFormat},
+};
+
+#[derive(Debug, StructOpt)]
+pub struct ClusterAddNode {
+    #[structopt(
+        name = "cluster-host",
+        short = "-h",
+        long = "--cluster-host",
+        default_value = "127.0.0.1"
+    )]
+    cluster_host: String,
+
+    #[structopt(
+        name = "conf",
+        short = "-c",
+        long = "--conf",
+        parse(from_os_str),
+        help = "Configuration file of the server to start and add."
+    )]
+    conf: PathBuf,
+
+    #[structopt(
+        name = "replica-of",
+        long = "--replica-of",
+        help = "Port of the primary the new node should replicate. Joins as an empty primary when left out."
+    )]
+    replica_of: Option<u16>,
+
+    #[structopt(
+        name = "ready-timeout",
+        long = "--ready-timeout",
+        help = "Seconds started servers get to accept connections and answer PING (30 by default)."
+    )]
+    ready_timeout: Option<u64>,
+}
+
+impl Executable for ClusterAddNode {
+    fn execute(&self, output: OutputFormat) -> Result<(), RrError> {
+        let _lock = lock_state(&current_cluster(), LOCK_TIMEOUT)?;
+        let entry = add_node(
+            &self.cluster_host,
+            &self.conf,
+            self.replica_of,
+            self.ready_timeout
+                .map(Duration::from_secs)
+                .unwrap_or(READY_TIMEOUT),
+        )?;
+        match output {
+            OutputFormat::Text => Ok(()),
+            OutputFormat::Json => print_json(&node_document(&entry)),
+        }
+    }
+}
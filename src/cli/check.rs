@@ -1,8 +1,14 @@
 use structopt::StructOpt;
 
-use crate::cluster::runtime::check_cluster_health;
+use crate::{
+    cluster::{create::CLUSTER_SLOTS, health::check_cluster_health, validate::Severity},
+    error::RrError,
+};
 
-use super::cmd::Executable;
+use super::{
+    cmd::Executable,
+    output::{print_json, OutputFormat},
+};
 
 #[derive(Debug, StructOpt)]
 pub struct ClusterCheck {
@@ -16,7 +22,35 @@ pub struct ClusterCheck {
 }
 
 impl Executable for ClusterCheck {
-    fn execute(&self) -> Result<(), String> {
-        check_cluster_health(&self.cluster_host)
+    fn execute(&self, output: OutputFormat) -> Result<(), RrError> {
+        let report = check_cluster_health(&self.cluster_host)?;
+        match output {
+            OutputFormat::Text => {
+                report.findings.iter().for_each(|f| match f.severity {
+                    Severity::Error => error!("{}", f),
+                    Severity::Warning => warn!("{}", f),
+                });
+                info!(
+                    "Checked {} nodes, {} of {} slots covered.",
+                    report.nodes_checked, report.slots_covered, CLUSTER_SLOTS
+                );
+            }
+            OutputFormat::Json => print_json(&report)?,
+        }
+
+        if report.is_ok() {
+            info!("Cluster is healthy.");
+            Ok(())
+        } else {
+            let errors = report
+                .findings
+                .iter()
+                .filter(|f| f.severity == Severity::Error)
+                .count();
+            Err(RrError::ClusterHealth(
+                format!("Found {} problems with the cluster.", errors),
+                None,
+            ))
+        }
     }
 }
@@ -0,0 +1,208 @@
+use std::{
+    fmt,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde::Serialize;
+use structopt::StructOpt;
+
+use crate::{
+    error::RrError,
+    local::{
+        cluster::{list_clusters, DEFAULT_CLUSTER_NAME},
+        logs::list_node_logs,
+        pid::is_recorded_server_running,
+        state::{read_cluster_state, ClusterState},
+    },
+};
+
+use super::{
+    cmd::Executable,
+    output::{format_duration, print_json, OutputFormat},
+};
+
+/// Whether the servers recorded for a cluster are running.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ClusterRunState {
+    /// Every recorded server runs.
+    Running,
+    /// Some recorded servers have exited.
+    Degraded,
+    /// Every recorded server has exited.
+    Down,
+    /// No server is recorded.
+    Stopped,
+}
+
+impl fmt::Display for ClusterRunState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ClusterRunState::Running => f.pad("running"),
+            ClusterRunState::Degraded => f.pad("degraded"),
+            ClusterRunState::Down => f.pad("down"),
+            ClusterRunState::Stopped => f.pad("stopped"),
+        }
+    }
+}
+
+/// A cluster rr knows of, as listed by `rr cluster ls`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ClusterSummary {
+    pub name: String,
+    pub state: ClusterRunState,
+    pub nodes: usize,
+    pub running: usize,
+    pub ports: Vec<u16>,
+    pub created_at: Option<u64>,
+}
+
+#[derive(Debug, Serialize)]
+struct ClusterListDocument<'a> {
+    clusters: &'a [ClusterSummary],
+}
+
+#[derive(Debug, StructOpt)]
+pub struct ClusterList {}
+
+fn summarise_cluster(name: &str, state: Option<ClusterState>) -> ClusterSummary {
+    let nodes = state
+        .as_ref()
+        .map(|state| state.nodes.clone())
+        .unwrap_or_default();
+    let running = nodes
+        .iter()
+        .filter(|node| is_recorded_server_running(node))
+        .count();
+    let state_of_run = match (nodes.len(), running) {
+        (0, _) => ClusterRunState::Stopped,
+        (_, 0) => ClusterRunState::Down,
+        (total, running) if running < total => ClusterRunState::Degraded,
+        _ => ClusterRunState::Running,
+    };
+    ClusterSummary {
+        name: name.to_string(),
+        state: state_of_run,
+        nodes: nodes.len(),
+        running,
+        ports: nodes.iter().map(|node| node.port).collect(),
+        created_at: state.map(|state| state.created_at),
+    }
+}
+
+fn print_cluster_table(summaries: &[ClusterSummary]) {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or_default();
+    println!(
+        "{:<20} {:<9} {:<6} {:<8} {:<30} {:<8}",
+        "CLUSTER", "STATE", "NODES", "RUNNING", "PORTS", "AGE"
+    );
+    for summary in summaries.iter() {
+        let ports = if summary.ports.is_empty() {
+            "-".to_string()
+        } else {
+            summary
+                .ports
+                .iter()
+                .map(|port| port.to_string())
+                .collect::<Vec<String>>()
+                .join(",")
+        };
+        println!(
+            "{:<20} {:<9} {:<6} {:<8} {:<30} {:<8}",
+            summary.name,
+            summary.state,
+            summary.nodes,
+            summary.running,
+            ports,
+            summary
+                .created_at
+                .map(|created_at| format_duration(now.saturating_sub(created_at)))
+                .unwrap_or_else(|| "-".to_string()),
+        );
+    }
+}
+
+impl Executable for ClusterList {
+    fn execute(&self, output: OutputFormat) -> Result<(), RrError> {
+        let mut names = list_clusters()?;
+        if !names.iter().any(|name| name == DEFAULT_CLUSTER_NAME) {
+            // The default cluster may still be recorded the way older
+            // releases did, outside the cluster directories.
+            names.insert(0, DEFAULT_CLUSTER_NAME.to_string());
+        }
+        let mut summaries = vec![];
+        for name in names.iter() {
+            let state = read_cluster_state(name)?;
+            // Clusters never started, or whose logs were cleaned up, are
+            // not worth listing.
+            if state.is_none() && list_node_logs(name)?.is_empty() {
+                continue;
+            }
+            summaries.push(summarise_cluster(name, state));
+        }
+        summaries.sort_by(|a, b| a.name.cmp(&b.name));
+        match output {
+            OutputFormat::Text => {
+                print_cluster_table(&summaries);
+                info!("Found {} clusters.", summaries.len());
+                Ok(())
+            }
+            OutputFormat::Json => print_json(&ClusterListDocument {
+                clusters: &summaries,
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use mocktopus::mocking::{MockResult, Mockable};
+
+    use crate::local::pid::PIDEntry;
+
+    use super::*;
+
+    fn state_with_ports(ports: &[u16]) -> ClusterState {
+        ClusterState {
+            created_at: 100,
+            ..ClusterState::new(
+                "search",
+                ports
+                    .iter()
+                    .map(|port| PIDEntry {
+                        port: *port,
+                        pid: u32::from(*port),
+                        ..Default::default()
+                    })
+                    .collect(),
+            )
+        }
+    }
+
+    #[test]
+    fn test_summarise_cluster() {
+        is_recorded_server_running.mock_safe(|entry| MockResult::Return(entry.port != 7002));
+
+        let summary = summarise_cluster("search", Some(state_with_ports(&[7000, 7001])));
+        assert_eq!(summary.state, ClusterRunState::Running);
+        assert_eq!(summary.running, 2);
+        assert_eq!(summary.ports, vec![7000, 7001]);
+        assert_eq!(summary.created_at, Some(100));
+
+        let summary = summarise_cluster("search", Some(state_with_ports(&[7000, 7002])));
+        assert_eq!(summary.state, ClusterRunState::Degraded);
+        assert_eq!(summary.running, 1);
+
+        let summary = summarise_cluster("search", Some(state_with_ports(&[7002])));
+        assert_eq!(summary.state, ClusterRunState::Down);
+
+        let summary = summarise_cluster("search", None);
+        assert_eq!(summary.state, ClusterRunState::Stopped);
+        assert_eq!(summary.nodes, 0);
+        assert_eq!(summary.created_at, None);
+    }
+}
@@ -1,3 +1,5 @@
+use crate::{cli::output::OutputFormat, error::RrError};
+
 pub trait Executable {
-    fn execute(&self) -> Result<(), String>;
+    fn execute(&self, output: OutputFormat) -> Result<(), RrError>;
 }
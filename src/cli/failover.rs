@@ -0,0 +1,88 @@
+use structopt::StructOpt;
+
+use crate::{
+    cluster::failover::{failover_replica, kill_primary, FailoverMode},
+    error::RrError,
+    local::{
+        cluster::current_cluster,
+        lock::{lock_state, LOCK_TIMEOUT},
+    },
+};
+
+use super::{
+    cmd::Executable,
+    output::{print_json, OutputFormat},
+};
+
+#[derive(Debug, StructOpt)]
+pub struct ClusterFailover {
+    #[structopt(
+        name = "cluster-host",
+        short = "-h",
+        long = "--cluster-host",
+        default_value = "127.0.0.1"
+    )]
+    cluster_host: String,
+
+    #[structopt(
+        name = "port",
+        short = "-p",
+        long = "--port",
+        required_unless = "kill",
+        conflicts_with = "kill",
+        help = "Port of the replica to promote."
+    )]
+    port: Option<u16>,
+
+    #[structopt(
+        name = "force",
+        long = "--force",
+        conflicts_with = "takeover",
+        help = "Fail over without the primary's agreement."
+    )]
+    force: bool,
+
+    #[structopt(
+        name = "takeover",
+        long = "--takeover",
+        help = "Fail over without an election among the primaries."
+    )]
+    takeover: bool,
+
+    #[structopt(
+        name = "kill",
+        long = "--kill",
+        conflicts_with_all = &["force", "takeover"],
+        help = "Port of a primary to kill, timing how long its replicas take to recover."
+    )]
+    kill: Option<u16>,
+}
+
+impl Executable for ClusterFailover {
+    fn execute(&self, output: OutputFormat) -> Result<(), RrError> {
+        let _lock = lock_state(&current_cluster(), LOCK_TIMEOUT)?;
+        let report = match (self.kill, self.port) {
+            (Some(primary), _) => kill_primary(&self.cluster_host, primary)?,
+            (None, Some(replica)) => {
+                let mode = if self.force {
+                    FailoverMode::Force
+                } else if self.takeover {
+                    FailoverMode::Takeover
+                } else {
+                    FailoverMode::Default
+                };
+                failover_replica(&self.cluster_host, replica, mode)?
+            }
+            (None, None) => {
+                return Err(RrError::Config(
+                    "Pass the port of a replica to promote or of a primary to kill.".to_string(),
+                    None,
+                ))
+            }
+        };
+        match output {
+            OutputFormat::Text => Ok(()),
+            OutputFormat::Json => print_json(&report),
+        }
+    }
+}
@@ -0,0 +1,61 @@
+use structopt::StructOpt;
+
+use crate::{
+    cluster::fix::fix_cluster,
+    error::RrError,
+    local::{
+        cluster::current_cluster,
+        lock::{lock_state, LOCK_TIMEOUT},
+    },
+};
+
+use super::{
+    cmd::Executable,
+    output::{print_json, OutputFormat},
+};
+
+#[derive(Debug, StructOpt)]
+pub struct ClusterFix {
+    #[structopt(
+        name = "cluster-host",
+        short = "-h",
+        long = "--cluster-host",
+        default_value = "127.0.0.1"
+    )]
+    cluster_host: String,
+
+    #[structopt(
+        name = "dry-run",
+        long = "--dry-run",
+        help = "Print the repairs that would be made without making them."
+    )]
+    dry_run: bool,
+}
+
+impl Executable for ClusterFix {
+    fn execute(&self, output: OutputFormat) -> Result<(), RrError> {
+        let _lock = lock_state(&current_cluster(), LOCK_TIMEOUT)?;
+        let report = fix_cluster(&self.cluster_host, self.dry_run)?;
+        match output {
+            OutputFormat::Text => {
+                report.findings.iter().for_each(|f| warn!("{}", f));
+                if report.actions.is_empty() {
+                    info!("Nothing to fix.");
+                } else if report.dry_run {
+                    report
+                        .actions
+                        .iter()
+                        .for_each(|action| info!("Would: {}", action));
+                    info!("Dry run, {} repairs planned.", report.actions.len());
+                } else {
+                    info!(
+                        "Made {} repairs. Run rr cluster check to confirm.",
+                        report.actions.len()
+                    );
+                }
+                Ok(())
+            }
+            OutputFormat::Json => print_json(&report),
+        }
+    }
+}
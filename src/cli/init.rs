@@ -0,0 +1,68 @@
+use std::path::PathBuf;
+
+use serde::Serialize;
+use structopt::StructOpt;
+
+use crate::{
+    cli::{
+        cmd::Executable,
+        output::{print_json, OutputFormat},
+    },
+    cluster::config::{generate_conf_files, resolve_base_file_path, resolve_data_dir_path},
+    error::RrError,
+};
+
+#[derive(Debug, Serialize)]
+struct ConfInitDocument {
+    files: Vec<PathBuf>,
+}
+
+#[derive(Debug, StructOpt)]
+pub struct ClusterInit {
+    #[structopt(
+        name = "base-dir",
+        short = "-b",
+        long = "--base-dir",
+        parse(from_os_str)
+    )]
+    base_dir: Option<PathBuf>,
+
+    #[structopt(
+        name = "data-dir",
+        short = "-d",
+        long = "--data-dir",
+        parse(from_os_str),
+        help = "Directory holding each node's data. Defaults to $HOME/.rr/data."
+    )]
+    data_dir: Option<PathBuf>,
+
+    #[structopt(name = "nodes", short = "-n", long = "--nodes", default_value = "6")]
+    nodes: u16,
+
+    #[structopt(
+        name = "start-port",
+        short = "-p",
+        long = "--start-port",
+        default_value = "7000"
+    )]
+    start_port: u16,
+}
+
+impl Executable for ClusterInit {
+    fn execute(&self, output: OutputFormat) -> Result<(), RrError> {
+        let base_path = resolve_base_file_path(&self.base_dir);
+        let data_path = resolve_data_dir_path(&self.data_dir)?;
+
+        let conf_files = generate_conf_files(&base_path, &data_path, self.nodes, self.start_port)?;
+        match output {
+            OutputFormat::Text => {
+                conf_files.iter().for_each(|f| {
+                    info!("Wrote {}", f.display());
+                });
+                info!("Generated {} configuration files.", conf_files.len());
+                Ok(())
+            }
+            OutputFormat::Json => print_json(&ConfInitDocument { files: conf_files }),
+        }
+    }
+}
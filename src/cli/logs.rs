@@ -0,0 +1,238 @@
+use std::{collections::HashMap, env, path::PathBuf, str::FromStr, thread, time::Duration};
+
+use serde::Serialize;
+use structopt::StructOpt;
+
+use crate::{
+    error::RrError,
+    local::{
+        cluster::current_cluster,
+        logs::{
+            list_node_logs, local_now_millis, merge_timelines, node_log_path, LogLine, NodeLog,
+        },
+        pid::get_currently_running_pids,
+    },
+};
+
+use super::{
+    cmd::Executable,
+    output::{print_json, OutputFormat},
+};
+
+// Delay between reads of the logs while following them.
+const FOLLOW_POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+// ANSI colours the nodes' lines are told apart by, in port order.
+const NODE_COLOURS: [&str; 6] = ["36", "33", "35", "32", "34", "31"];
+
+#[derive(Debug, Serialize)]
+struct LogsDocument {
+    lines: Vec<LogLine>,
+}
+
+/// Parse a duration such as 30s, 5m, 2h or 1d.
+fn parse_since(value: &str) -> Result<Duration, String> {
+    let split = value
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(value.len());
+    let (amount, unit) = value.split_at(split);
+    let amount = u64::from_str(amount).map_err(|_| format!("Invalid duration: {}", value))?;
+    let seconds = match unit {
+        "s" | "" => 1,
+        "m" => 60,
+        "h" => 3600,
+        "d" => 86400,
+        _ => return Err(format!("Invalid duration unit: {}", unit)),
+    };
+    amount
+        .checked_mul(seconds)
+        .map(Duration::from_secs)
+        .ok_or_else(|| format!("Invalid duration: {}", value))
+}
+
+#[derive(Debug, StructOpt)]
+pub struct NodeLogs {
+    #[structopt(
+        name = "port",
+        short = "-p",
+        long = "--port",
+        conflicts_with = "all",
+        help = "Only show the log of the node on this port."
+    )]
+    port: Option<u16>,
+
+    #[structopt(
+        name = "all",
+        long = "--all",
+        help = "Show the logs of every node ever started, not only the running ones."
+    )]
+    all: bool,
+
+    #[structopt(
+        name = "follow",
+        short = "-f",
+        long = "--follow",
+        help = "Keep printing lines as the nodes write them."
+    )]
+    follow: bool,
+
+    #[structopt(
+        name = "since",
+        long = "--since",
+        parse(try_from_str = parse_since),
+        help = "Only show lines logged within this long, such as 30s, 5m, 2h or 1d."
+    )]
+    since: Option<Duration>,
+
+    #[structopt(
+        name = "grep",
+        long = "--grep",
+        help = "Only show lines containing this text."
+    )]
+    grep: Option<String>,
+
+    #[structopt(name = "no-color", long = "--no-color", help = "Do not colour lines.")]
+    no_color: bool,
+}
+
+impl NodeLogs {
+    /// The logs to read: the given port's, every log on disk, or those
+    /// of the running nodes.
+    fn node_logs(&self) -> Result<Vec<NodeLog>, RrError> {
+        let mut logs = if self.all {
+            list_node_logs(&current_cluster())?
+        } else {
+            let pids = get_currently_running_pids()?;
+            let mut recorded = pids
+                .iter()
+                .filter_map(|entry| Some((entry.port, entry.log.clone()?)))
+                .filter(|(port, _)| self.port.is_none_or(|p| p == *port))
+                .collect::<Vec<(u16, PathBuf)>>();
+            if let (Some(port), true) = (self.port, recorded.is_empty()) {
+                recorded.push((port, node_log_path(&current_cluster(), port)?));
+            }
+            recorded
+        };
+        logs.retain(|(_, path)| path.exists());
+        logs.sort_unstable();
+        if logs.is_empty() {
+            return Err(RrError::State(
+                match self.port {
+                    Some(port) => format!("No log found for the node on port {}.", port),
+                    None => "No logs found for the running nodes. Pass --all to read the logs of stopped nodes.".to_string(),
+                },
+                None,
+            ));
+        }
+        Ok(logs
+            .into_iter()
+            .map(|(port, path)| NodeLog::new(port, path))
+            .collect())
+    }
+
+    fn wanted(&self, line: &LogLine, cutoff: Option<i64>) -> bool {
+        let recent = match cutoff {
+            Some(cutoff) => line.timestamp.is_some_and(|t| t >= cutoff),
+            None => true,
+        };
+        recent
+            && self
+                .grep
+                .as_ref()
+                .is_none_or(|text| line.text.contains(text.as_str()))
+    }
+
+    fn read_lines(
+        &self,
+        logs: &mut [NodeLog],
+        cutoff: Option<i64>,
+    ) -> Result<Vec<LogLine>, RrError> {
+        let lines = logs
+            .iter_mut()
+            .map(|log| log.read_new())
+            .collect::<Result<Vec<Vec<LogLine>>, RrError>>()?;
+        Ok(merge_timelines(lines)
+            .into_iter()
+            .filter(|line| self.wanted(line, cutoff))
+            .collect())
+    }
+}
+
+fn print_lines(lines: &[LogLine], colours: &Option<HashMap<u16, &str>>) {
+    for line in lines.iter() {
+        match colours.as_ref().and_then(|c| c.get(&line.port)) {
+            Some(colour) => println!("\x1b[{}m{:>5}\x1b[0m | {}", colour, line.port, line.text),
+            None => println!("{:>5} | {}", line.port, line.text),
+        }
+    }
+}
+
+impl Executable for NodeLogs {
+    fn execute(&self, output: OutputFormat) -> Result<(), RrError> {
+        if self.follow && output == OutputFormat::Json {
+            return Err(RrError::Config(
+                "Logs cannot be followed with JSON output.".to_string(),
+                None,
+            ));
+        }
+        let mut logs = self.node_logs()?;
+        let cutoff = self.since.map(|since| {
+            local_now_millis().saturating_sub(i64::try_from(since.as_millis()).unwrap_or(i64::MAX))
+        });
+        let lines = self.read_lines(&mut logs, cutoff)?;
+        if output == OutputFormat::Json {
+            return print_json(&LogsDocument { lines });
+        }
+
+        let coloured =
+            !self.no_color && env::var("NO_COLOR").is_err() && atty::is(atty::Stream::Stdout);
+        let colours = coloured.then(|| {
+            logs.iter()
+                .zip(NODE_COLOURS.iter().cycle())
+                .map(|(log, colour)| (log.port, *colour))
+                .collect::<HashMap<u16, &str>>()
+        });
+        print_lines(&lines, &colours);
+        if !self.follow {
+            return Ok(());
+        }
+        loop {
+            thread::sleep(FOLLOW_POLL_INTERVAL);
+            print_lines(&self.read_lines(&mut logs, None)?, &colours);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_parse_since() {
+        assert_eq!(parse_since("30s"), Ok(Duration::from_secs(30)));
+        assert_eq!(parse_since("5m"), Ok(Duration::from_secs(300)));
+        assert_eq!(parse_since("2h"), Ok(Duration::from_secs(7200)));
+        assert_eq!(parse_since("45"), Ok(Duration::from_secs(45)));
+        assert!(parse_since("5w").is_err());
+        assert!(parse_since("m").is_err());
+        assert_eq!(
+            parse_since("999999999999999999d"),
+            Err("Invalid duration: 999999999999999999d".to_string())
+        );
+    }
+
+    #[test]
+    fn test_wanted() {
+        let logs = NodeLogs::from_iter(&["logs", "--grep", "failover"]);
+        let line = |timestamp, text: &str| LogLine {
+            port: 7000,
+            timestamp,
+            text: text.to_string(),
+        };
+        assert!(logs.wanted(&line(Some(10), "Starting failover"), Some(5)));
+        assert!(!logs.wanted(&line(Some(1), "Starting failover"), Some(5)));
+        assert!(!logs.wanted(&line(None, "failover banner"), Some(5)));
+        assert!(!logs.wanted(&line(Some(10), "Ready"), None));
+    }
+}
@@ -1,12 +1,23 @@
 use std::path::PathBuf;
 
+use serde::Serialize;
 use structopt::StructOpt;
 
 use crate::{
-    cli::cmd::Executable,
-    cluster::config::{list_conf_files, resolve_base_file_path},
+    cli::{
+        cmd::Executable,
+        output::{print_json, OutputFormat},
+    },
+    cluster::config::{aggregate_config_files, resolve_base_file_path},
+    error::RrError,
 };
 
+#[derive(Debug, Serialize)]
+struct ConfListDocument {
+    base_dir: PathBuf,
+    files: Vec<String>,
+}
+
 #[derive(Debug, StructOpt)]
 pub struct ClusterLs {
     #[structopt(
@@ -19,13 +30,30 @@ pub struct ClusterLs {
 }
 
 impl Executable for ClusterLs {
-    fn execute(&self) -> Result<(), String> {
+    fn execute(&self, output: OutputFormat) -> Result<(), RrError> {
         let base_path = resolve_base_file_path(&self.base_dir);
 
         if !base_path.exists() {
-            Err("The given base_dir does not exist.".to_string())
+            Err(RrError::Config(
+                "The given base_dir does not exist.".to_string(),
+                None,
+            ))
         } else {
-            list_conf_files(&base_path)
+            let mut files = aggregate_config_files(&base_path)?;
+            files.sort();
+            match output {
+                OutputFormat::Text => {
+                    files.iter().for_each(|f| {
+                        info!("{}", f);
+                    });
+                    info!("Found {} configuration files.", files.len());
+                    Ok(())
+                }
+                OutputFormat::Json => print_json(&ConfListDocument {
+                    base_dir: base_path,
+                    files,
+                }),
+            }
         }
     }
 }
@@ -1,14 +1,28 @@
 use structopt::StructOpt;
 
+pub mod add_node;
 pub mod check;
+pub mod clusters;
 pub mod cmd;
+pub mod failover;
+pub mod fix;
+pub mod init;
+pub mod logs;
 pub mod ls;
+pub mod output;
+pub mod rebalance;
+pub mod remove_node;
+pub mod reshard;
 pub mod start;
+pub mod status;
 pub mod stop;
+pub mod validate;
 
 #[derive(Debug, StructOpt)]
 pub enum ClusterConfig {
     Ls(ls::ClusterLs),
+    Init(init::ClusterInit),
+    Validate(validate::ClusterValidate),
 }
 
 #[derive(Debug, StructOpt)]
@@ -16,16 +30,58 @@ pub enum ClusterRuntime {
     Start(start::ClusterStart),
     Stop(stop::ClusterStop),
     Check(check::ClusterCheck),
+    Status(status::ClusterStatus),
+    Fix(fix::ClusterFix),
+    AddNode(add_node::ClusterAddNode),
+    RemoveNode(remove_node::ClusterRemoveNode),
+    Reshard(reshard::ClusterReshard),
+    Rebalance(rebalance::ClusterRebalance),
+    Failover(failover::ClusterFailover),
+    Ls(clusters::ClusterList),
 }
 
 #[derive(Debug, StructOpt)]
 pub enum ClusterCommand {
     Config(ClusterConfig),
     Cluster(ClusterRuntime),
+    Logs(logs::NodeLogs),
+}
+
+impl ClusterCommand {
+    /// Whether the command works on a cluster that must already exist,
+    /// rather than starting one or working on no cluster at all.
+    pub fn needs_existing_cluster(&self) -> bool {
+        match self {
+            ClusterCommand::Config(_) => false,
+            ClusterCommand::Cluster(ClusterRuntime::Start(_))
+            | ClusterCommand::Cluster(ClusterRuntime::AddNode(_))
+            | ClusterCommand::Cluster(ClusterRuntime::Ls(_)) => false,
+            ClusterCommand::Cluster(_) | ClusterCommand::Logs(_) => true,
+        }
+    }
 }
 
 #[derive(Debug, StructOpt)]
 pub struct RootCommand {
+    #[structopt(
+        name = "output",
+        short = "-o",
+        long = "--output",
+        default_value = "text",
+        possible_values = &["text", "json"],
+        global = true,
+        help = "Print results as log lines (text) or as one JSON document on stdout (json)."
+    )]
+    pub output: output::OutputFormat,
+
+    #[structopt(
+        name = "cluster",
+        long = "--cluster",
+        global = true,
+        help = "Name of the cluster to work on, by default the name in the project's rr.toml or default. Each cluster keeps its own state and logs under $HOME/.rr/clusters/<name>."
+    )]
+    pub cluster: Option<String>,
+
     #[structopt(subcommand)]
     pub cmd: ClusterCommand,
 }
@@ -0,0 +1,256 @@
+use std::{
+    collections::HashSet,
+    io::{self, Write},
+    path::PathBuf,
+    str::FromStr,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use serde::Serialize;
+
+use crate::{
+    error::RrError,
+    local::pid::{NodeRole, PIDEntry},
+};
+
+// Set once a command has written its document, so a failure afterwards
+// does not add a second document to stdout.
+static DOCUMENT_WRITTEN: AtomicBool = AtomicBool::new(false);
+
+/// How a command reports its result.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// Human readable log lines.
+    Text,
+    /// A single JSON document on stdout.
+    Json,
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(OutputFormat::Text),
+            "json" => Ok(OutputFormat::Json),
+            _ => Err(format!("Unknown output format: {}", s)),
+        }
+    }
+}
+
+/// A node rr started or stopped, as reported in JSON output.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct NodeDocument {
+    pub port: u16,
+    pub pid: u32,
+    pub role: NodeRole,
+    pub log: Option<PathBuf>,
+}
+
+/// Turn a PID entry into a node document.
+///
+/// # Arguments
+/// * `entry` - The PID entry of the node.
+///
+/// # Examples
+/// ```
+/// let node = node_document(&entry);
+/// ```
+pub fn node_document(entry: &PIDEntry) -> NodeDocument {
+    NodeDocument {
+        port: entry.port,
+        pid: entry.pid,
+        role: entry.role,
+        log: entry.log.clone(),
+    }
+}
+
+/// Turn PID entries into node documents, ordered by port so the output
+/// is stable between runs.
+///
+/// # Arguments
+/// * `entries` - The PID entries of the nodes.
+///
+/// # Examples
+/// ```
+/// let nodes = node_documents(&get_currently_running_pids()?);
+/// ```
+pub fn node_documents(entries: &HashSet<PIDEntry>) -> Vec<NodeDocument> {
+    let mut nodes = entries
+        .iter()
+        .map(node_document)
+        .collect::<Vec<NodeDocument>>();
+    nodes.sort_unstable_by_key(|node| node.port);
+    nodes
+}
+
+/// Format a byte count the way Redis' *_human INFO fields do.
+///
+/// # Examples
+/// ```
+/// assert_eq!(format_bytes(1536), "1.50K");
+/// ```
+pub fn format_bytes(bytes: u64) -> String {
+    let units = ["B", "K", "M", "G", "T"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < units.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{}B", bytes)
+    } else {
+        format!("{:.2}{}", value, units[unit])
+    }
+}
+
+/// Format a number of seconds as the two largest units, e.g. 3h25m.
+///
+/// # Examples
+/// ```
+/// assert_eq!(format_duration(90061), "1d1h");
+/// ```
+pub fn format_duration(seconds: u64) -> String {
+    let parts = [
+        (seconds / 86400, "d"),
+        (seconds % 86400 / 3600, "h"),
+        (seconds % 3600 / 60, "m"),
+        (seconds % 60, "s"),
+    ];
+    let first = parts
+        .iter()
+        .position(|(value, _)| *value > 0)
+        .unwrap_or(parts.len() - 1);
+    parts[first..]
+        .iter()
+        .take(2)
+        .map(|(value, unit)| format!("{}{}", value, unit))
+        .collect()
+}
+
+#[derive(Debug, Serialize)]
+struct ErrorDocument<'a> {
+    kind: &'a str,
+    message: &'a str,
+    causes: Vec<String>,
+    exit_code: i32,
+}
+
+#[derive(Debug, Serialize)]
+struct ErrorEnvelope<'a> {
+    error: ErrorDocument<'a>,
+}
+
+/// Write a document to stdout as pretty printed JSON.
+///
+/// # Arguments
+/// * `document` - The document to write.
+///
+/// # Examples
+/// ```
+/// print_json(&ConfListDocument { base_dir, files })?;
+/// ```
+pub fn print_json<T: Serialize>(document: &T) -> Result<(), RrError> {
+    let stdout = io::stdout();
+    let mut handle = stdout.lock();
+    serde_json::to_writer_pretty(&mut handle, document).map_err(|err| {
+        RrError::State(
+            "Failed to write JSON output.".to_string(),
+            Some(Box::new(err)),
+        )
+    })?;
+    writeln!(handle).map_err(|err| {
+        RrError::State(
+            "Failed to write JSON output.".to_string(),
+            Some(Box::new(err)),
+        )
+    })?;
+    DOCUMENT_WRITTEN.store(true, Ordering::SeqCst);
+    Ok(())
+}
+
+/// Write a failed command's error to stdout as a JSON document, unless
+/// the command already wrote its own document before failing.
+///
+/// # Arguments
+/// * `err` - The error the command failed with.
+///
+/// # Examples
+/// ```
+/// if let Err(err) = cmd_result {
+///     print_json_error(&err);
+/// }
+/// ```
+pub fn print_json_error(err: &RrError) {
+    if DOCUMENT_WRITTEN.load(Ordering::SeqCst) {
+        return;
+    }
+
+    let envelope = ErrorEnvelope {
+        error: ErrorDocument {
+            kind: err.kind(),
+            message: err.message(),
+            causes: err.causes(),
+            exit_code: err.exit_code(),
+        },
+    };
+    if let Err(write_err) = print_json(&envelope) {
+        error!("{}", write_err);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_output_format_from_str() {
+        assert_eq!(OutputFormat::from_str("json"), Ok(OutputFormat::Json));
+        assert_eq!(OutputFormat::from_str("text"), Ok(OutputFormat::Text));
+        assert!(OutputFormat::from_str("yaml").is_err());
+    }
+
+    #[test]
+    fn test_format_bytes() {
+        assert_eq!(format_bytes(512), "512B");
+        assert_eq!(format_bytes(1536), "1.50K");
+        assert_eq!(format_bytes(3 * 1024 * 1024 * 1024), "3.00G");
+    }
+
+    #[test]
+    fn test_format_duration() {
+        assert_eq!(format_duration(0), "0s");
+        assert_eq!(format_duration(59), "59s");
+        assert_eq!(format_duration(3600), "1h0m");
+        assert_eq!(format_duration(90061), "1d1h");
+    }
+
+    #[test]
+    fn test_node_documents_sorted_by_port() {
+        let mut entries = HashSet::new();
+        entries.insert(PIDEntry {
+            port: 7001,
+            pid: 11,
+            role: NodeRole::Replica,
+            ..Default::default()
+        });
+        entries.insert(PIDEntry {
+            port: 7000,
+            pid: 10,
+            role: NodeRole::Primary,
+            log: Some(PathBuf::from("/tmp/rr/7000.log")),
+            ..Default::default()
+        });
+
+        let json = serde_json::to_value(node_documents(&entries)).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!([
+                {"port": 7000, "pid": 10, "role": "primary", "log": "/tmp/rr/7000.log"},
+                {"port": 7001, "pid": 11, "role": "replica", "log": null}
+            ])
+        );
+    }
+}
@@ -0,0 +1,87 @@
+use std::{collections::HashMap, str::FromStr};
+
+use structopt::StructOpt;
+
+use crate::{
+    cluster::reshard::rebalance_cluster,
+    error::RrError,
+    local::{
+        cluster::current_cluster,
+        lock::{lock_state, LOCK_TIMEOUT},
+    },
+};
+
+use super::{
+    cmd::Executable,
+    output::{print_json, OutputFormat},
+    reshard::log_report,
+};
+
+fn parse_weight(value: &str) -> Result<(u16, f64), String> {
+    let (port, weight) = value
+        .split_once('=')
+        .ok_or_else(|| format!("Expected port=weight, got {}", value))?;
+    let port = u16::from_str(port).map_err(|_| format!("Invalid port: {}", port))?;
+    match f64::from_str(weight) {
+        Ok(weight) if weight >= 0.0 && weight.is_finite() => Ok((port, weight)),
+        _ => Err(format!("Invalid weight: {}", weight)),
+    }
+}
+
+#[derive(Debug, StructOpt)]
+pub struct ClusterRebalance {
+    #[structopt(
+        name = "cluster-host",
+        short = "-h",
+        long = "--cluster-host",
+        default_value = "127.0.0.1"
+    )]
+    cluster_host: String,
+
+    #[structopt(
+        name = "weight",
+        long = "--weight",
+        number_of_values = 1,
+        parse(try_from_str = parse_weight),
+        help = "Weight of a primary as port=weight, 1 when left out. Can be repeated."
+    )]
+    weights: Vec<(u16, f64)>,
+
+    #[structopt(
+        name = "pipeline",
+        long = "--pipeline",
+        default_value = "100",
+        help = "Number of keys moved with each MIGRATE."
+    )]
+    pipeline: usize,
+}
+
+impl Executable for ClusterRebalance {
+    fn execute(&self, output: OutputFormat) -> Result<(), RrError> {
+        let _lock = lock_state(&current_cluster(), LOCK_TIMEOUT)?;
+        let weights = self.weights.iter().copied().collect::<HashMap<u16, f64>>();
+        let report = rebalance_cluster(&self.cluster_host, &weights, self.pipeline)?;
+        match output {
+            OutputFormat::Text => {
+                log_report(&report);
+                Ok(())
+            }
+            OutputFormat::Json => print_json(&report),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_parse_weight() {
+        assert_eq!(parse_weight("7000=2.5"), Ok((7000, 2.5)));
+        assert_eq!(parse_weight("7000=0"), Ok((7000, 0.0)));
+        assert!(parse_weight("7000").is_err());
+        assert!(parse_weight("7000=-1").is_err());
+        assert!(parse_weight("port=1").is_err());
+    }
+}
@@ -0,0 +1,53 @@
+use structopt::StructOpt;
+
+use crate::{
+    cluster::membership::remove_node,
+    error::RrError,
+    local::{
+        cluster::current_cluster,
+        lock::{lock_state, LOCK_TIMEOUT},
+    },
+};
+
+use super::{
+    cmd::Executable,
+    output::{print_json, OutputFormat},
+};
+
+#[derive(Debug, StructOpt)]
+pub struct ClusterRemoveNode {
+    #[structopt(
+        name = "cluster-host",
+        short = "-h",
+        long = "--cluster-host",
+        default_value = "127.0.0.1"
+    )]
+    cluster_host: String,
+
+    #[structopt(
+        name = "port",
+        short = "-p",
+        long = "--port",
+        help = "Port of the node to remove."
+    )]
+    port: u16,
+}
+
+impl Executable for ClusterRemoveNode {
+    fn execute(&self, output: OutputFormat) -> Result<(), RrError> {
+        let _lock = lock_state(&current_cluster(), LOCK_TIMEOUT)?;
+        let removed = remove_node(&self.cluster_host, self.port)?;
+        match output {
+            OutputFormat::Text => {
+                if removed.slots_moved > 0 {
+                    info!(
+                        "Moved {} slots and {} keys to the remaining primaries.",
+                        removed.slots_moved, removed.keys_moved
+                    );
+                }
+                Ok(())
+            }
+            OutputFormat::Json => print_json(&removed),
+        }
+    }
+}
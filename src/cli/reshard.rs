@@ -0,0 +1,97 @@
+use structopt::StructOpt;
+
+use crate::{
+    cluster::reshard::{reshard_cluster, ReshardReport},
+    error::RrError,
+    local::{
+        cluster::current_cluster,
+        lock::{lock_state, LOCK_TIMEOUT},
+    },
+};
+
+use super::{
+    cmd::Executable,
+    output::{print_json, OutputFormat},
+};
+
+#[derive(Debug, StructOpt)]
+pub struct ClusterReshard {
+    #[structopt(
+        name = "cluster-host",
+        short = "-h",
+        long = "--cluster-host",
+        default_value = "127.0.0.1"
+    )]
+    cluster_host: String,
+
+    #[structopt(
+        name = "from",
+        long = "--from",
+        help = "Port of the primary giving up slots."
+    )]
+    from: u16,
+
+    #[structopt(
+        name = "to",
+        long = "--to",
+        help = "Port of the primary taking the slots."
+    )]
+    to: u16,
+
+    #[structopt(name = "slots", long = "--slots", help = "Number of slots to move.")]
+    slots: usize,
+
+    #[structopt(
+        name = "pipeline",
+        long = "--pipeline",
+        default_value = "100",
+        help = "Number of keys moved with each MIGRATE."
+    )]
+    pipeline: usize,
+}
+
+/// Log the outcome of a reshard or rebalance.
+pub fn log_report(report: &ReshardReport) {
+    if report.resumed > 0 {
+        info!(
+            "Finished {} slot moves from an interrupted run.",
+            report.resumed
+        );
+    }
+    for moved in report.moves.iter() {
+        let ranges = moved
+            .slots
+            .iter()
+            .map(|range| range.to_string())
+            .collect::<Vec<String>>()
+            .join(",");
+        info!(
+            "Moved slots {} from {} to {}.",
+            ranges, moved.from, moved.to
+        );
+    }
+    info!(
+        "Moved {} slots and {} keys.",
+        report.slots_moved, report.keys_moved
+    );
+}
+
+impl Executable for ClusterReshard {
+    fn execute(&self, output: OutputFormat) -> Result<(), RrError> {
+        let _lock = lock_state(&current_cluster(), LOCK_TIMEOUT)?;
+        let report = reshard_cluster(
+            &self.cluster_host,
+            self.from,
+            self.to,
+            self.slots,
+            self.pipeline,
+        )?;
+        match output {
+            OutputFormat::Text => {
+                log_report(&report);
+                Ok(())
+            }
+            OutputFormat::Json => print_json(&report),
+        }
+    }
+}
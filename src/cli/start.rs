@@ -1,13 +1,31 @@
-use std::path::PathBuf;
+use std::{path::PathBuf, time::Duration};
 
+use serde::Serialize;
 use structopt::StructOpt;
 
-use crate::cluster::{
-    config::{aggregate_config_files, resolve_base_file_path},
-    runtime::start_cluster,
+use crate::{
+    cluster::{
+        config::{aggregate_config_files, resolve_base_file_path},
+        manifest::{project_manifest, write_manifest_confs},
+        runtime::{start_cluster, DEFAULT_REDIS_SERVER, READY_TIMEOUT},
+    },
+    error::RrError,
+    local::{
+        cluster::current_cluster,
+        lock::{lock_state, LOCK_TIMEOUT},
+    },
+    resp::client::{env_password, select_password},
 };
 
-use super::cmd::Executable;
+use super::{
+    cmd::Executable,
+    output::{node_documents, print_json, NodeDocument, OutputFormat},
+};
+
+#[derive(Debug, Serialize)]
+struct StartDocument {
+    nodes: Vec<NodeDocument>,
+}
 
 #[derive(Debug, StructOpt)]
 pub struct ClusterStart {
@@ -15,7 +33,8 @@ pub struct ClusterStart {
         name = "base-dir",
         short = "-b",
         long = "--base-dir",
-        parse(from_os_str)
+        parse(from_os_str),
+        help = "Directory of configuration files to start. Without it, the confs are generated from the rr.toml found in or above the current directory, if any."
     )]
     base_dir: Option<PathBuf>,
 
@@ -26,24 +45,89 @@ pub struct ClusterStart {
         default_value = "127.0.0.1"
     )]
     cluster_host: String,
+
+    #[structopt(
+        name = "replicas",
+        short = "-r",
+        long = "--replicas",
+        help = "Number of replicas to attach to each primary (0, or the manifest's replicas, by default)."
+    )]
+    replicas: Option<usize>,
+
+    #[structopt(
+        name = "ready-timeout",
+        long = "--ready-timeout",
+        help = "Seconds started servers get to accept connections and answer PING (30 by default)."
+    )]
+    ready_timeout: Option<u64>,
+
+    #[structopt(
+        name = "keep-partial",
+        long = "--keep-partial",
+        help = "Keep the servers that started running when others fail, and build the cluster from them."
+    )]
+    keep_partial: bool,
 }
 
 impl Executable for ClusterStart {
-    fn execute(&self) -> Result<(), String> {
-        let base_conf_path = resolve_base_file_path(&self.base_dir);
-
-        match aggregate_config_files(&base_conf_path) {
-            Ok(conf_list) => {
-                if conf_list.len() <= 0 {
-                    Err(format!(
-                        "No configuration files found in path: {}",
-                        base_conf_path.as_os_str().to_str().unwrap_or("DIR_ERROR")
-                    ))
-                } else {
-                    start_cluster(&self.cluster_host, conf_list)
-                }
+    fn execute(&self, output: OutputFormat) -> Result<(), RrError> {
+        let cluster = current_cluster();
+        let _lock = lock_state(&cluster, LOCK_TIMEOUT)?;
+        let manifest = match self.base_dir {
+            Some(_) => None,
+            None => project_manifest()?,
+        };
+        let (base_conf_path, binary, replicas, password) = match &manifest {
+            Some(manifest) => {
+                info!(
+                    "Generating configuration files from {}.",
+                    manifest.path.display()
+                );
+                let binary = manifest.binary();
+                manifest.check_version(&binary)?;
+                let conf_dir = write_manifest_confs(manifest, &cluster)?;
+                // The nodes rr talks to while starting need the
+                // manifest's password too.
+                let password = manifest.password.clone().or_else(env_password);
+                (conf_dir, binary, manifest.replicas, password)
             }
-            Err(err) => Err(err.to_string()),
+            None => (
+                resolve_base_file_path(&self.base_dir),
+                PathBuf::from(DEFAULT_REDIS_SERVER),
+                None,
+                env_password(),
+            ),
+        };
+        // Servers stopped when the start fails need the same password.
+        select_password(password.clone())?;
+
+        let conf_list = aggregate_config_files(&base_conf_path)?;
+        if conf_list.is_empty() {
+            return Err(RrError::Config(
+                format!(
+                    "No configuration files found in path: {}",
+                    base_conf_path.as_os_str().to_str().unwrap_or("DIR_ERROR")
+                ),
+                None,
+            ));
+        }
+
+        let started = start_cluster(
+            &self.cluster_host,
+            &binary,
+            conf_list,
+            self.replicas.or(replicas).unwrap_or(0),
+            password.as_deref(),
+            self.ready_timeout
+                .map(Duration::from_secs)
+                .unwrap_or(READY_TIMEOUT),
+            self.keep_partial,
+        )?;
+        match output {
+            OutputFormat::Text => Ok(()),
+            OutputFormat::Json => print_json(&StartDocument {
+                nodes: node_documents(&started),
+            }),
         }
     }
 }
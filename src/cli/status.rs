@@ -0,0 +1,94 @@
+use serde::Serialize;
+use structopt::StructOpt;
+
+use crate::{
+    cluster::status::{cluster_status, NodeHealth, NodeStatus},
+    error::RrError,
+};
+
+use super::{
+    cmd::Executable,
+    output::{format_bytes, format_duration, print_json, OutputFormat},
+};
+
+#[derive(Debug, Serialize)]
+struct StatusDocument<'a> {
+    nodes: &'a [NodeStatus],
+}
+
+#[derive(Debug, StructOpt)]
+pub struct ClusterStatus {
+    #[structopt(
+        name = "cluster-host",
+        short = "-h",
+        long = "--cluster-host",
+        default_value = "127.0.0.1"
+    )]
+    cluster_host: String,
+}
+
+fn or_dash<T, F: Fn(&T) -> String>(value: &Option<T>, format: F) -> String {
+    value
+        .as_ref()
+        .map(format)
+        .unwrap_or_else(|| "-".to_string())
+}
+
+fn print_status_table(statuses: &[NodeStatus]) {
+    println!(
+        "{:<6} {:<8} {:<8} {:<15} {:<10} {:<24} {:<9} {:<9} {:<8}",
+        "PORT", "PID", "ROLE", "STATUS", "NODE", "SLOTS", "REPLICAS", "MEMORY", "UPTIME"
+    );
+    for status in statuses.iter() {
+        let slots = if status.slots.is_empty() {
+            "-".to_string()
+        } else {
+            status
+                .slots
+                .iter()
+                .map(|range| range.to_string())
+                .collect::<Vec<String>>()
+                .join(",")
+        };
+        println!(
+            "{:<6} {:<8} {:<8} {:<15} {:<10} {:<24} {:<9} {:<9} {:<8}",
+            status.port,
+            status.pid,
+            status.role,
+            status.health,
+            or_dash(&status.node_id, |id| id.chars().take(8).collect()),
+            slots,
+            or_dash(&status.connected_replicas, |count| count.to_string()),
+            or_dash(&status.used_memory, |bytes| format_bytes(*bytes)),
+            or_dash(&status.uptime_seconds, |seconds| format_duration(*seconds)),
+        );
+    }
+    for status in statuses.iter() {
+        if let Some(problem) = &status.problem {
+            warn!("Node on port {}: {}", status.port, problem);
+        }
+    }
+}
+
+impl Executable for ClusterStatus {
+    fn execute(&self, output: OutputFormat) -> Result<(), RrError> {
+        let statuses = cluster_status(&self.cluster_host)?;
+        match output {
+            OutputFormat::Text => print_status_table(&statuses),
+            OutputFormat::Json => print_json(&StatusDocument { nodes: &statuses })?,
+        }
+
+        let flagged = statuses
+            .iter()
+            .filter(|status| status.health != NodeHealth::Ok)
+            .count();
+        if flagged > 0 {
+            Err(RrError::ClusterHealth(
+                format!("{} of {} nodes need attention.", flagged, statuses.len()),
+                None,
+            ))
+        } else {
+            Ok(())
+        }
+    }
+}
@@ -1,14 +1,104 @@
+use std::time::Duration;
+
+use serde::Serialize;
 use structopt::StructOpt;
 
-use crate::cluster::runtime::stop_cluster;
+use crate::{
+    cluster::runtime::{stop_cluster, StopMethod, STOP_TIMEOUT},
+    error::RrError,
+    local::{
+        cluster::current_cluster,
+        lock::{lock_state, LOCK_TIMEOUT},
+    },
+};
+
+use super::{
+    cmd::Executable,
+    output::{node_documents, print_json, NodeDocument, OutputFormat},
+};
 
-use super::cmd::Executable;
+#[derive(Debug, Serialize)]
+struct StopDocument {
+    stopped: Vec<NodeDocument>,
+    killed: Vec<NodeDocument>,
+}
 
 #[derive(Debug, StructOpt)]
-pub struct ClusterStop {}
+pub struct ClusterStop {
+    #[structopt(
+        name = "cluster-host",
+        short = "-h",
+        long = "--cluster-host",
+        default_value = "127.0.0.1"
+    )]
+    cluster_host: String,
+
+    #[structopt(
+        name = "save",
+        long = "--save",
+        conflicts_with_all = &["nosave", "signal"],
+        help = "Save a snapshot before shutting down, even without save points configured."
+    )]
+    save: bool,
+
+    #[structopt(
+        name = "nosave",
+        long = "--nosave",
+        conflicts_with = "signal",
+        help = "Shut down without saving a snapshot."
+    )]
+    nosave: bool,
+
+    #[structopt(
+        name = "signal",
+        long = "--signal",
+        help = "Send SIGTERM instead of SHUTDOWN."
+    )]
+    signal: bool,
+
+    #[structopt(
+        name = "timeout",
+        short = "-t",
+        long = "--timeout",
+        help = "Seconds servers get to exit before being sent SIGKILL (10 by default)."
+    )]
+    timeout: Option<u64>,
+}
 
 impl Executable for ClusterStop {
-    fn execute(&self) -> Result<(), String> {
-        stop_cluster()
+    fn execute(&self, output: OutputFormat) -> Result<(), RrError> {
+        let _lock = lock_state(&current_cluster(), LOCK_TIMEOUT)?;
+        let method = if self.signal {
+            StopMethod::Terminate
+        } else if self.save {
+            StopMethod::ShutdownSave
+        } else if self.nosave {
+            StopMethod::ShutdownNoSave
+        } else {
+            StopMethod::Shutdown
+        };
+        let outcome = stop_cluster(
+            &self.cluster_host,
+            method,
+            self.timeout
+                .map(Duration::from_secs)
+                .unwrap_or(STOP_TIMEOUT),
+        )?;
+        match output {
+            OutputFormat::Text => {
+                info!("Stopped {} server processes.", outcome.stopped.len());
+                if !outcome.killed.is_empty() {
+                    warn!(
+                        "{} of them had to be killed with SIGKILL.",
+                        outcome.killed.len()
+                    );
+                }
+                Ok(())
+            }
+            OutputFormat::Json => print_json(&StopDocument {
+                stopped: node_documents(&outcome.stopped),
+                killed: node_documents(&outcome.killed),
+            }),
+        }
     }
 }
@@ -0,0 +1,65 @@
+use std::path::PathBuf;
+
+use serde::Serialize;
+use structopt::StructOpt;
+
+use crate::{
+    cli::{
+        cmd::Executable,
+        output::{print_json, OutputFormat},
+    },
+    cluster::{
+        config::resolve_base_file_path,
+        validate::{validate_conf_dir, ConfFinding, Severity},
+    },
+    error::RrError,
+};
+
+#[derive(Debug, Serialize)]
+struct ValidateDocument<'a> {
+    valid: bool,
+    findings: &'a [ConfFinding],
+}
+
+#[derive(Debug, StructOpt)]
+pub struct ClusterValidate {
+    #[structopt(
+        name = "base-dir",
+        short = "-b",
+        long = "--base-dir",
+        parse(from_os_str)
+    )]
+    base_dir: Option<PathBuf>,
+}
+
+impl Executable for ClusterValidate {
+    fn execute(&self, output: OutputFormat) -> Result<(), RrError> {
+        let base_path = resolve_base_file_path(&self.base_dir);
+
+        let findings = validate_conf_dir(&base_path)?;
+        let errors = findings
+            .iter()
+            .filter(|f| f.severity == Severity::Error)
+            .count();
+        match output {
+            OutputFormat::Text => findings.iter().for_each(|f| match f.severity {
+                Severity::Error => error!("{}", f),
+                Severity::Warning => warn!("{}", f),
+            }),
+            OutputFormat::Json => print_json(&ValidateDocument {
+                valid: errors == 0,
+                findings: &findings,
+            })?,
+        }
+
+        if errors > 0 {
+            Err(RrError::Config(
+                format!("Found {} problems in the configuration files.", errors),
+                None,
+            ))
+        } else {
+            info!("Configuration files are valid.");
+            Ok(())
+        }
+    }
+}
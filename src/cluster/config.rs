@@ -1,13 +1,25 @@
 use std::{
-    collections::HashMap,
-    fs::File,
-    io::{BufRead, BufReader},
+    fs,
     path::{Path, PathBuf},
     str::FromStr,
 };
 
+use crate::{
+    cluster::parser::{load_conf_file, RedisConf},
+    error::RrError,
+    local::dotdir::get_or_create_local_config_dir,
+};
+
 pub const DEFAULT_BASE_CONF_PATH: &str = "/usr/local/etc/redis/cluster";
 
+// Name of the directory under the local config directory that holds
+// node data when no data directory is given.
+const DEFAULT_DATA_DIR_NAME: &str = "data";
+
+// Redis listens for cluster bus traffic on the client port plus this
+// offset, so client ports above 65535 - offset cannot be used.
+pub const CLUSTER_BUS_PORT_OFFSET: u16 = 10000;
+
 fn walk_directory(result_vec: &mut Vec<String>, base_dir: &Path) {
     if base_dir.is_dir() {
         if let Ok(read_dir) = base_dir.read_dir() {
@@ -28,59 +40,38 @@ fn walk_directory(result_vec: &mut Vec<String>, base_dir: &Path) {
     }
 }
 
-pub fn aggregate_config_files(base_dir: &PathBuf) -> Result<Vec<String>, String> {
+pub fn aggregate_config_files(base_dir: &PathBuf) -> Result<Vec<String>, RrError> {
     if base_dir.is_dir() {
         let mut result_vec: Vec<String> = vec![];
         walk_directory(&mut result_vec, base_dir.as_path());
         Ok(result_vec)
     } else {
-        Err(format!(
-            "Entry {} is not a directory.",
-            base_dir.to_str().unwrap_or("DIR_ERROR")
+        Err(RrError::Config(
+            format!(
+                "Entry {} is not a directory.",
+                base_dir.to_str().unwrap_or("DIR_ERROR")
+            ),
+            None,
         ))
     }
 }
 
-pub fn list_conf_files(base_dir: &PathBuf) -> Result<(), String> {
-    let conf_files = aggregate_config_files(base_dir);
-    match conf_files {
-        Ok(files) => {
-            files.iter().for_each(|f| {
-                info!("{}", f);
-            });
-            info!("Found {} configuration files.", files.len());
-            Ok(())
-        }
-        Err(err) => Err(err),
-    }
-}
-
-pub fn read_conf_file(file_path: &PathBuf) -> Result<HashMap<String, String>, String> {
-    if file_path.exists()
-        && file_path.is_file()
-        && file_path.extension().unwrap().eq_ignore_ascii_case("conf")
-    {
-        match File::open(file_path) {
-            Ok(file) => {
-                // This is a very naive way of accomplishing the goal, but it'll
-                // work for now
-                let reader = BufReader::new(file);
-                let mut result_map = HashMap::new();
-                reader.lines().for_each(|line| {
-                    if let Ok(l) = line {
-                        let line_vec: Vec<String> =
-                            l.split_whitespace().map(|s| s.to_string()).collect();
-                        if line_vec.len() == 2 {
-                            result_map.insert(line_vec[0].clone(), line_vec[1].clone());
-                        }
-                    }
-                });
-                Ok(result_map)
-            }
-            Err(err) => Err(err.to_string()),
-        }
+pub fn read_conf_file(file_path: &Path) -> Result<RedisConf, RrError> {
+    if file_path.is_file() {
+        load_conf_file(file_path).map_err(|err| {
+            RrError::Config(
+                format!("Failed to read {}.", file_path.display()),
+                Some(Box::new(err)),
+            )
+        })
     } else {
-        Err("Input entry is not a valid Redis configuration file.".to_string())
+        Err(RrError::Config(
+            format!(
+                "{} is not a valid Redis configuration file.",
+                file_path.display()
+            ),
+            None,
+        ))
     }
 }
 
@@ -91,3 +82,250 @@ pub fn resolve_base_file_path(base_dir: &Option<PathBuf>) -> PathBuf {
         None => PathBuf::from_str(DEFAULT_BASE_CONF_PATH).unwrap(),
     }
 }
+
+/// Resolve where node data directories live, falling back to the data
+/// directory inside the local config directory.
+///
+/// # Arguments
+/// * `data_dir` - Data directory given on the command line, if any.
+///
+/// # Examples
+/// ```
+/// let data_path = resolve_data_dir_path(&None).expect("Failed to resolve data directory.");
+/// ```
+pub fn resolve_data_dir_path(data_dir: &Option<PathBuf>) -> Result<PathBuf, RrError> {
+    match data_dir {
+        Some(path) => Ok(path.clone()),
+        None => Ok(get_or_create_local_config_dir()?.join(DEFAULT_DATA_DIR_NAME)),
+    }
+}
+
+/// Quote a directive value if it holds characters redis.conf would
+/// otherwise split on or treat as an escape. Control characters are
+/// escaped the way Redis reads them back inside double quotes, so a
+/// value can never spill onto a line of its own.
+pub fn quote_conf_value(value: &str) -> String {
+    if !value.is_empty()
+        && !value.contains(|c: char| c.is_whitespace() || c.is_control() || c == '"' || c == '\'')
+    {
+        return value.to_string();
+    }
+    let mut quoted = String::from("\"");
+    for c in value.chars() {
+        match c {
+            '\\' => quoted.push_str("\\\\"),
+            '"' => quoted.push_str("\\\""),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c if c.is_ascii_control() => quoted.push_str(&format!("\\x{:02x}", c as u8)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Render the contents of a cluster-enabled config file for one node,
+/// keeping every file the node writes inside its own data directory.
+///
+/// # Arguments
+/// * `port` - Client port of the node.
+/// * `node_dir` - Data directory of the node.
+///
+/// # Examples
+/// ```
+/// let conf = render_node_conf(7000, &PathBuf::from("/tmp/rr/7000"));
+/// ```
+pub fn render_node_conf(port: u16, node_dir: &Path) -> String {
+    let dir = node_dir.to_string_lossy();
+    let logfile = node_dir.join(format!("redis-{}.log", port));
+    [
+        "# Generated by rr.".to_string(),
+        format!("port {}", port),
+        "bind 127.0.0.1".to_string(),
+        "daemonize no".to_string(),
+        "cluster-enabled yes".to_string(),
+        format!("cluster-config-file nodes-{}.conf", port),
+        "cluster-node-timeout 5000".to_string(),
+        format!("dir {}", quote_conf_value(&dir)),
+        format!("dbfilename dump-{}.rdb", port),
+        "appendonly yes".to_string(),
+        format!("appendfilename appendonly-{}.aof", port),
+        format!("logfile {}", quote_conf_value(&logfile.to_string_lossy())),
+    ]
+    .join("\n")
+        + "\n"
+}
+
+/// Write one cluster-enabled config file per node into the base
+/// directory, creating each node's data directory on the way. Existing
+/// config files are never overwritten.
+///
+/// # Arguments
+/// * `base_dir` - Directory to write the config files to.
+/// * `data_dir` - Directory to create the node data directories in.
+/// * `nodes` - Number of nodes to generate.
+/// * `start_port` - Port of the first node, the rest count up from it.
+///
+/// # Examples
+/// ```
+/// let base_path = PathBuf::from("/usr/local/etc/redis/cluster");
+/// let data_path = PathBuf::from("/tmp/rr");
+/// generate_conf_files(&base_path, &data_path, 6, 7000).expect("Failed to generate config files.");
+/// ```
+pub fn generate_conf_files(
+    base_dir: &Path,
+    data_dir: &Path,
+    nodes: u16,
+    start_port: u16,
+) -> Result<Vec<PathBuf>, RrError> {
+    if nodes == 0 {
+        return Err(RrError::Config(
+            "At least one node is needed.".to_string(),
+            None,
+        ));
+    }
+    let last_port = start_port as u32 + nodes as u32 - 1;
+    if start_port == 0 || last_port + CLUSTER_BUS_PORT_OFFSET as u32 > u16::MAX as u32 {
+        return Err(RrError::Config(
+            format!(
+                "Ports {}-{} leave no room for the cluster bus ports (port + {}).",
+                start_port, last_port, CLUSTER_BUS_PORT_OFFSET
+            ),
+            None,
+        ));
+    }
+
+    let conf_files = (0..nodes)
+        .map(|offset| {
+            let port = start_port + offset;
+            (port, base_dir.join(format!("redis-{}.conf", port)))
+        })
+        .collect::<Vec<(u16, PathBuf)>>();
+    if let Some((_, existing)) = conf_files.iter().find(|(_, path)| path.exists()) {
+        return Err(RrError::Config(
+            format!(
+                "{} already exists, refusing to overwrite it.",
+                existing.display()
+            ),
+            None,
+        ));
+    }
+
+    if let Err(err) = fs::create_dir_all(base_dir) {
+        return Err(RrError::Config(
+            format!("Failed to create {}.", base_dir.display()),
+            Some(Box::new(err)),
+        ));
+    }
+    conf_files
+        .into_iter()
+        .map(|(port, conf_path)| {
+            let node_dir = data_dir.join(port.to_string());
+            if let Err(err) = fs::create_dir_all(&node_dir) {
+                return Err(RrError::Config(
+                    format!("Failed to create {}.", node_dir.display()),
+                    Some(Box::new(err)),
+                ));
+            }
+            match fs::write(&conf_path, render_node_conf(port, &node_dir)) {
+                Ok(_) => Ok(conf_path),
+                Err(err) => Err(RrError::Config(
+                    format!("Failed to write {}.", conf_path.display()),
+                    Some(Box::new(err)),
+                )),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::cluster::parser::{parse_conf_str, split_args};
+
+    use super::*;
+
+    #[test]
+    fn test_render_node_conf() {
+        let conf = render_node_conf(7003, Path::new("/tmp/rr data/7003"));
+        let lines = conf.lines().collect::<Vec<&str>>();
+        assert!(lines.contains(&"port 7003"));
+        assert!(lines.contains(&"cluster-enabled yes"));
+        assert!(lines.contains(&"cluster-config-file nodes-7003.conf"));
+        assert!(lines.contains(&"dir \"/tmp/rr data/7003\""));
+        assert!(lines.contains(&"appendonly yes"));
+        assert!(lines.contains(&"logfile \"/tmp/rr data/7003/redis-7003.log\""));
+    }
+
+    #[test]
+    fn test_generate_conf_files_success() {
+        let base_path = PathBuf::from("./test_generate_confs");
+        let data_path = base_path.join("data");
+
+        let conf_files = generate_conf_files(&base_path, &data_path, 3, 7100);
+        assert!(conf_files.is_ok());
+        assert_eq!(conf_files.unwrap().len(), 3);
+        assert!(data_path.join("7102").is_dir());
+        assert_eq!(aggregate_config_files(&base_path).unwrap().len(), 3);
+
+        let overwrite = generate_conf_files(&base_path, &data_path, 1, 7100);
+        assert!(overwrite.is_err());
+
+        fs::remove_dir_all(&base_path).expect("Failed to remove test directory.");
+    }
+
+    #[test]
+    fn test_generate_conf_files_bad_ports() {
+        let base_path = PathBuf::from("./test_generate_bad_ports");
+        let data_path = base_path.join("data");
+
+        assert!(generate_conf_files(&base_path, &data_path, 0, 7000).is_err());
+        assert!(generate_conf_files(&base_path, &data_path, 2, 55535).is_err());
+        assert!(!base_path.exists());
+    }
+
+    #[test]
+    fn test_read_conf_file_without_extension() {
+        let conf_path = PathBuf::from("./test_read_conf_no_ext");
+        fs::write(&conf_path, "port 7000\nsave 900 1\n").expect("Failed to write test file.");
+
+        let conf = read_conf_file(&conf_path);
+        assert!(conf.is_ok());
+        assert_eq!(conf.unwrap().value("port"), Some("7000"));
+
+        fs::remove_file(&conf_path).expect("Failed to remove test file.");
+    }
+
+    #[test]
+    fn test_read_conf_file_not_a_file() {
+        assert!(read_conf_file(&PathBuf::from("./src")).is_err());
+    }
+
+    #[test]
+    fn test_quote_conf_value() {
+        assert_eq!(quote_conf_value("secret"), "secret");
+        assert_eq!(quote_conf_value(""), "\"\"");
+        assert_eq!(quote_conf_value("a b"), "\"a b\"");
+        assert_eq!(quote_conf_value("a\"b\\c"), r#""a\"b\\c""#);
+        assert_eq!(
+            quote_conf_value("x\nport 6379\r\t\u{7}"),
+            r#""x\nport 6379\r\t\x07""#
+        );
+        for value in ["a b", "x\ndir /", "tab\there", "bell\u{7}", "quote\"'\\"] {
+            assert_eq!(
+                split_args(&format!("requirepass {}", quote_conf_value(value))),
+                Ok(vec!["requirepass".to_string(), value.to_string()])
+            );
+        }
+    }
+
+    #[test]
+    fn test_generated_conf_round_trips() {
+        let conf_text = render_node_conf(7000, Path::new("/tmp/rr data/7000"));
+        let conf = parse_conf_str(&conf_text, Path::new("redis-7000.conf")).unwrap();
+        assert_eq!(conf.value("dir"), Some("/tmp/rr data/7000"));
+        assert_eq!(conf.value("cluster-enabled"), Some("yes"));
+    }
+}
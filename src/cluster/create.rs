@@ -0,0 +1,468 @@
+use std::{
+    collections::{HashMap, HashSet},
+    net::{IpAddr, ToSocketAddrs},
+    str::FromStr,
+    thread,
+    time::{Duration, Instant},
+};
+
+use mocktopus::macros::mockable;
+
+use crate::{
+    cluster::nodes::{parse_cluster_nodes, ClusterNode},
+    error::RrError,
+    local::pid::{NodeRole, PIDEntry},
+    resp::{
+        client::{connect_node_with_password, RedisClient},
+        reply::{parse_info_fields, Reply},
+    },
+};
+
+// Number of hash slots a Redis cluster is split into.
+pub const CLUSTER_SLOTS: u16 = 16384;
+
+// How long to wait for the nodes to agree on the cluster after
+// the slots have been handed out.
+pub const CLUSTER_FORM_TIMEOUT: Duration = Duration::from_secs(30);
+
+// Delay between polls while waiting on the cluster.
+pub const CLUSTER_POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+/// Split the hash slots into contiguous, evenly sized ranges, one per
+/// primary. Any remainder is spread over the first ranges.
+///
+/// # Arguments
+/// * `primaries` - Number of primaries to split the slots between.
+///
+/// # Examples
+/// ```
+/// let ranges = slot_ranges(3);
+/// assert_eq!(ranges, vec![(0, 5461), (5462, 10922), (10923, 16383)]);
+/// ```
+pub fn slot_ranges(primaries: usize) -> Vec<(u16, u16)> {
+    if primaries == 0 {
+        return vec![];
+    }
+
+    let total = CLUSTER_SLOTS as usize;
+    let base = total / primaries;
+    let remainder = total % primaries;
+    let mut start = 0;
+    (0..primaries)
+        .map(|index| {
+            let size = base + usize::from(index < remainder);
+            let range = (start as u16, (start + size - 1) as u16);
+            start += size;
+            range
+        })
+        .collect()
+}
+
+/// Decide which ports become primaries and which replicate them. The
+/// lowest ports become primaries and the rest are handed out to them
+/// round-robin, so each primary gets `replicas` replicas and any spare
+/// nodes become extra replicas.
+///
+/// # Arguments
+/// * `ports` - Ports of the nodes making up the cluster.
+/// * `replicas` - Number of replicas wanted per primary.
+///
+/// # Examples
+/// ```
+/// let plan = plan_replicas(&[7000, 7001, 7002, 7003], 1).expect("Not enough nodes.");
+/// assert_eq!(plan, vec![(7000, None), (7001, None), (7002, Some(7000)), (7003, Some(7001))]);
+/// ```
+pub fn plan_replicas(ports: &[u16], replicas: usize) -> Result<Vec<(u16, Option<u16>)>, RrError> {
+    let mut sorted_ports = ports.to_vec();
+    sorted_ports.sort_unstable();
+    if let Some(window) = sorted_ports.windows(2).find(|pair| pair[0] == pair[1]) {
+        return Err(RrError::Config(
+            format!("More than one node is configured on port {}.", window[0]),
+            None,
+        ));
+    }
+
+    let primaries = sorted_ports.len() / (replicas + 1);
+    if primaries == 0 {
+        return Err(RrError::Config(
+            format!(
+                "{} nodes are not enough for one primary with {} replicas.",
+                sorted_ports.len(),
+                replicas
+            ),
+            None,
+        ));
+    }
+
+    Ok(sorted_ports
+        .iter()
+        .enumerate()
+        .map(|(index, port)| {
+            if index < primaries {
+                (*port, None)
+            } else {
+                (*port, Some(sorted_ports[(index - primaries) % primaries]))
+            }
+        })
+        .collect())
+}
+
+/// Resolve the host nodes are reached on to an IP address, which is
+/// the only form CLUSTER MEET accepts.
+///
+/// # Arguments
+/// * `cluster_host` - Host name or IP of the cluster nodes.
+///
+/// # Examples
+/// ```
+/// let ip = resolve_meet_ip("localhost").expect("Failed to resolve host.");
+/// ```
+pub fn resolve_meet_ip(cluster_host: &str) -> Result<String, RrError> {
+    if let Ok(ip) = IpAddr::from_str(cluster_host) {
+        return Ok(ip.to_string());
+    }
+
+    match (cluster_host, 0).to_socket_addrs() {
+        Ok(addrs) => {
+            let addrs = addrs.collect::<Vec<_>>();
+            addrs
+                .iter()
+                .find(|addr| addr.is_ipv4())
+                .or_else(|| addrs.first())
+                .map(|addr| addr.ip().to_string())
+                .ok_or_else(|| {
+                    RrError::Protocol(format!("No address found for host {}.", cluster_host), None)
+                })
+        }
+        Err(err) => Err(RrError::Protocol(
+            format!("Failed to resolve host {}.", cluster_host),
+            Some(Box::new(err)),
+        )),
+    }
+}
+
+pub fn cluster_info(client: &mut RedisClient) -> Result<HashMap<String, String>, RrError> {
+    let reply = client.command(&["CLUSTER", "INFO"])?;
+    Ok(parse_info_fields(&reply.as_string().unwrap_or_default()))
+}
+
+/// Check a freshly started node can take part in a new cluster: it must
+/// know no other node, own no slots and hold no keys.
+pub fn ensure_node_is_empty(client: &mut RedisClient) -> Result<(), RrError> {
+    let info = cluster_info(client)?;
+    let known_nodes = info.get("cluster_known_nodes").map(|v| v.as_str());
+    let assigned = info.get("cluster_slots_assigned").map(|v| v.as_str());
+    if known_nodes != Some("1") || assigned != Some("0") {
+        return Err(RrError::ClusterHealth(
+            format!(
+                "Node {} is already part of a cluster or owns slots. Remove its cluster config file and restart it.",
+                client.endpoint
+            ),
+            None,
+        ));
+    }
+
+    match client.command(&["DBSIZE"])? {
+        Reply::Integer(0) => Ok(()),
+        _ => Err(RrError::ClusterHealth(
+            format!(
+                "Node {} is not empty. Flush it before creating the cluster.",
+                client.endpoint
+            ),
+            None,
+        )),
+    }
+}
+
+/// Poll every node until it reports the expected field value, failing
+/// once the deadline passes.
+pub fn wait_for_field(
+    clients: &mut [RedisClient],
+    field: &str,
+    expected: &str,
+    deadline: Instant,
+) -> Result<(), RrError> {
+    for client in clients.iter_mut() {
+        loop {
+            let value = cluster_info(client)?.remove(field).unwrap_or_default();
+            if value == expected {
+                break;
+            }
+            if Instant::now() >= deadline {
+                return Err(RrError::ClusterHealth(
+                    format!(
+                        "Timed out waiting for {} to report {}:{} (last saw {}).",
+                        client.endpoint, field, expected, value
+                    ),
+                    None,
+                ));
+            }
+            thread::sleep(CLUSTER_POLL_INTERVAL);
+        }
+    }
+    Ok(())
+}
+
+/// Whether the node with the given ID is known by its address, past the
+/// handshake, so CLUSTER REPLICATE will accept it.
+fn knows_node(nodes: &[ClusterNode], node_id: &str) -> bool {
+    nodes
+        .iter()
+        .any(|node| node.id == node_id && !node.has_flag("handshake") && !node.has_flag("noaddr"))
+}
+
+/// Poll a node until it knows the node with the given ID, failing once
+/// the deadline passes. Nodes learn each other's IDs through gossip some
+/// time after the MEET, so this has to pass before CLUSTER REPLICATE.
+///
+/// # Arguments
+/// * `client` - Connection to the node that has to learn the ID.
+/// * `node_id` - ID of the node it has to know.
+/// * `deadline` - When to give up waiting.
+///
+/// # Examples
+/// ```
+/// wait_for_node(&mut client, &primary_id, Instant::now() + CLUSTER_FORM_TIMEOUT)?;
+/// client.command(&["CLUSTER", "REPLICATE", &primary_id])?;
+/// ```
+pub fn wait_for_node(
+    client: &mut RedisClient,
+    node_id: &str,
+    deadline: Instant,
+) -> Result<(), RrError> {
+    loop {
+        let reply = client.command(&["CLUSTER", "NODES"])?;
+        if knows_node(
+            &parse_cluster_nodes(&reply.as_string().unwrap_or_default())?,
+            node_id,
+        ) {
+            return Ok(());
+        }
+        if Instant::now() >= deadline {
+            return Err(RrError::ClusterHealth(
+                format!(
+                    "Timed out waiting for {} to learn about node {}.",
+                    client.endpoint, node_id
+                ),
+                None,
+            ));
+        }
+        thread::sleep(CLUSTER_POLL_INTERVAL);
+    }
+}
+
+/// Form a cluster out of freshly started nodes: hand each primary its share
+/// of the slots, give each node a distinct config epoch, introduce them to
+/// each other with CLUSTER MEET, attach the replicas to their primaries and
+/// wait until every node reports the cluster as ok. Only returns Ok once the
+/// cluster is really formed.
+///
+/// # Arguments
+/// * `cluster_host` - Host the nodes are listening on.
+/// * `pid_entries` - The nodes to form the cluster from, with their roles.
+/// * `password` - Password of the nodes, if they need one.
+///
+/// # Examples
+/// ```
+/// let sample_host = "127.0.0.1".to_string();
+/// create_cluster(&sample_host, &get_currently_running_pids()?, None).expect("Failed to create cluster.");
+/// ```
+#[mockable]
+pub fn create_cluster(
+    cluster_host: &String,
+    pid_entries: &HashSet<PIDEntry>,
+    password: Option<&str>,
+) -> Result<(), RrError> {
+    let meet_ip = resolve_meet_ip(cluster_host)?;
+    let mut nodes = pid_entries
+        .iter()
+        .map(|entry| (entry.port, entry.role))
+        .collect::<Vec<(u16, NodeRole)>>();
+    // Primaries first, each group by port, matching the order
+    // plan_replicas hands out roles in.
+    nodes.sort_unstable_by_key(|(port, role)| (*role == NodeRole::Replica, *port));
+    let primaries = nodes
+        .iter()
+        .filter(|(_, role)| *role == NodeRole::Primary)
+        .count();
+    if primaries == 0 {
+        return Err(RrError::Config(
+            "At least one primary is needed to create a cluster.".to_string(),
+            None,
+        ));
+    }
+
+    let mut clients = nodes
+        .iter()
+        .map(|(port, _)| connect_node_with_password(cluster_host, *port, password))
+        .collect::<Result<Vec<RedisClient>, RrError>>()?;
+    for client in clients.iter_mut() {
+        ensure_node_is_empty(client)?;
+    }
+
+    let ranges = slot_ranges(primaries);
+    for (client, (first, last)) in clients.iter_mut().zip(ranges.iter()) {
+        client.command(&[
+            "CLUSTER",
+            "ADDSLOTSRANGE",
+            &first.to_string(),
+            &last.to_string(),
+        ])?;
+        info!("Assigned slots {}-{} to {}.", first, last, client.endpoint);
+    }
+    for (epoch, client) in clients.iter_mut().enumerate() {
+        client.command(&["CLUSTER", "SET-CONFIG-EPOCH", &(epoch + 1).to_string()])?;
+    }
+
+    if let Some((first_client, _)) = clients.split_first_mut() {
+        for (port, _) in nodes.iter().skip(1) {
+            first_client.command(&["CLUSTER", "MEET", &meet_ip, &port.to_string()])?;
+        }
+    }
+
+    let deadline = Instant::now() + CLUSTER_FORM_TIMEOUT;
+    wait_for_field(
+        &mut clients,
+        "cluster_known_nodes",
+        &nodes.len().to_string(),
+        deadline,
+    )?;
+
+    let primary_ids = clients[..primaries]
+        .iter_mut()
+        .map(
+            |client| match client.command(&["CLUSTER", "MYID"])?.as_string() {
+                Some(node_id) => Ok(node_id),
+                None => Err(RrError::Protocol(
+                    format!("{} did not report its node ID.", client.endpoint),
+                    None,
+                )),
+            },
+        )
+        .collect::<Result<Vec<String>, RrError>>()?;
+    for (index, client) in clients[primaries..].iter_mut().enumerate() {
+        let primary = index % primaries;
+        wait_for_node(client, &primary_ids[primary], deadline)?;
+        client.command(&["CLUSTER", "REPLICATE", &primary_ids[primary]])?;
+        info!(
+            "{} now replicates the primary on port {}.",
+            client.endpoint, nodes[primary].0
+        );
+    }
+
+    wait_for_field(&mut clients, "cluster_state", "ok", deadline)?;
+
+    info!(
+        "Cluster formed with {} primaries and {} replicas.",
+        primaries,
+        nodes.len() - primaries
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_slot_ranges_even_split() {
+        let ranges = slot_ranges(4);
+        assert_eq!(
+            ranges,
+            vec![(0, 4095), (4096, 8191), (8192, 12287), (12288, 16383)]
+        );
+    }
+
+    #[test]
+    fn test_slot_ranges_remainder() {
+        let ranges = slot_ranges(3);
+        assert_eq!(ranges, vec![(0, 5461), (5462, 10922), (10923, 16383)]);
+    }
+
+    #[test]
+    fn test_slot_ranges_covers_all_slots() {
+        for primaries in 1..20 {
+            let ranges = slot_ranges(primaries);
+            let covered: usize = ranges
+                .iter()
+                .map(|(first, last)| (*last - *first) as usize + 1)
+                .sum();
+            assert_eq!(covered, CLUSTER_SLOTS as usize);
+            assert_eq!(ranges.last().unwrap().1, CLUSTER_SLOTS - 1);
+        }
+    }
+
+    #[test]
+    fn test_slot_ranges_no_primaries() {
+        assert!(slot_ranges(0).is_empty());
+    }
+
+    #[test]
+    fn test_plan_replicas_round_robin() {
+        let plan = plan_replicas(&[7005, 7001, 7000, 7003, 7002, 7004, 7006], 1).unwrap();
+        assert_eq!(
+            plan,
+            vec![
+                (7000, None),
+                (7001, None),
+                (7002, None),
+                (7003, Some(7000)),
+                (7004, Some(7001)),
+                (7005, Some(7002)),
+                (7006, Some(7000)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_plan_replicas_no_replicas() {
+        let plan = plan_replicas(&[7000, 7001], 0).unwrap();
+        assert_eq!(plan, vec![(7000, None), (7001, None)]);
+    }
+
+    #[test]
+    fn test_plan_replicas_too_few_nodes() {
+        assert!(plan_replicas(&[7000, 7001], 2).is_err());
+    }
+
+    #[test]
+    fn test_plan_replicas_duplicate_ports() {
+        assert!(plan_replicas(&[7000, 7001, 7000], 0).is_err());
+    }
+
+    #[test]
+    fn test_resolve_meet_ip() {
+        assert_eq!(resolve_meet_ip("127.0.0.1").unwrap(), "127.0.0.1");
+        assert!(resolve_meet_ip("localhost").is_ok());
+    }
+
+    #[test]
+    fn test_knows_node() {
+        let nodes = parse_cluster_nodes(
+            "\
+e7d1eecce10fd6bb5eb35b9f99a514335d9ba9ca 127.0.0.1:7003@17003 myself,master - 0 0 0 connected
+67ed2db8d677e59ec4a4cefb06858cf2a1a89fa1 127.0.0.1:7000@17000 master - 0 1426238316232 1 connected 0-16383
+2b7c2ec2fa1ae27b7f02e2a6f1c4a7d6e7a0f4a1 127.0.0.1:7001@17001 handshake - 0 0 0 connected
+824fe116063bc5fcf9f4ffd895bc17aee7731ac3 :0@0 master,noaddr - 0 0 2 disconnected
+",
+        )
+        .unwrap();
+        assert!(knows_node(
+            &nodes,
+            "67ed2db8d677e59ec4a4cefb06858cf2a1a89fa1"
+        ));
+        assert!(!knows_node(
+            &nodes,
+            "2b7c2ec2fa1ae27b7f02e2a6f1c4a7d6e7a0f4a1"
+        ));
+        assert!(!knows_node(
+            &nodes,
+            "824fe116063bc5fcf9f4ffd895bc17aee7731ac3"
+        ));
+        assert!(!knows_node(
+            &nodes,
+            "07c37dfeb235213a872192d90877d0cd55635b91"
+        ));
+    }
+}
@@ -0,0 +1,349 @@
+use std::{
+    collections::HashSet,
+    fmt, thread,
+    time::{Duration, Instant},
+};
+
+use serde::Serialize;
+
+use crate::{
+    cluster::{
+        create::{cluster_info, CLUSTER_POLL_INTERVAL},
+        health::myself,
+        nodes::ClusterNode,
+        status::{query_node, NodeView},
+    },
+    error::RrError,
+    local::{
+        pid::{get_currently_running_pids, write_data_to_pid_file, NodeRole, PIDEntry},
+        process::signal_process,
+    },
+    resp::client::connect_node,
+};
+
+// How long to wait for a replica to be promoted and the cluster to
+// serve all slots again. Covers the node timeout the generated
+// configuration files set plus the election.
+const FAILOVER_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// How a replica takes over from its primary.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum FailoverMode {
+    /// CLUSTER FAILOVER, coordinated with the primary.
+    Default,
+    /// CLUSTER FAILOVER FORCE, without the primary's agreement.
+    Force,
+    /// CLUSTER FAILOVER TAKEOVER, without an election.
+    Takeover,
+    /// The primary's process is killed and the cluster left to recover.
+    Kill,
+}
+
+impl fmt::Display for FailoverMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FailoverMode::Default => f.pad("default"),
+            FailoverMode::Force => f.pad("force"),
+            FailoverMode::Takeover => f.pad("takeover"),
+            FailoverMode::Kill => f.pad("kill"),
+        }
+    }
+}
+
+/// What a failover did and how long the cluster took to recover.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FailoverReport {
+    pub mode: FailoverMode,
+    pub promoted: u16,
+    pub previous_primary: Option<u16>,
+    pub recovery_ms: u64,
+}
+
+fn failover_command(mode: FailoverMode) -> Vec<&'static str> {
+    match mode {
+        FailoverMode::Force => vec!["CLUSTER", "FAILOVER", "FORCE"],
+        FailoverMode::Takeover => vec!["CLUSTER", "FAILOVER", "TAKEOVER"],
+        _ => vec!["CLUSTER", "FAILOVER"],
+    }
+}
+
+fn recorded_entry(pids: &HashSet<PIDEntry>, port: u16) -> Result<&PIDEntry, RrError> {
+    pids.iter()
+        .find(|entry| entry.port == port)
+        .ok_or_else(|| RrError::State(format!("No server is recorded on port {}.", port), None))
+}
+
+fn cluster_member(view: &NodeView, port: u16) -> Result<&ClusterNode, RrError> {
+    myself(view).ok_or_else(|| {
+        RrError::ClusterHealth(
+            format!("Node on port {} is not a cluster member.", port),
+            None,
+        )
+    })
+}
+
+/// Record new roles for some of the recorded nodes, leaving the others
+/// as they are.
+fn record_roles(pids: HashSet<PIDEntry>, roles: &[(u16, NodeRole)]) -> Result<(), RrError> {
+    let updated = pids
+        .into_iter()
+        .map(|mut entry| {
+            if let Some((_, role)) = roles.iter().find(|(port, _)| *port == entry.port) {
+                entry.role = *role;
+            }
+            entry
+        })
+        .collect::<HashSet<PIDEntry>>();
+    write_data_to_pid_file(&updated)
+}
+
+/// Whether the node sees itself as a primary in a cluster serving all
+/// slots.
+fn is_serving_primary(cluster_host: &String, port: u16) -> Result<bool, RrError> {
+    let view = query_node(cluster_host, port)?;
+    if !myself(&view).is_some_and(|node| node.is_primary()) {
+        return Ok(false);
+    }
+    let state = cluster_info(&mut connect_node(cluster_host, port)?)?.remove("cluster_state");
+    Ok(state.as_deref() == Some("ok"))
+}
+
+/// Poll the candidates until one of them serves as a primary, returning
+/// its port.
+fn wait_for_promotion(
+    cluster_host: &String,
+    candidates: &[u16],
+    deadline: Instant,
+) -> Result<u16, RrError> {
+    loop {
+        if let Some(port) = candidates
+            .iter()
+            .find(|port| is_serving_primary(cluster_host, **port).unwrap_or(false))
+        {
+            return Ok(*port);
+        }
+        if Instant::now() >= deadline {
+            return Err(RrError::ClusterHealth(
+                format!(
+                    "No replica was promoted within {} seconds.",
+                    FAILOVER_TIMEOUT.as_secs()
+                ),
+                None,
+            ));
+        }
+        thread::sleep(CLUSTER_POLL_INTERVAL);
+    }
+}
+
+/// Promote a recorded replica with CLUSTER FAILOVER and wait until it
+/// serves as a primary. The recorded roles of the replica and its former
+/// primary are swapped once the promotion completes.
+///
+/// # Arguments
+/// * `cluster_host` - Host the nodes are listening on.
+/// * `port` - Port of the replica to promote.
+/// * `mode` - How the replica takes over, Kill is not accepted here.
+///
+/// # Examples
+/// ```
+/// let sample_host = "127.0.0.1".to_string();
+/// let report = failover_replica(&sample_host, 7003, FailoverMode::Default).expect("Failed to fail over.");
+/// ```
+pub fn failover_replica(
+    cluster_host: &String,
+    port: u16,
+    mode: FailoverMode,
+) -> Result<FailoverReport, RrError> {
+    if mode == FailoverMode::Kill {
+        return Err(RrError::Config(
+            "Kill the primary with kill_primary instead.".to_string(),
+            None,
+        ));
+    }
+    let pids = get_currently_running_pids()?;
+    recorded_entry(&pids, port)?;
+    let view = query_node(cluster_host, port)?;
+    let node = cluster_member(&view, port)?;
+    if node.is_primary() {
+        return Err(RrError::Config(
+            format!(
+                "Node on port {} is already a primary. Pass the port of one of its replicas.",
+                port
+            ),
+            None,
+        ));
+    }
+    let previous_primary = view
+        .cluster_nodes
+        .iter()
+        .find(|other| Some(&other.id) == node.primary_id.as_ref())
+        .and_then(|other| other.port);
+
+    let started = Instant::now();
+    connect_node(cluster_host, port)?.command(&failover_command(mode))?;
+    info!(
+        "Asked the replica on port {} to take over ({}).",
+        port, mode
+    );
+    wait_for_promotion(cluster_host, &[port], started + FAILOVER_TIMEOUT)?;
+    let recovery_ms = started.elapsed().as_millis() as u64;
+
+    let mut roles = vec![(port, NodeRole::Primary)];
+    if let Some(primary_port) = previous_primary {
+        roles.push((primary_port, NodeRole::Replica));
+    }
+    record_roles(pids, &roles)?;
+    info!(
+        "Replica on port {} was promoted in {} ms.",
+        port, recovery_ms
+    );
+    Ok(FailoverReport {
+        mode,
+        promoted: port,
+        previous_primary,
+        recovery_ms,
+    })
+}
+
+/// Kill a recorded primary's process and time how long it takes one of
+/// its replicas to be promoted and the cluster to serve all slots again.
+/// The promoted replica is recorded as a primary, while the killed node
+/// stays recorded so rr cluster status reports it as dead.
+///
+/// # Arguments
+/// * `cluster_host` - Host the nodes are listening on.
+/// * `port` - Port of the primary to kill.
+///
+/// # Examples
+/// ```
+/// let sample_host = "127.0.0.1".to_string();
+/// let report = kill_primary(&sample_host, 7000).expect("Failed to fail over.");
+/// ```
+pub fn kill_primary(cluster_host: &String, port: u16) -> Result<FailoverReport, RrError> {
+    let pids = get_currently_running_pids()?;
+    let pid = recorded_entry(&pids, port)?.pid;
+    let view = query_node(cluster_host, port)?;
+    let node = cluster_member(&view, port)?;
+    if !node.is_primary() {
+        return Err(RrError::Config(
+            format!("Node on port {} is not a primary.", port),
+            None,
+        ));
+    }
+    let replicas = view
+        .cluster_nodes
+        .iter()
+        .filter(|other| other.primary_id.as_ref() == Some(&node.id) && !other.has_flag("fail"))
+        .filter_map(|other| other.port)
+        .collect::<Vec<u16>>();
+    if replicas.is_empty() {
+        return Err(RrError::ClusterHealth(
+            format!("The primary on port {} has no replica to take over.", port),
+            None,
+        ));
+    }
+
+    let started = Instant::now();
+    signal_process(pid, "KILL")?;
+    info!("Killed the primary on port {} (PID {}).", port, pid);
+    let promoted = wait_for_promotion(cluster_host, &replicas, started + FAILOVER_TIMEOUT)?;
+    let recovery_ms = started.elapsed().as_millis() as u64;
+
+    record_roles(pids, &[(promoted, NodeRole::Primary)])?;
+    info!(
+        "Replica on port {} took over and the cluster recovered in {} ms.",
+        promoted, recovery_ms
+    );
+    Ok(FailoverReport {
+        mode: FailoverMode::Kill,
+        promoted,
+        previous_primary: Some(port),
+        recovery_ms,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+
+    use mocktopus::mocking::{MockResult, Mockable};
+
+    use crate::{cluster::nodes::parse_cluster_nodes, resp::reply::parse_info_fields};
+
+    use super::*;
+
+    const NODES: &str = "\
+aaa 127.0.0.1:7000@17000 myself,master - 0 0 1 connected 0-8191
+bbb 127.0.0.1:7001@17001 master - 0 0 2 connected 8192-16383
+ccc 127.0.0.1:7002@17002 slave aaa 0 0 1 connected
+";
+
+    fn mock_cluster(nodes: &'static str) {
+        get_currently_running_pids.mock_safe(|| {
+            MockResult::Return(Ok([(7000, 10), (7001, 11), (7002, 12)]
+                .iter()
+                .map(|(port, pid)| PIDEntry {
+                    port: *port,
+                    pid: *pid,
+                    role: NodeRole::Primary,
+                    ..Default::default()
+                })
+                .collect()))
+        });
+        query_node.mock_safe(move |_, _| {
+            MockResult::Return(Ok(NodeView {
+                cluster_nodes: parse_cluster_nodes(nodes).unwrap(),
+                info: parse_info_fields(""),
+            }))
+        });
+    }
+
+    #[test]
+    fn test_failover_command() {
+        assert_eq!(
+            failover_command(FailoverMode::Default),
+            vec!["CLUSTER", "FAILOVER"]
+        );
+        assert_eq!(
+            failover_command(FailoverMode::Takeover),
+            vec!["CLUSTER", "FAILOVER", "TAKEOVER"]
+        );
+    }
+
+    #[test]
+    fn test_failover_replica_refuses_primary() {
+        mock_cluster(NODES);
+        let result = failover_replica(&"127.0.0.1".to_string(), 7000, FailoverMode::Force);
+        assert!(matches!(result, Err(RrError::Config(..))));
+    }
+
+    #[test]
+    fn test_kill_primary_needs_replica() {
+        mock_cluster("bbb 127.0.0.1:7001@17001 myself,master - 0 0 2 connected 8192-16383\n");
+        signal_process.mock_safe(|_, _| panic!("The primary must not be killed."));
+        let result = kill_primary(&"127.0.0.1".to_string(), 7001);
+        assert!(matches!(result, Err(RrError::ClusterHealth(..))));
+    }
+
+    #[test]
+    fn test_record_roles() {
+        mock_cluster(NODES);
+        write_data_to_pid_file.mock_safe(|pids| {
+            for entry in pids.iter() {
+                let expected = match entry.port {
+                    7002 => NodeRole::Primary,
+                    7000 => NodeRole::Replica,
+                    _ => NodeRole::Primary,
+                };
+                assert_eq!(entry.role, expected);
+            }
+            MockResult::Return(Ok(()))
+        });
+        let pids = get_currently_running_pids().unwrap();
+        record_roles(
+            pids,
+            &[(7002, NodeRole::Primary), (7000, NodeRole::Replica)],
+        )
+        .unwrap();
+    }
+}
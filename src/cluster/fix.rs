@@ -0,0 +1,521 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt,
+};
+
+use serde::Serialize;
+
+use crate::{
+    cluster::{
+        health::{inspect_cluster, myself, slot_map, HealthCheck, HealthFinding},
+        migrate::{move_slot_keys, set_slot_owner, spread_slots, MIGRATE_BATCH_SIZE},
+        nodes::SlotRange,
+        status::NodeView,
+    },
+    error::RrError,
+    resp::{
+        client::{connect_node, RedisClient},
+        reply::Reply,
+    },
+};
+
+/// A single repair `rr cluster fix` makes.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "action", rename_all = "kebab-case")]
+pub enum FixAction {
+    /// Finish an interrupted migration: move the keys left on the source
+    /// to the target and hand the target the slot.
+    CompleteMigration { slot: u16, source: u16, target: u16 },
+    /// Abandon a half open slot: move any keys back to the owner and clear
+    /// the MIGRATING and IMPORTING state of the other nodes.
+    StabilizeSlot {
+        slot: u16,
+        owner: u16,
+        nodes: Vec<u16>,
+    },
+    /// Give slots no node serves to a primary.
+    AssignSlots { first: u16, last: u16, port: u16 },
+}
+
+impl fmt::Display for FixAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FixAction::CompleteMigration {
+                slot,
+                source,
+                target,
+            } => write!(
+                f,
+                "Finish moving slot {} from port {} to port {}.",
+                slot, source, target
+            ),
+            FixAction::StabilizeSlot { slot, owner, nodes } => write!(
+                f,
+                "Close slot {} on ports {} and give it to port {}.",
+                slot,
+                nodes
+                    .iter()
+                    .map(|port| port.to_string())
+                    .collect::<Vec<String>>()
+                    .join(", "),
+                owner
+            ),
+            FixAction::AssignSlots { first, last, port } => write!(
+                f,
+                "Assign slots {} to port {}.",
+                SlotRange {
+                    first: *first,
+                    last: *last
+                },
+                port
+            ),
+        }
+    }
+}
+
+/// What `rr cluster fix` found and did, or would do on a dry run.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FixReport {
+    pub dry_run: bool,
+    pub findings: Vec<HealthFinding>,
+    pub actions: Vec<FixAction>,
+}
+
+#[derive(Debug, Default)]
+struct OpenSlot {
+    migrating: Vec<(u16, String)>,
+    importing: Vec<(u16, String)>,
+}
+
+fn node_ports(views: &[(u16, NodeView)]) -> HashMap<String, u16> {
+    views
+        .iter()
+        .filter_map(|(port, view)| myself(view).map(|node| (node.id.clone(), *port)))
+        .collect()
+}
+
+fn plan_open_slots(
+    views: &[(u16, NodeView)],
+    owners: &[Option<&str>],
+    ports: &HashMap<String, u16>,
+) -> BTreeMap<u16, FixAction> {
+    let mut open: BTreeMap<u16, OpenSlot> = BTreeMap::new();
+    for (port, view) in views.iter() {
+        if let Some(node) = myself(view) {
+            for (slot, target) in node.migrating.iter() {
+                let state = open.entry(*slot).or_default();
+                state.migrating.push((*port, target.clone()));
+            }
+            for (slot, source) in node.importing.iter() {
+                let state = open.entry(*slot).or_default();
+                state.importing.push((*port, source.clone()));
+            }
+        }
+    }
+
+    let ids = ports
+        .iter()
+        .map(|(id, port)| (*port, id.as_str()))
+        .collect::<HashMap<u16, &str>>();
+    let mut actions = BTreeMap::new();
+    for (slot, state) in open.iter() {
+        let owner = match owners[*slot as usize] {
+            Some(owner_id) => match ports.get(owner_id) {
+                Some(port) => Some(*port),
+                // The owner is not one of the nodes rr can reach, so
+                // leave the slot alone rather than guess.
+                None => continue,
+            },
+            None => None,
+        };
+
+        let complete = state.migrating.iter().find_map(|(source, target_id)| {
+            let target = *ports.get(target_id)?;
+            let importing = state.importing.iter().any(|(port, source_id)| {
+                *port == target && ids.get(source) == Some(&source_id.as_str())
+            });
+            (owner == Some(*source) && importing).then_some((*source, target))
+        });
+        let action = match complete {
+            Some((source, target)) => FixAction::CompleteMigration {
+                slot: *slot,
+                source,
+                target,
+            },
+            None => {
+                let mut nodes = state
+                    .migrating
+                    .iter()
+                    .chain(state.importing.iter())
+                    .map(|(port, _)| *port)
+                    .collect::<Vec<u16>>();
+                nodes.sort_unstable();
+                nodes.dedup();
+                // With no owner, the node importing the slot most likely
+                // holds its keys already.
+                let owner = owner
+                    .or_else(|| state.importing.first().map(|(port, _)| *port))
+                    .unwrap_or(nodes[0]);
+                FixAction::StabilizeSlot {
+                    slot: *slot,
+                    owner,
+                    nodes,
+                }
+            }
+        };
+        actions.insert(*slot, action);
+    }
+    actions
+}
+
+/// Plan the repairs for the cluster as seen by the given nodes: open slots
+/// are either finished or closed, then slots no node serves are handed to
+/// the least loaded primaries. The first view is taken as the reference
+/// for who owns which slot.
+///
+/// # Arguments
+/// * `views` - Port and view of every node that could be queried.
+///
+/// # Examples
+/// ```
+/// let (_, views) = inspect_cluster(&sample_host)?;
+/// let actions = plan_fix(&views)?;
+/// ```
+pub fn plan_fix(views: &[(u16, NodeView)]) -> Result<Vec<FixAction>, RrError> {
+    let reference = match views.first() {
+        Some((_, view)) => view,
+        None => return Ok(vec![]),
+    };
+    let owners = slot_map(reference);
+    let ports = node_ports(views);
+
+    let open = plan_open_slots(views, &owners, &ports);
+    let uncovered = owners
+        .iter()
+        .enumerate()
+        .filter(|(slot, owner)| owner.is_none() && !open.contains_key(&(*slot as u16)))
+        .map(|(slot, _)| slot as u16)
+        .collect::<Vec<u16>>();
+
+    let mut loads = reference
+        .cluster_nodes
+        .iter()
+        .filter(|node| node.is_primary() && !node.has_flag("fail"))
+        .filter_map(|node| {
+            let port = ports.get(&node.id)?;
+            let load = node
+                .slots
+                .iter()
+                .map(|r| (r.last - r.first) as usize + 1)
+                .sum();
+            Some((*port, load))
+        })
+        .collect::<Vec<(u16, usize)>>();
+    loads.sort_unstable();
+    if !uncovered.is_empty() && loads.is_empty() {
+        return Err(RrError::ClusterHealth(
+            "No working primary is available to take the uncovered slots.".to_string(),
+            None,
+        ));
+    }
+
+    let mut actions = open.into_values().collect::<Vec<FixAction>>();
+    actions.extend(
+        spread_slots(&uncovered, &mut loads)
+            .into_iter()
+            .map(|(range, port)| FixAction::AssignSlots {
+                first: range.first,
+                last: range.last,
+                port,
+            }),
+    );
+    Ok(actions)
+}
+
+fn count_keys(client: &mut RedisClient, slot: u16) -> Result<i64, RrError> {
+    match client.command(&["CLUSTER", "COUNTKEYSINSLOT", &slot.to_string()])? {
+        Reply::Integer(count) => Ok(count),
+        other => Err(RrError::Protocol(
+            format!(
+                "Unexpected COUNTKEYSINSLOT reply from {}: {:?}",
+                client.endpoint, other
+            ),
+            None,
+        )),
+    }
+}
+
+fn apply_action(
+    cluster_host: &str,
+    views: &[(u16, NodeView)],
+    action: &FixAction,
+) -> Result<(), RrError> {
+    let ports = node_ports(views);
+    let id_of = |port: u16| {
+        ports
+            .iter()
+            .find(|(_, p)| **p == port)
+            .map(|(id, _)| id.clone())
+            .unwrap_or_default()
+    };
+
+    match action {
+        FixAction::CompleteMigration {
+            slot,
+            source,
+            target,
+        } => {
+            let mut source_client = connect_node(cluster_host, *source)?;
+            let moved = move_slot_keys(
+                &mut source_client,
+                cluster_host,
+                *target,
+                *slot,
+                MIGRATE_BATCH_SIZE,
+                true,
+            )?;
+            debug!("Moved {} keys of slot {}.", moved, slot);
+            // Every primary learns about the new owner, the target first
+            // so the slot is never left without one.
+            let mut primaries = vec![*target, *source];
+            primaries.extend(
+                views
+                    .iter()
+                    .filter(|(port, view)| {
+                        *port != *target
+                            && *port != *source
+                            && myself(view).is_some_and(|node| node.is_primary())
+                    })
+                    .map(|(port, _)| *port),
+            );
+            let mut clients = primaries
+                .iter()
+                .map(|port| connect_node(cluster_host, *port))
+                .collect::<Result<Vec<RedisClient>, RrError>>()?;
+            set_slot_owner(&mut clients, *slot, &id_of(*target))
+        }
+        FixAction::StabilizeSlot { slot, owner, nodes } => {
+            let owner_id = id_of(*owner);
+            let mut owner_client = connect_node(cluster_host, *owner)?;
+            set_slot_owner(std::slice::from_mut(&mut owner_client), *slot, &owner_id)?;
+            let mut others = nodes
+                .iter()
+                .filter(|port| **port != *owner)
+                .map(|port| connect_node(cluster_host, *port))
+                .collect::<Result<Vec<RedisClient>, RrError>>()?;
+            for client in others.iter_mut() {
+                if count_keys(client, *slot)? > 0 {
+                    move_slot_keys(
+                        client,
+                        cluster_host,
+                        *owner,
+                        *slot,
+                        MIGRATE_BATCH_SIZE,
+                        true,
+                    )?;
+                }
+            }
+            set_slot_owner(&mut others, *slot, &owner_id)
+        }
+        FixAction::AssignSlots { first, last, port } => {
+            let mut client = connect_node(cluster_host, *port)?;
+            client.command(&[
+                "CLUSTER",
+                "ADDSLOTSRANGE",
+                &first.to_string(),
+                &last.to_string(),
+            ])?;
+            // A fresh epoch makes the other nodes accept the new owner.
+            client.command(&["CLUSTER", "BUMPEPOCH"])?;
+            Ok(())
+        }
+    }
+}
+
+/// Repair open and uncovered slots found by the cluster health check. On a
+/// dry run the plan is only returned, nothing is changed.
+///
+/// # Arguments
+/// * `cluster_host` - Host the nodes are listening on.
+/// * `dry_run` - Only plan the repairs, without making them.
+///
+/// # Examples
+/// ```
+/// let sample_host = "127.0.0.1".to_string();
+/// let report = fix_cluster(&sample_host, true).expect("Failed to plan repairs.");
+/// ```
+pub fn fix_cluster(cluster_host: &String, dry_run: bool) -> Result<FixReport, RrError> {
+    let (health, views) = inspect_cluster(cluster_host)?;
+    if views.is_empty() {
+        return Err(RrError::ClusterHealth(
+            "None of the recorded nodes could be queried.".to_string(),
+            None,
+        ));
+    }
+
+    let actions = plan_fix(&views)?;
+    if !dry_run {
+        for action in actions.iter() {
+            apply_action(cluster_host, &views, action)?;
+            info!("{}", action);
+        }
+    }
+
+    Ok(FixReport {
+        dry_run,
+        findings: health
+            .findings
+            .into_iter()
+            .filter(|f| matches!(f.check, HealthCheck::OpenSlots | HealthCheck::SlotCoverage))
+            .collect(),
+        actions,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::collections::HashSet;
+
+    use mocktopus::mocking::{MockResult, Mockable};
+
+    use crate::{
+        cluster::{nodes::parse_cluster_nodes, status::query_node},
+        local::{
+            pid::{get_currently_running_pids, NodeRole, PIDEntry},
+            process::is_process_alive,
+        },
+        resp::reply::parse_info_fields,
+    };
+
+    use super::*;
+
+    // Build every node's view from one CLUSTER NODES listing, with the
+    // open slot state each node reports about itself.
+    fn views(nodes: &str, open: &[(u16, &str)]) -> Vec<(u16, NodeView)> {
+        [7000, 7001, 7002]
+            .iter()
+            .map(|port| {
+                let address = format!("127.0.0.1:{}@", port);
+                let text = nodes
+                    .lines()
+                    .map(|line| {
+                        if line.contains(&address) {
+                            let extra = open
+                                .iter()
+                                .filter(|(p, _)| p == port)
+                                .map(|(_, slot)| format!(" {}", slot))
+                                .collect::<String>();
+                            line.replacen(" master ", " myself,master ", 1) + &extra
+                        } else {
+                            line.to_string()
+                        }
+                    })
+                    .collect::<Vec<String>>()
+                    .join("\n");
+                let view = NodeView {
+                    cluster_nodes: parse_cluster_nodes(&text).unwrap(),
+                    info: parse_info_fields(""),
+                };
+                (*port, view)
+            })
+            .collect()
+    }
+
+    const NODES: &str = "\
+aaa 127.0.0.1:7000@17000 master - 0 0 1 connected 0-5460
+bbb 127.0.0.1:7001@17001 master - 0 0 2 connected 5461-10922
+ccc 127.0.0.1:7002@17002 master - 0 0 3 connected 10923-16383
+";
+
+    #[test]
+    fn test_plan_fix_healthy() {
+        assert!(plan_fix(&views(NODES, &[])).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_plan_fix_open_slots() {
+        let open = [
+            (7000, "[100->-bbb]"),
+            (7001, "[100-<-aaa]"),
+            (7002, "[200-<-aaa]"),
+        ];
+        let actions = plan_fix(&views(NODES, &open)).unwrap();
+        assert_eq!(
+            actions,
+            vec![
+                FixAction::CompleteMigration {
+                    slot: 100,
+                    source: 7000,
+                    target: 7001
+                },
+                FixAction::StabilizeSlot {
+                    slot: 200,
+                    owner: 7000,
+                    nodes: vec![7002]
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_plan_fix_uncovered_goes_to_least_loaded() {
+        let nodes = NODES
+            .replace("0-5460", "0-4999")
+            .replace("10923-16383", "12000-16383");
+        let actions = plan_fix(&views(&nodes, &[])).unwrap();
+        // 7002 is the least loaded and fills up to its share first, the
+        // rest goes to 7000. 7001 already has its share.
+        assert_eq!(
+            actions,
+            vec![
+                FixAction::AssignSlots {
+                    first: 5000,
+                    last: 5460,
+                    port: 7002
+                },
+                FixAction::AssignSlots {
+                    first: 10923,
+                    last: 11539,
+                    port: 7002
+                },
+                FixAction::AssignSlots {
+                    first: 11540,
+                    last: 11999,
+                    port: 7000
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_fix_cluster_dry_run() {
+        get_currently_running_pids.mock_safe(|| {
+            let mut test_set: HashSet<PIDEntry> = HashSet::new();
+            for (index, port) in [7000, 7001, 7002].iter().enumerate() {
+                test_set.insert(PIDEntry {
+                    port: *port,
+                    pid: 100 + index as u32,
+                    role: NodeRole::Primary,
+                    ..Default::default()
+                });
+            }
+            MockResult::Return(Ok(test_set))
+        });
+        is_process_alive.mock_safe(|_| MockResult::Return(true));
+        query_node.mock_safe(|_, port| {
+            let nodes = NODES.replace("10923-16383", "10923-16000");
+            let view = views(&nodes, &[(7000, "[10->-ccc]")])
+                .into_iter()
+                .find(|(p, _)| *p == port)
+                .map(|(_, view)| view)
+                .unwrap();
+            MockResult::Return(Ok(view))
+        });
+
+        let report = fix_cluster(&"127.0.0.1".to_string(), true).unwrap();
+        assert!(report.dry_run);
+        assert_eq!(report.findings.len(), 2);
+        assert_eq!(report.actions.len(), 2);
+    }
+}
@@ -0,0 +1,606 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt,
+};
+
+use serde::Serialize;
+
+use crate::{
+    cluster::{
+        create::CLUSTER_SLOTS,
+        nodes::{ClusterNode, SlotRange},
+        status::{query_node, NodeView},
+        validate::Severity,
+    },
+    error::RrError,
+    local::{
+        pid::{get_currently_running_pids, PIDEntry},
+        process::is_process_alive,
+    },
+};
+
+/// Which part of the cluster check a finding comes from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum HealthCheck {
+    /// A recorded node could not be queried.
+    NodeReachable,
+    /// Every slot is served by some node.
+    SlotCoverage,
+    /// Every node has the same slot map.
+    SlotAgreement,
+    /// Every node has the same config epochs, and no two primaries share one.
+    EpochAgreement,
+    /// No slot is half way through a migration.
+    OpenSlots,
+    /// No node is flagged as failing.
+    FailedNodes,
+    /// Every primary serving slots has a replica.
+    ReplicaCoverage,
+    /// Every recorded node is a member of the cluster.
+    Membership,
+}
+
+impl fmt::Display for HealthCheck {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HealthCheck::NodeReachable => write!(f, "node-reachable"),
+            HealthCheck::SlotCoverage => write!(f, "slot-coverage"),
+            HealthCheck::SlotAgreement => write!(f, "slot-agreement"),
+            HealthCheck::EpochAgreement => write!(f, "epoch-agreement"),
+            HealthCheck::OpenSlots => write!(f, "open-slots"),
+            HealthCheck::FailedNodes => write!(f, "failed-nodes"),
+            HealthCheck::ReplicaCoverage => write!(f, "replica-coverage"),
+            HealthCheck::Membership => write!(f, "membership"),
+        }
+    }
+}
+
+/// A single problem found while checking the cluster.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct HealthFinding {
+    pub severity: Severity,
+    pub check: HealthCheck,
+    pub message: String,
+}
+
+impl fmt::Display for HealthFinding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[{}] {}", self.check, self.message)
+    }
+}
+
+/// The outcome of checking the cluster.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct HealthReport {
+    pub nodes_checked: usize,
+    pub slots_covered: usize,
+    pub findings: Vec<HealthFinding>,
+}
+
+impl HealthReport {
+    /// Whether the check found no errors. Warnings do not count.
+    pub fn is_ok(&self) -> bool {
+        !self
+            .findings
+            .iter()
+            .any(|finding| finding.severity == Severity::Error)
+    }
+}
+
+fn finding(severity: Severity, check: HealthCheck, message: String) -> HealthFinding {
+    HealthFinding {
+        severity,
+        check,
+        message,
+    }
+}
+
+/// Collapse a sorted list of slots into contiguous ranges.
+///
+/// # Examples
+/// ```
+/// let ranges = collapse_slots(&[1, 2, 3, 7]);
+/// assert_eq!(ranges.len(), 2);
+/// ```
+pub fn collapse_slots(slots: &[u16]) -> Vec<SlotRange> {
+    let mut ranges: Vec<SlotRange> = vec![];
+    for slot in slots.iter() {
+        match ranges.last_mut() {
+            Some(range) if range.last + 1 == *slot => range.last = *slot,
+            _ => ranges.push(SlotRange {
+                first: *slot,
+                last: *slot,
+            }),
+        }
+    }
+    ranges
+}
+
+fn join_ranges(ranges: &[SlotRange]) -> String {
+    ranges
+        .iter()
+        .map(|range| range.to_string())
+        .collect::<Vec<String>>()
+        .join(",")
+}
+
+/// The owner of every slot according to one node's view.
+pub fn slot_map(view: &NodeView) -> Vec<Option<&str>> {
+    let mut owners = vec![None; CLUSTER_SLOTS as usize];
+    for node in view.cluster_nodes.iter() {
+        for range in node.slots.iter() {
+            for slot in range.first..=range.last {
+                owners[slot as usize] = Some(node.id.as_str());
+            }
+        }
+    }
+    owners
+}
+
+/// The entry a node's view has for the node itself.
+pub fn myself(view: &NodeView) -> Option<&ClusterNode> {
+    view.cluster_nodes.iter().find(|node| node.is_myself())
+}
+
+fn check_agreement(views: &[(u16, NodeView)], findings: &mut Vec<HealthFinding>) {
+    let (reference_port, reference) = &views[0];
+    let reference_map = slot_map(reference);
+    for (port, view) in views.iter().skip(1) {
+        let differing = slot_map(view)
+            .iter()
+            .zip(reference_map.iter())
+            .filter(|(owner, reference_owner)| owner != reference_owner)
+            .count();
+        if differing > 0 {
+            findings.push(finding(
+                Severity::Error,
+                HealthCheck::SlotAgreement,
+                format!(
+                    "Node on port {} disagrees with node on port {} about the owner of {} slots.",
+                    port, reference_port, differing
+                ),
+            ));
+        }
+
+        for node in reference.cluster_nodes.iter() {
+            let other = view.cluster_nodes.iter().find(|other| other.id == node.id);
+            if let Some(other) = other {
+                if other.config_epoch != node.config_epoch {
+                    findings.push(finding(
+                        Severity::Error,
+                        HealthCheck::EpochAgreement,
+                        format!(
+                            "Node on port {} sees config epoch {} for {}, node on port {} sees {}.",
+                            port,
+                            other.config_epoch,
+                            node.address,
+                            reference_port,
+                            node.config_epoch
+                        ),
+                    ));
+                }
+            }
+        }
+    }
+
+    let mut epochs: BTreeMap<u64, Vec<&str>> = BTreeMap::new();
+    reference
+        .cluster_nodes
+        .iter()
+        .filter(|node| node.is_primary())
+        .for_each(|node| {
+            epochs
+                .entry(node.config_epoch)
+                .or_default()
+                .push(&node.address)
+        });
+    for (epoch, addresses) in epochs.iter().filter(|(_, addresses)| addresses.len() > 1) {
+        findings.push(finding(
+            Severity::Warning,
+            HealthCheck::EpochAgreement,
+            format!(
+                "Primaries {} share config epoch {}.",
+                addresses.join(", "),
+                epoch
+            ),
+        ));
+    }
+}
+
+fn check_slots(views: &[(u16, NodeView)], findings: &mut Vec<HealthFinding>) -> usize {
+    let reference_map = slot_map(&views[0].1);
+    let uncovered = reference_map
+        .iter()
+        .enumerate()
+        .filter(|(_, owner)| owner.is_none())
+        .map(|(slot, _)| slot as u16)
+        .collect::<Vec<u16>>();
+    if !uncovered.is_empty() {
+        findings.push(finding(
+            Severity::Error,
+            HealthCheck::SlotCoverage,
+            format!(
+                "{} of {} slots are not served by any node: {}",
+                uncovered.len(),
+                CLUSTER_SLOTS,
+                join_ranges(&collapse_slots(&uncovered))
+            ),
+        ));
+    }
+
+    for (port, view) in views.iter() {
+        if let Some(node) = myself(view) {
+            for (slot, target) in node.migrating.iter() {
+                findings.push(finding(
+                    Severity::Warning,
+                    HealthCheck::OpenSlots,
+                    format!("Slot {} on port {} is migrating to {}.", slot, port, target),
+                ));
+            }
+            for (slot, source) in node.importing.iter() {
+                findings.push(finding(
+                    Severity::Warning,
+                    HealthCheck::OpenSlots,
+                    format!(
+                        "Slot {} on port {} is importing from {}.",
+                        slot, port, source
+                    ),
+                ));
+            }
+        }
+    }
+
+    CLUSTER_SLOTS as usize - uncovered.len()
+}
+
+fn check_nodes(views: &[(u16, NodeView)], findings: &mut Vec<HealthFinding>) {
+    let mut failing = BTreeSet::new();
+    let mut suspected = BTreeSet::new();
+    for (_, view) in views.iter() {
+        for node in view.cluster_nodes.iter() {
+            if node.has_flag("fail") {
+                failing.insert(node.address.clone());
+            } else if node.has_flag("fail?") {
+                suspected.insert(node.address.clone());
+            }
+        }
+    }
+    for address in failing.iter() {
+        findings.push(finding(
+            Severity::Error,
+            HealthCheck::FailedNodes,
+            format!("Node {} is marked as failed.", address),
+        ));
+    }
+    for address in suspected.difference(&failing) {
+        findings.push(finding(
+            Severity::Warning,
+            HealthCheck::FailedNodes,
+            format!("Node {} is suspected to be failing.", address),
+        ));
+    }
+
+    let reference = &views[0].1;
+    let is_failing = |node: &ClusterNode| node.has_flag("fail") || node.has_flag("fail?");
+    for primary in reference
+        .cluster_nodes
+        .iter()
+        .filter(|node| node.is_primary() && !node.slots.is_empty() && !is_failing(node))
+    {
+        let replicas = reference
+            .cluster_nodes
+            .iter()
+            .filter(|node| node.primary_id.as_deref() == Some(primary.id.as_str()))
+            .filter(|node| !is_failing(node))
+            .count();
+        if replicas == 0 {
+            findings.push(finding(
+                Severity::Warning,
+                HealthCheck::ReplicaCoverage,
+                format!("Primary {} has no working replica.", primary.address),
+            ));
+        }
+    }
+}
+
+fn check_membership(
+    entries: &[&PIDEntry],
+    views: &[(u16, NodeView)],
+    findings: &mut Vec<HealthFinding>,
+) {
+    let members = views[0]
+        .1
+        .cluster_nodes
+        .iter()
+        .filter_map(|node| node.port)
+        .collect::<BTreeSet<u16>>();
+    for entry in entries.iter() {
+        if !members.contains(&entry.port) {
+            findings.push(finding(
+                Severity::Error,
+                HealthCheck::Membership,
+                format!(
+                    "Node on port {} (pid {}) is recorded by rr but is not part of the cluster.",
+                    entry.port, entry.pid
+                ),
+            ));
+        }
+    }
+}
+
+/// Check the cluster formed by the running server processes, returning
+/// the report along with the view of every node that could be queried,
+/// in port order. The views are what repairs are planned from.
+///
+/// # Arguments
+/// * `cluster_host` - String representing the target cluster host.
+///
+/// # Examples
+/// ```
+/// let sample_host = "localhost".to_string();
+/// let (report, views) = inspect_cluster(&sample_host).expect("Failed to inspect cluster.");
+/// ```
+pub fn inspect_cluster(
+    cluster_host: &String,
+) -> Result<(HealthReport, Vec<(u16, NodeView)>), RrError> {
+    let pids = get_currently_running_pids()?;
+    if pids.is_empty() {
+        return Err(RrError::State(
+            "There are no currently running server processes.".to_string(),
+            None,
+        ));
+    }
+
+    let mut entries = pids.iter().collect::<Vec<&PIDEntry>>();
+    entries.sort_unstable_by_key(|entry| entry.port);
+    let mut findings = vec![];
+    let mut views = vec![];
+    for entry in entries.iter() {
+        let port = entry.port;
+        if !is_process_alive(entry.pid) {
+            findings.push(finding(
+                Severity::Error,
+                HealthCheck::NodeReachable,
+                format!("Process {} for port {} is not running.", entry.pid, port),
+            ));
+            continue;
+        }
+        match query_node(cluster_host, port) {
+            Ok(view) if myself(&view).is_some() => views.push((port, view)),
+            Ok(_) => findings.push(finding(
+                Severity::Error,
+                HealthCheck::Membership,
+                format!(
+                    "Node on port {} does not have cluster support enabled.",
+                    port
+                ),
+            )),
+            Err(err) => findings.push(finding(
+                Severity::Error,
+                HealthCheck::NodeReachable,
+                format!("Node on port {} could not be queried: {}", port, err),
+            )),
+        }
+    }
+
+    let mut slots_covered = 0;
+    if !views.is_empty() {
+        slots_covered = check_slots(&views, &mut findings);
+        check_agreement(&views, &mut findings);
+        check_nodes(&views, &mut findings);
+        check_membership(&entries, &views, &mut findings);
+    }
+
+    let report = HealthReport {
+        nodes_checked: views.len(),
+        slots_covered,
+        findings,
+    };
+    Ok((report, views))
+}
+
+/// Check the cluster formed by the running server processes: every node
+/// is asked for its view of the cluster, and the views are checked for
+/// slot coverage, agreement on the slot map and config epochs, open
+/// slots, failed nodes, primaries without replicas and recorded nodes
+/// that are not cluster members. An unhealthy cluster is still reported
+/// as Ok, check the report's findings.
+///
+/// # Arguments
+/// * `cluster_host` - String representing the target cluster host.
+///
+/// # Examples
+/// ```
+/// let sample_host = "localhost".to_string();
+/// let report = check_cluster_health(&sample_host).expect("Failed to run server health check.");
+/// assert!(report.is_ok());
+/// ```
+pub fn check_cluster_health(cluster_host: &String) -> Result<HealthReport, RrError> {
+    inspect_cluster(cluster_host).map(|(report, _)| report)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::collections::HashSet;
+
+    use mocktopus::mocking::{MockResult, Mockable};
+
+    use crate::{
+        cluster::nodes::parse_cluster_nodes, local::pid::NodeRole, resp::reply::parse_info_fields,
+    };
+
+    use super::*;
+
+    const HEALTHY: &str = "\
+aaa 127.0.0.1:7000@17000 master - 0 0 1 connected 0-8191
+bbb 127.0.0.1:7001@17001 master - 0 0 2 connected 8192-16383
+ccc 127.0.0.1:7002@17002 slave aaa 0 0 1 connected
+ddd 127.0.0.1:7003@17003 slave bbb 0 0 2 connected
+";
+
+    // Mark the line of the node on `port` as myself, the way that
+    // node would answer CLUSTER NODES.
+    fn view_from(nodes: &str, port: u16) -> NodeView {
+        let address = format!("127.0.0.1:{}@", port);
+        let text = nodes
+            .lines()
+            .map(|line| {
+                if line.contains(&address) {
+                    line.replacen(" master ", " myself,master ", 1).replacen(
+                        " slave ",
+                        " myself,slave ",
+                        1,
+                    )
+                } else {
+                    line.to_string()
+                }
+            })
+            .collect::<Vec<String>>()
+            .join("\n");
+        NodeView {
+            cluster_nodes: parse_cluster_nodes(&text).unwrap(),
+            info: parse_info_fields(""),
+        }
+    }
+
+    fn mock_nodes(ports: &'static [u16]) {
+        get_currently_running_pids.mock_safe(move || {
+            MockResult::Return(Ok(ports
+                .iter()
+                .enumerate()
+                .map(|(index, port)| PIDEntry {
+                    port: *port,
+                    pid: 100 + index as u32,
+                    role: NodeRole::Primary,
+                    ..Default::default()
+                })
+                .collect()))
+        });
+        is_process_alive.mock_safe(|_| MockResult::Return(true));
+    }
+
+    fn checks(report: &HealthReport) -> Vec<(Severity, HealthCheck)> {
+        report
+            .findings
+            .iter()
+            .map(|finding| (finding.severity, finding.check))
+            .collect()
+    }
+
+    #[test]
+    fn check_cluster_health_failed_pid_check() {
+        get_currently_running_pids.mock_safe(|| {
+            MockResult::Return(Err(RrError::State("Failed to get PIDs".to_string(), None)))
+        });
+
+        let sample_host = "localhost".to_string();
+        let cluster_result = check_cluster_health(&sample_host);
+        assert_eq!(cluster_result.unwrap_err().exit_code(), 74);
+    }
+
+    #[test]
+    fn check_cluster_health_empty_pid_set() {
+        get_currently_running_pids.mock_safe(|| MockResult::Return(Ok(HashSet::new())));
+
+        let sample_host = "localhost".to_string();
+        let cluster_result = check_cluster_health(&sample_host);
+        assert!(cluster_result.is_err());
+    }
+
+    #[test]
+    fn check_cluster_health_state_ok() {
+        mock_nodes(&[7000, 7001, 7002, 7003]);
+        query_node.mock_safe(|_, port| MockResult::Return(Ok(view_from(HEALTHY, port))));
+
+        let report = check_cluster_health(&"localhost".to_string()).unwrap();
+        assert!(report.is_ok());
+        assert!(report.findings.is_empty());
+        assert_eq!(report.nodes_checked, 4);
+        assert_eq!(report.slots_covered, CLUSTER_SLOTS as usize);
+    }
+
+    #[test]
+    fn check_cluster_health_uncovered_and_unreplicated() {
+        mock_nodes(&[7000, 7001]);
+        query_node.mock_safe(|_, port| {
+            let nodes = "\
+aaa 127.0.0.1:7000@17000 master - 0 0 1 connected 0-8191
+bbb 127.0.0.1:7001@17001 master - 0 0 2 connected 8192-16000 [16001-<-aaa]
+";
+            MockResult::Return(Ok(view_from(nodes, port)))
+        });
+
+        let report = check_cluster_health(&"localhost".to_string()).unwrap();
+        assert!(!report.is_ok());
+        assert_eq!(report.slots_covered, 16001);
+        assert_eq!(
+            checks(&report),
+            vec![
+                (Severity::Error, HealthCheck::SlotCoverage),
+                (Severity::Warning, HealthCheck::OpenSlots),
+                (Severity::Warning, HealthCheck::ReplicaCoverage),
+                (Severity::Warning, HealthCheck::ReplicaCoverage),
+            ]
+        );
+        assert!(report.findings[0].message.ends_with("16001-16383"));
+    }
+
+    #[test]
+    fn check_cluster_health_disagreement_and_failure() {
+        mock_nodes(&[7000, 7001, 7002, 7003]);
+        query_node.mock_safe(|_, port| {
+            let nodes = if port == 7001 {
+                HEALTHY
+                    .replace("0-8191", "0-8000")
+                    .replace("8192-16383", "8001-16383")
+                    .replace(" slave bbb 0 0 2", " slave,fail bbb 0 0 3")
+            } else {
+                HEALTHY.to_string()
+            };
+            MockResult::Return(Ok(view_from(&nodes, port)))
+        });
+
+        let report = check_cluster_health(&"localhost".to_string()).unwrap();
+        assert!(!report.is_ok());
+        let found = checks(&report);
+        assert!(found.contains(&(Severity::Error, HealthCheck::SlotAgreement)));
+        assert!(found.contains(&(Severity::Error, HealthCheck::EpochAgreement)));
+        assert!(found.contains(&(Severity::Error, HealthCheck::FailedNodes)));
+    }
+
+    #[test]
+    fn check_cluster_health_node_unreachable() {
+        mock_nodes(&[7000, 7001, 7002, 7003, 7004]);
+        query_node.mock_safe(|_, port| {
+            if port == 7004 {
+                MockResult::Return(Err(RrError::Protocol(
+                    "Connection refused".to_string(),
+                    None,
+                )))
+            } else {
+                MockResult::Return(Ok(view_from(HEALTHY, port)))
+            }
+        });
+
+        let report = check_cluster_health(&"localhost".to_string()).unwrap();
+        assert!(!report.is_ok());
+        assert_eq!(
+            checks(&report),
+            vec![
+                (Severity::Error, HealthCheck::NodeReachable),
+                (Severity::Error, HealthCheck::Membership),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_collapse_slots() {
+        assert_eq!(
+            join_ranges(&collapse_slots(&[0, 1, 2, 5, 7, 8])),
+            "0-2,5,7-8"
+        );
+        assert!(collapse_slots(&[]).is_empty());
+    }
+}
@@ -0,0 +1,621 @@
+use std::{
+    collections::{BTreeMap, HashSet},
+    env, fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use serde::Deserialize;
+
+use crate::{
+    cluster::{
+        config::{quote_conf_value, render_node_conf, CLUSTER_BUS_PORT_OFFSET},
+        runtime::{redis_server_version, DEFAULT_REDIS_SERVER},
+    },
+    error::RrError,
+    local::cluster::get_or_create_cluster_dir,
+};
+
+// Name of the project manifest rr looks for, walking up from the
+// current directory.
+pub const MANIFEST_FILE_NAME: &str = "rr.toml";
+
+// Nodes and first port of a manifest that does not list its ports.
+const DEFAULT_NODES: u16 = 6;
+const DEFAULT_START_PORT: u16 = 7000;
+
+// Names of the directories, inside a cluster's directory, the
+// configuration files generated from a manifest and the node data
+// are kept in.
+const CONF_DIR_NAME: &str = "conf";
+const DATA_DIR_NAME: &str = "data";
+
+// Directives rr sets itself, as the cluster does not work without them.
+const MANAGED_DIRECTIVES: [&str; 4] = ["port", "cluster-enabled", "dir", "daemonize"];
+
+/// How the nodes of a manifest keep their data on disk.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Persistence {
+    /// An append only file, as rr config init sets up.
+    #[default]
+    Aof,
+    /// Periodic snapshots only.
+    Rdb,
+    /// Nothing is written to disk.
+    None,
+}
+
+/// The value of a directive in a manifest. Lists write the directive
+/// once per item.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(untagged)]
+pub enum DirectiveValue {
+    Flag(bool),
+    Number(i64),
+    Text(String),
+    List(Vec<String>),
+}
+
+impl DirectiveValue {
+    fn conf_values(&self) -> Vec<String> {
+        // Text is written as given, so values made of several arguments
+        // such as "900 1 300 10" keep working.
+        let text = |value: &str| {
+            if value.is_empty() {
+                quote_conf_value(value)
+            } else {
+                value.to_string()
+            }
+        };
+        match self {
+            DirectiveValue::Flag(true) => vec!["yes".to_string()],
+            DirectiveValue::Flag(false) => vec!["no".to_string()],
+            DirectiveValue::Number(number) => vec![number.to_string()],
+            DirectiveValue::Text(value) => vec![text(value)],
+            DirectiveValue::List(values) => values.iter().map(|value| text(value)).collect(),
+        }
+    }
+}
+
+/// The redis-server a manifest's nodes run.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RedisSection {
+    /// Path of the executable, relative to the manifest, or a name
+    /// looked up on the PATH.
+    pub binary: Option<PathBuf>,
+    /// Version the executable must report, such as 7.2 or 7.2.4.
+    pub version: Option<String>,
+}
+
+/// An rr.toml manifest describing a cluster.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Manifest {
+    /// Where the manifest was read from.
+    #[serde(skip)]
+    pub path: PathBuf,
+    /// Name of the cluster, used when no --cluster is given.
+    pub name: Option<String>,
+    pub nodes: Option<u16>,
+    pub start_port: Option<u16>,
+    /// Ports of the nodes, instead of counting up from start_port.
+    pub ports: Option<Vec<u16>>,
+    pub replicas: Option<usize>,
+    #[serde(default)]
+    pub redis: RedisSection,
+    #[serde(default)]
+    pub persistence: Persistence,
+    /// Password clients and replicas authenticate with.
+    pub password: Option<String>,
+    /// Directory holding each node's data, relative to the manifest.
+    pub data_dir: Option<PathBuf>,
+    /// Directives set on every node.
+    #[serde(default)]
+    pub directives: BTreeMap<String, DirectiveValue>,
+    /// Directives set on single nodes, by port.
+    #[serde(default)]
+    pub node: BTreeMap<String, BTreeMap<String, DirectiveValue>>,
+}
+
+fn manifest_error(path: &Path, problem: String) -> RrError {
+    RrError::Config(format!("{}: {}", path.display(), problem), None)
+}
+
+fn check_directives(
+    path: &Path,
+    directives: &BTreeMap<String, DirectiveValue>,
+) -> Result<(), RrError> {
+    for (name, value) in directives.iter() {
+        if MANAGED_DIRECTIVES.contains(&name.as_str()) {
+            return Err(manifest_error(
+                path,
+                format!(
+                    "the {} directive is set by rr and cannot be overridden.",
+                    name
+                ),
+            ));
+        }
+        if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-') {
+            return Err(manifest_error(
+                path,
+                format!("\"{}\" is not a valid directive name.", name),
+            ));
+        }
+        // Values are written as given, so a line break would start a
+        // directive of its own.
+        if value
+            .conf_values()
+            .iter()
+            .any(|text| text.contains(|c: char| c.is_control()))
+        {
+            return Err(manifest_error(
+                path,
+                format!("the value of {} contains control characters.", name),
+            ));
+        }
+    }
+    Ok(())
+}
+
+impl Manifest {
+    /// Directory the manifest is in, which relative paths start from.
+    fn project_dir(&self) -> &Path {
+        self.path.parent().unwrap_or_else(|| Path::new("."))
+    }
+
+    /// Ports of the nodes, either as listed or counting up from the
+    /// start port.
+    ///
+    /// # Examples
+    /// ```
+    /// let ports = manifest.ports().expect("Invalid ports.");
+    /// ```
+    pub fn ports(&self) -> Result<Vec<u16>, RrError> {
+        let ports = match (&self.ports, self.nodes) {
+            (Some(ports), Some(nodes)) if ports.len() != nodes as usize => {
+                return Err(manifest_error(
+                    &self.path,
+                    format!("nodes is {} but {} ports are listed.", nodes, ports.len()),
+                ))
+            }
+            (Some(ports), _) => ports.clone(),
+            (None, nodes) => {
+                let start_port = self.start_port.unwrap_or(DEFAULT_START_PORT);
+                (0..nodes.unwrap_or(DEFAULT_NODES))
+                    .map(|offset| start_port as u32 + offset as u32)
+                    .map(|port| u16::try_from(port).unwrap_or(u16::MAX))
+                    .collect()
+            }
+        };
+        if ports.is_empty() {
+            return Err(manifest_error(
+                &self.path,
+                "at least one node is needed.".to_string(),
+            ));
+        }
+        let mut seen = HashSet::new();
+        for port in ports.iter() {
+            if *port == 0 || *port as u32 + CLUSTER_BUS_PORT_OFFSET as u32 > u16::MAX as u32 {
+                return Err(manifest_error(
+                    &self.path,
+                    format!(
+                        "port {} leaves no room for its cluster bus port (port + {}).",
+                        port, CLUSTER_BUS_PORT_OFFSET
+                    ),
+                ));
+            }
+            if !seen.insert(*port) {
+                return Err(manifest_error(
+                    &self.path,
+                    format!("port {} is listed twice.", port),
+                ));
+            }
+        }
+        Ok(ports)
+    }
+
+    /// Check everything that can be checked before starting a server.
+    fn validate(&self) -> Result<(), RrError> {
+        let ports = self.ports()?;
+        check_directives(&self.path, &self.directives)?;
+        for (port, directives) in self.node.iter() {
+            match u16::from_str(port) {
+                Ok(port) if ports.contains(&port) => {}
+                _ => {
+                    return Err(manifest_error(
+                        &self.path,
+                        format!("[node.{}] does not name the port of a node.", port),
+                    ))
+                }
+            }
+            check_directives(&self.path, directives)?;
+        }
+        Ok(())
+    }
+
+    /// The redis-server executable the nodes run.
+    ///
+    /// # Examples
+    /// ```
+    /// let binary = manifest.binary();
+    /// ```
+    pub fn binary(&self) -> PathBuf {
+        match &self.redis.binary {
+            // A bare name is looked up on the PATH, like the default.
+            Some(binary) if binary.is_relative() && binary.components().count() > 1 => {
+                self.project_dir().join(binary)
+            }
+            Some(binary) => binary.clone(),
+            None => PathBuf::from(DEFAULT_REDIS_SERVER),
+        }
+    }
+
+    /// Check the executable reports the version the manifest asks for.
+    ///
+    /// # Arguments
+    /// * `binary` - The redis-server executable to check.
+    ///
+    /// # Examples
+    /// ```
+    /// manifest.check_version(&manifest.binary()).expect("Wrong redis-server version.");
+    /// ```
+    pub fn check_version(&self, binary: &Path) -> Result<(), RrError> {
+        let wanted = match &self.redis.version {
+            Some(wanted) => wanted,
+            None => return Ok(()),
+        };
+        match redis_server_version(binary) {
+            Some(version) if version_matches(wanted, &version) => Ok(()),
+            Some(version) => Err(manifest_error(
+                &self.path,
+                format!(
+                    "redis-server {} is wanted but {} reports {}.",
+                    wanted,
+                    binary.display(),
+                    version
+                ),
+            )),
+            None => Err(manifest_error(
+                &self.path,
+                format!(
+                    "redis-server {} is wanted but the version of {} could not be found.",
+                    wanted,
+                    binary.display()
+                ),
+            )),
+        }
+    }
+
+    /// Render the configuration file of one node: the settings of rr
+    /// config init, then the manifest's persistence, password and
+    /// directives, with the node's own directives last so they win.
+    ///
+    /// # Arguments
+    /// * `port` - Client port of the node.
+    /// * `node_dir` - Data directory of the node.
+    ///
+    /// # Examples
+    /// ```
+    /// let conf = manifest.render_conf(7000, &PathBuf::from("/tmp/rr/7000"));
+    /// ```
+    pub fn render_conf(&self, port: u16, node_dir: &Path) -> String {
+        let mut lines = vec![format!("# From {}.", self.path.display())];
+        match self.persistence {
+            Persistence::Aof => {}
+            Persistence::Rdb => lines.push("appendonly no".to_string()),
+            Persistence::None => {
+                lines.push("appendonly no".to_string());
+                lines.push("save \"\"".to_string());
+            }
+        }
+        if let Some(password) = &self.password {
+            lines.push(format!("requirepass {}", quote_conf_value(password)));
+            lines.push(format!("masterauth {}", quote_conf_value(password)));
+        }
+        let node_directives = self.node.get(&port.to_string());
+        for directives in [Some(&self.directives), node_directives].iter().flatten() {
+            for (name, value) in directives.iter() {
+                for value in value.conf_values() {
+                    lines.push(format!("{} {}", name, value));
+                }
+            }
+        }
+        render_node_conf(port, node_dir) + &lines.join("\n") + "\n"
+    }
+}
+
+/// Whether a version reported by redis-server is the one wanted, which
+/// may leave out the trailing parts.
+fn version_matches(wanted: &str, version: &str) -> bool {
+    let mut reported = version.split('.');
+    wanted
+        .split('.')
+        .all(|part| reported.next().is_some_and(|other| other == part))
+}
+
+/// Find the manifest of the project a directory is in, looking in the
+/// directory and then in each of its parents.
+///
+/// # Arguments
+/// * `dir` - Directory to start looking in.
+///
+/// # Examples
+/// ```
+/// let manifest_path = find_manifest(&env::current_dir()?);
+/// ```
+pub fn find_manifest(dir: &Path) -> Option<PathBuf> {
+    dir.ancestors()
+        .map(|ancestor| ancestor.join(MANIFEST_FILE_NAME))
+        .find(|path| path.is_file())
+}
+
+/// Read and check a manifest.
+///
+/// # Arguments
+/// * `path` - Path of the manifest.
+///
+/// # Examples
+/// ```
+/// let manifest = load_manifest(Path::new("rr.toml")).expect("Invalid manifest.");
+/// ```
+pub fn load_manifest(path: &Path) -> Result<Manifest, RrError> {
+    let text = fs::read_to_string(path).map_err(|err| {
+        RrError::Config(
+            format!("Failed to read {}.", path.display()),
+            Some(Box::new(err)),
+        )
+    })?;
+    let manifest = toml::from_str::<Manifest>(&text).map_err(|err| {
+        RrError::Config(
+            format!("{} is not a valid manifest.", path.display()),
+            Some(Box::new(err)),
+        )
+    })?;
+    let manifest = Manifest {
+        path: path.to_path_buf(),
+        ..manifest
+    };
+    manifest.validate()?;
+    Ok(manifest)
+}
+
+/// Read the manifest of the project the current directory is in, if
+/// there is one.
+///
+/// # Examples
+/// ```
+/// let manifest = project_manifest().expect("Invalid manifest.");
+/// ```
+pub fn project_manifest() -> Result<Option<Manifest>, RrError> {
+    let current_dir = env::current_dir().map_err(|err| {
+        RrError::Config(
+            "Failed to get the current directory.".to_string(),
+            Some(Box::new(err)),
+        )
+    })?;
+    find_manifest(&current_dir)
+        .map(|path| load_manifest(&path))
+        .transpose()
+}
+
+/// Write the configuration file of every node of a manifest into the
+/// cluster's directory, replacing those written before, and create the
+/// node data directories. Returns the directory the files were written
+/// to.
+///
+/// # Arguments
+/// * `manifest` - The manifest to generate the files from.
+/// * `cluster` - Name of the cluster the files are for.
+///
+/// # Examples
+/// ```
+/// let conf_dir = write_manifest_confs(&manifest, &current_cluster()).expect("Failed to write config files.");
+/// ```
+pub fn write_manifest_confs(manifest: &Manifest, cluster: &str) -> Result<PathBuf, RrError> {
+    let cluster_dir = get_or_create_cluster_dir(cluster)?;
+    let conf_dir = cluster_dir.join(CONF_DIR_NAME);
+    let data_dir = match &manifest.data_dir {
+        Some(data_dir) => manifest.project_dir().join(data_dir),
+        None => cluster_dir.join(DATA_DIR_NAME),
+    };
+    let write_error = |path: &Path, err: std::io::Error| {
+        RrError::Config(
+            format!("Failed to write {}.", path.display()),
+            Some(Box::new(err)),
+        )
+    };
+
+    // Files of nodes the manifest no longer lists must not be started.
+    if conf_dir.exists() {
+        fs::remove_dir_all(&conf_dir).map_err(|err| write_error(&conf_dir, err))?;
+    }
+    fs::create_dir_all(&conf_dir).map_err(|err| write_error(&conf_dir, err))?;
+    for port in manifest.ports()? {
+        let node_dir = data_dir.join(port.to_string());
+        fs::create_dir_all(&node_dir).map_err(|err| write_error(&node_dir, err))?;
+        let conf_path = conf_dir.join(format!("redis-{}.conf", port));
+        fs::write(&conf_path, manifest.render_conf(port, &node_dir))
+            .map_err(|err| write_error(&conf_path, err))?;
+    }
+    Ok(conf_dir)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use mocktopus::mocking::{MockResult, Mockable};
+
+    use crate::cluster::parser::parse_conf_str;
+
+    use super::*;
+
+    const SAMPLE_MANIFEST: &str = r#"
+name = "payments"
+ports = [7100, 7101, 7102]
+replicas = 0
+persistence = "none"
+password = "s3cret pass"
+data_dir = "tmp/data"
+
+[redis]
+binary = "bin/redis-server"
+version = "7.2"
+
+[directives]
+maxmemory = "100mb"
+cluster-node-timeout = 2000
+protected-mode = false
+
+[node.7101]
+maxmemory = "200mb"
+"#;
+
+    fn sample_manifest() -> Manifest {
+        Manifest {
+            path: PathBuf::from("/work/payments/rr.toml"),
+            ..toml::from_str(SAMPLE_MANIFEST).unwrap()
+        }
+    }
+
+    #[test]
+    fn test_manifest_ports() {
+        let manifest = sample_manifest();
+        assert_eq!(manifest.ports().unwrap(), vec![7100, 7101, 7102]);
+        assert_eq!(
+            Manifest::default().ports().unwrap(),
+            vec![7000, 7001, 7002, 7003, 7004, 7005]
+        );
+        let counted = Manifest {
+            nodes: Some(3),
+            start_port: Some(8000),
+            ..Default::default()
+        };
+        assert_eq!(counted.ports().unwrap(), vec![8000, 8001, 8002]);
+
+        for bad in [
+            Manifest {
+                nodes: Some(2),
+                ..manifest.clone()
+            },
+            Manifest {
+                ports: Some(vec![7100, 7100]),
+                ..Default::default()
+            },
+            Manifest {
+                start_port: Some(60000),
+                ..Default::default()
+            },
+            Manifest {
+                nodes: Some(0),
+                ..Default::default()
+            },
+        ] {
+            assert!(matches!(bad.ports(), Err(RrError::Config(..))));
+        }
+    }
+
+    #[test]
+    fn test_manifest_validate() {
+        assert!(sample_manifest().validate().is_ok());
+
+        let mut unknown_node = sample_manifest();
+        unknown_node
+            .node
+            .insert("7200".to_string(), BTreeMap::new());
+        assert!(unknown_node.validate().is_err());
+
+        let mut managed = sample_manifest();
+        managed
+            .directives
+            .insert("port".to_string(), DirectiveValue::Number(7000));
+        assert!(managed.validate().is_err());
+
+        for injected in [
+            DirectiveValue::Text("x\nport 6379".to_string()),
+            DirectiveValue::List(vec!["900 1".to_string(), "x\r\ndir /".to_string()]),
+        ] {
+            let mut manifest = sample_manifest();
+            manifest
+                .node
+                .get_mut("7101")
+                .unwrap()
+                .insert("maxmemory".to_string(), injected);
+            assert!(matches!(manifest.validate(), Err(RrError::Config(..))));
+        }
+
+        assert!(toml::from_str::<Manifest>("nodez = 3").is_err());
+    }
+
+    #[test]
+    fn test_manifest_render_conf() {
+        let manifest = sample_manifest();
+        let conf_text = manifest.render_conf(7101, Path::new("/tmp/rr data/7101"));
+        let conf = parse_conf_str(&conf_text, Path::new("redis-7101.conf")).unwrap();
+        assert_eq!(conf.value("port"), Some("7101"));
+        assert_eq!(conf.value("appendonly"), Some("no"));
+        assert_eq!(conf.value("save"), Some(""));
+        assert_eq!(conf.value("requirepass"), Some("s3cret pass"));
+        assert_eq!(conf.value("masterauth"), Some("s3cret pass"));
+        assert_eq!(conf.value("cluster-node-timeout"), Some("2000"));
+        assert_eq!(conf.value("protected-mode"), Some("no"));
+        assert_eq!(conf.value("maxmemory"), Some("200mb"));
+
+        let conf_text = manifest.render_conf(7100, Path::new("/tmp/rr data/7100"));
+        let conf = parse_conf_str(&conf_text, Path::new("redis-7100.conf")).unwrap();
+        assert_eq!(conf.value("maxmemory"), Some("100mb"));
+    }
+
+    #[test]
+    fn test_manifest_binary_and_version() {
+        let manifest = sample_manifest();
+        assert_eq!(
+            manifest.binary(),
+            PathBuf::from("/work/payments/bin/redis-server")
+        );
+        assert_eq!(
+            Manifest::default().binary(),
+            PathBuf::from(DEFAULT_REDIS_SERVER)
+        );
+
+        redis_server_version.mock_safe(|_| MockResult::Return(Some("7.2.4".to_string())));
+        assert!(manifest.check_version(&manifest.binary()).is_ok());
+        redis_server_version.mock_safe(|_| MockResult::Return(Some("7.0.15".to_string())));
+        assert!(manifest.check_version(&manifest.binary()).is_err());
+        assert!(Manifest::default()
+            .check_version(Path::new(DEFAULT_REDIS_SERVER))
+            .is_ok());
+
+        assert!(version_matches("7", "7.2.4"));
+        assert!(!version_matches("7.2", "7.20.1"));
+        assert!(!version_matches("7.2.4.1", "7.2.4"));
+    }
+
+    #[test]
+    fn test_find_and_load_manifest() {
+        let project_dir = env::temp_dir().join(format!("rr-manifest-{}", std::process::id()));
+        let nested_dir = project_dir.join("src/deep");
+        fs::create_dir_all(&nested_dir).unwrap();
+        assert_eq!(find_manifest(&nested_dir), None);
+
+        let manifest_path = project_dir.join(MANIFEST_FILE_NAME);
+        fs::write(&manifest_path, SAMPLE_MANIFEST).unwrap();
+        assert_eq!(find_manifest(&nested_dir), Some(manifest_path.clone()));
+
+        let manifest = load_manifest(&manifest_path).unwrap();
+        assert_eq!(manifest.path, manifest_path);
+        assert_eq!(manifest.name.as_deref(), Some("payments"));
+
+        fs::write(
+            &manifest_path,
+            "ports = [7000]\n[node.7001]\nmaxmemory = 1\n",
+        )
+        .unwrap();
+        assert!(matches!(
+            load_manifest(&manifest_path),
+            Err(RrError::Config(..))
+        ));
+        fs::remove_dir_all(project_dir).unwrap();
+    }
+}
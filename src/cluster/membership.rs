@@ -0,0 +1,471 @@
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+    slice,
+    str::FromStr,
+    time::{Duration, Instant},
+};
+
+use serde::Serialize;
+
+use crate::{
+    cluster::{
+        config::read_conf_file,
+        create::{
+            cluster_info, ensure_node_is_empty, resolve_meet_ip, wait_for_field, wait_for_node,
+            CLUSTER_FORM_TIMEOUT,
+        },
+        health::myself,
+        migrate::{migrate_slot, node_id, spread_slots, MIGRATE_BATCH_SIZE},
+        nodes::ClusterNode,
+        runtime::{
+            redis_server_version, spawn_server_process, stop_processes, wait_until_ready,
+            StopMethod, DEFAULT_REDIS_SERVER, STOP_TIMEOUT,
+        },
+        status::{query_node, NodeView},
+    },
+    error::RrError,
+    local::{
+        cluster::current_cluster,
+        pid::{get_currently_running_pids, write_data_to_pid_file, NodeRole, PIDEntry},
+        process::{is_process_alive, process_info},
+        state::check_ports_unclaimed,
+    },
+    resp::client::{connect_node, node_password, RedisClient},
+};
+
+/// What removing a node from the cluster did.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct RemovedNode {
+    pub port: u16,
+    pub pid: u32,
+    pub role: NodeRole,
+    pub node_id: Option<String>,
+    pub slots_moved: usize,
+    pub keys_moved: usize,
+}
+
+/// Start a server from a configuration file and join it to the running
+/// cluster, as an empty primary or as a replica of the node on the given
+/// port. The node is recorded in the PID file as soon as it answers PING
+/// so rr cluster stop still finds it if joining fails, while a server that
+/// never gets ready is killed. The server runs the same redis-server
+/// executable as the recorded nodes.
+///
+/// # Arguments
+/// * `cluster_host` - Host the nodes are listening on.
+/// * `conf_file` - Configuration file of the node to add.
+/// * `replica_of` - Port of the primary the new node should replicate.
+/// * `ready_timeout` - How long the server gets to answer PING.
+///
+/// # Examples
+/// ```
+/// let sample_host = "127.0.0.1".to_string();
+/// let entry = add_node(&sample_host, Path::new("7006.conf"), None, READY_TIMEOUT).expect("Failed to add node.");
+/// ```
+pub fn add_node(
+    cluster_host: &str,
+    conf_file: &Path,
+    replica_of: Option<u16>,
+    ready_timeout: Duration,
+) -> Result<PIDEntry, RrError> {
+    let conf = read_conf_file(conf_file)?;
+    let port = match conf.value("port").map(u16::from_str) {
+        Some(Ok(port)) => port,
+        _ => {
+            return Err(RrError::Config(
+                format!("{} does not set a valid port.", conf_file.display()),
+                None,
+            ))
+        }
+    };
+
+    let mut pids = get_currently_running_pids()?;
+    if pids.is_empty() {
+        return Err(RrError::State(
+            "No servers are running. Start the cluster before adding nodes.".to_string(),
+            None,
+        ));
+    }
+    let mut ports = pids.iter().map(|entry| entry.port).collect::<Vec<u16>>();
+    ports.sort_unstable();
+    if ports.contains(&port) {
+        return Err(RrError::State(
+            format!("A server is already recorded on port {}.", port),
+            None,
+        ));
+    }
+    if let Some(primary_port) = replica_of {
+        if !ports.contains(&primary_port) {
+            return Err(RrError::State(
+                format!("No server is recorded on port {}.", primary_port),
+                None,
+            ));
+        }
+    }
+    let ports_lock = check_ports_unclaimed(&current_cluster(), &[port])?;
+
+    // Any member that answers can introduce the new node, preferring the
+    // primary it will replicate.
+    let mut introducer = replica_of
+        .into_iter()
+        .chain(ports.iter().copied())
+        .find_map(|member| connect_node(cluster_host, member).ok())
+        .ok_or_else(|| {
+            RrError::ClusterHealth("No cluster member could be reached.".to_string(), None)
+        })?;
+    let known_nodes = cluster_info(&mut introducer)?
+        .remove("cluster_known_nodes")
+        .and_then(|v| usize::from_str(&v).ok())
+        .unwrap_or(1);
+
+    let binary = pids
+        .iter()
+        .find_map(|entry| entry.binary.clone())
+        .unwrap_or_else(|| PathBuf::from(DEFAULT_REDIS_SERVER));
+    let conf_path = conf_file.display().to_string();
+    let (mut child, log_path) = spawn_server_process(&binary, conf_path.clone(), port)?;
+    let pid = wait_until_ready(
+        &mut child,
+        &conf_path,
+        &log_path,
+        cluster_host,
+        port,
+        node_password().as_deref(),
+        Instant::now() + ready_timeout,
+    )
+    .inspect_err(|_| {
+        let _ = child.kill();
+    })?;
+    let process = process_info(pid);
+    let mut entry = PIDEntry {
+        port,
+        pid,
+        role: match replica_of {
+            Some(_) => NodeRole::Replica,
+            None => NodeRole::Primary,
+        },
+        start_time: process.as_ref().map(|info| info.start_time),
+        conf: Some(conf_file.to_path_buf()),
+        data_dir: conf.value("dir").map(PathBuf::from),
+        log: Some(log_path),
+        binary: process.and_then(|info| info.exe),
+        version: redis_server_version(&binary),
+        ..Default::default()
+    };
+    info!("Server on port {} started with PID: {}.", port, pid);
+    pids.insert(entry.clone());
+    write_data_to_pid_file(&pids)?;
+    drop(ports_lock);
+
+    let deadline = Instant::now() + CLUSTER_FORM_TIMEOUT;
+    let mut client = connect_node(cluster_host, port)?;
+    ensure_node_is_empty(&mut client)?;
+    introducer.command(&[
+        "CLUSTER",
+        "MEET",
+        &resolve_meet_ip(cluster_host)?,
+        &port.to_string(),
+    ])?;
+    wait_for_field(
+        slice::from_mut(&mut client),
+        "cluster_known_nodes",
+        &(known_nodes + 1).to_string(),
+        deadline,
+    )?;
+
+    if let Some(primary_port) = replica_of {
+        let primary_id = node_id(&mut connect_node(cluster_host, primary_port)?)?;
+        wait_for_node(&mut client, &primary_id, deadline)?;
+        client.command(&["CLUSTER", "REPLICATE", &primary_id])?;
+        info!(
+            "{} now replicates the primary on port {}.",
+            client.endpoint, primary_port
+        );
+    }
+    wait_for_field(
+        slice::from_mut(&mut client),
+        "cluster_state",
+        "ok",
+        deadline,
+    )?;
+
+    pids.remove(&entry);
+    entry.node_id = node_id(&mut client).ok();
+    pids.insert(entry.clone());
+    write_data_to_pid_file(&pids)?;
+    info!("Node on port {} joined the cluster.", port);
+    Ok(entry)
+}
+
+/// Move every slot of a primary to the other primaries, least loaded
+/// first. Returns the number of slots and keys moved.
+fn drain_primary(
+    cluster_host: &str,
+    source: &mut RedisClient,
+    node: &ClusterNode,
+    view: &NodeView,
+) -> Result<(usize, usize), RrError> {
+    let slots = node
+        .slots
+        .iter()
+        .flat_map(|range| range.first..=range.last)
+        .collect::<Vec<u16>>();
+    if slots.is_empty() {
+        return Ok((0, 0));
+    }
+
+    let mut loads = view
+        .cluster_nodes
+        .iter()
+        .filter(|other| other.id != node.id && other.is_primary() && !other.has_flag("fail"))
+        .filter_map(|other| {
+            let served = other
+                .slots
+                .iter()
+                .map(|range| (range.last - range.first) as usize + 1)
+                .sum::<usize>();
+            other.port.map(|port| (port, served))
+        })
+        .collect::<Vec<(u16, usize)>>();
+    if loads.is_empty() {
+        return Err(RrError::ClusterHealth(
+            format!(
+                "{} is the only primary, its slots have nowhere to go.",
+                source.endpoint
+            ),
+            None,
+        ));
+    }
+    loads.sort_unstable();
+
+    let mut keys_moved = 0;
+    for (range, port) in spread_slots(&slots, &mut loads) {
+        let mut target = connect_node(cluster_host, port)?;
+        for slot in range.first..=range.last {
+            keys_moved += migrate_slot(
+                source,
+                &mut target,
+                cluster_host,
+                port,
+                slot,
+                MIGRATE_BATCH_SIZE,
+                false,
+            )?;
+        }
+        info!("Moved slots {} to the primary on port {}.", range, port);
+    }
+    Ok((slots.len(), keys_moved))
+}
+
+/// Point the replicas of a primary that is about to leave at the
+/// remaining primary with the fewest replicas.
+fn move_replicas(cluster_host: &str, node: &ClusterNode, view: &NodeView) -> Result<(), RrError> {
+    let live = |other: &&ClusterNode| other.id != node.id && !other.has_flag("fail");
+    let replica_count = |primary: &ClusterNode| {
+        view.cluster_nodes
+            .iter()
+            .filter(|other| other.primary_id.as_deref() == Some(primary.id.as_str()))
+            .count()
+    };
+    let new_primary = view
+        .cluster_nodes
+        .iter()
+        .filter(live)
+        .filter(|other| other.is_primary())
+        .min_by_key(|other| (replica_count(other), other.port));
+    for replica in view
+        .cluster_nodes
+        .iter()
+        .filter(live)
+        .filter(|other| other.primary_id.as_deref() == Some(node.id.as_str()))
+    {
+        match (replica.port, new_primary) {
+            (Some(port), Some(primary)) => {
+                connect_node(cluster_host, port)?.command(&[
+                    "CLUSTER",
+                    "REPLICATE",
+                    &primary.id,
+                ])?;
+                info!(
+                    "Replica on port {} now replicates {}.",
+                    port,
+                    primary.port.unwrap_or_default()
+                );
+            }
+            _ => warn!(
+                "Replica {} of the removed node was left without a primary.",
+                replica.id
+            ),
+        }
+    }
+    Ok(())
+}
+
+/// Take a recorded node out of the cluster: move its slots to the other
+/// primaries and its replicas to another primary, make every other node
+/// forget it, shut it down and drop it from the PID file. A node whose
+/// process already died is only forgotten; the slots it owned are left
+/// uncovered for rr cluster fix to reassign.
+///
+/// # Arguments
+/// * `cluster_host` - Host the nodes are listening on.
+/// * `port` - Port of the node to remove.
+///
+/// # Examples
+/// ```
+/// let sample_host = "127.0.0.1".to_string();
+/// let removed = remove_node(&sample_host, 7006).expect("Failed to remove node.");
+/// ```
+pub fn remove_node(cluster_host: &String, port: u16) -> Result<RemovedNode, RrError> {
+    let mut pids = get_currently_running_pids()?;
+    let entry = match pids.iter().find(|entry| entry.port == port) {
+        Some(entry) => entry.clone(),
+        None => {
+            return Err(RrError::State(
+                format!("No server is recorded on port {}.", port),
+                None,
+            ))
+        }
+    };
+    let mut removed = RemovedNode {
+        port,
+        pid: entry.pid,
+        role: entry.role,
+        node_id: None,
+        slots_moved: 0,
+        keys_moved: 0,
+    };
+
+    let alive = is_process_alive(entry.pid);
+    // The cluster as seen by the node itself while it still runs,
+    // otherwise by the first other member that answers.
+    let view = if alive {
+        Some(query_node(cluster_host, port)?)
+    } else {
+        pids.iter()
+            .filter(|other| other.pid != entry.pid && is_process_alive(other.pid))
+            .find_map(|other| query_node(cluster_host, other.port).ok())
+    };
+    let node = view.as_ref().and_then(|view| match alive {
+        true => myself(view).cloned(),
+        false => view
+            .cluster_nodes
+            .iter()
+            .find(|node| node.port == Some(port))
+            .cloned(),
+    });
+
+    if let (Some(view), Some(node)) = (&view, &node) {
+        removed.node_id = Some(node.id.clone());
+        if node.is_primary() {
+            if alive {
+                let mut source = connect_node(cluster_host, port)?;
+                let (slots, keys) = drain_primary(cluster_host, &mut source, node, view)?;
+                removed.slots_moved = slots;
+                removed.keys_moved = keys;
+            } else if !node.slots.is_empty() {
+                warn!(
+                    "The dead node owned slots that are now uncovered. Run rr cluster fix to reassign them."
+                );
+            }
+            move_replicas(cluster_host, node, view)?;
+        }
+
+        for other in view.cluster_nodes.iter().filter(|other| {
+            other.id != node.id && !other.has_flag("fail") && !other.has_flag("noaddr")
+        }) {
+            if let Some(other_port) = other.port {
+                connect_node(cluster_host, other_port)?
+                    .command(&["CLUSTER", "FORGET", &node.id])?;
+            }
+        }
+        info!("Every other node forgot {}.", node.id);
+    }
+
+    if alive {
+        let outcome = stop_processes(
+            cluster_host,
+            &HashSet::from([entry.clone()]),
+            StopMethod::Shutdown,
+            STOP_TIMEOUT,
+        );
+        if !outcome.survivors.is_empty() {
+            return Err(RrError::Process(
+                format!("Server on port {} is still running after SIGKILL.", port),
+                None,
+            ));
+        }
+    }
+    pids.remove(&entry);
+    write_data_to_pid_file(&pids)?;
+    info!("Removed the node on port {}.", port);
+    Ok(removed)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::{fs, path::PathBuf};
+
+    use mocktopus::mocking::{MockResult, Mockable};
+
+    use crate::cluster::runtime::READY_TIMEOUT;
+
+    use super::*;
+
+    fn mock_pids() {
+        get_currently_running_pids.mock_safe(|| {
+            MockResult::Return(Ok(HashSet::from([PIDEntry {
+                port: 7000,
+                pid: 10,
+                role: NodeRole::Primary,
+                ..Default::default()
+            }])))
+        });
+    }
+
+    #[test]
+    fn test_add_node_port_already_recorded() {
+        mock_pids();
+        let conf_path = PathBuf::from("membership-7000.conf");
+        fs::write(&conf_path, "port 7000\n").unwrap();
+
+        let result = add_node("127.0.0.1", &conf_path, None, READY_TIMEOUT);
+        fs::remove_file(&conf_path).unwrap();
+        assert!(matches!(result, Err(RrError::State(..))));
+    }
+
+    #[test]
+    fn test_add_node_unknown_primary() {
+        mock_pids();
+        let conf_path = PathBuf::from("membership-7001.conf");
+        fs::write(&conf_path, "port 7001\n").unwrap();
+
+        let result = add_node("127.0.0.1", &conf_path, Some(7005), READY_TIMEOUT);
+        fs::remove_file(&conf_path).unwrap();
+        assert!(matches!(result, Err(RrError::State(..))));
+    }
+
+    #[test]
+    fn test_remove_node_not_recorded() {
+        mock_pids();
+        let result = remove_node(&"127.0.0.1".to_string(), 7005);
+        assert!(matches!(result, Err(RrError::State(..))));
+    }
+
+    #[test]
+    fn test_remove_dead_node_only_updates_pid_file() {
+        mock_pids();
+        is_process_alive.mock_safe(|_| MockResult::Return(false));
+        write_data_to_pid_file.mock_safe(|pids| {
+            assert!(pids.is_empty());
+            MockResult::Return(Ok(()))
+        });
+
+        let removed = remove_node(&"127.0.0.1".to_string(), 7000).unwrap();
+        assert_eq!(removed.pid, 10);
+        assert_eq!(removed.node_id, None);
+        assert_eq!(removed.slots_moved, 0);
+    }
+}
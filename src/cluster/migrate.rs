@@ -0,0 +1,298 @@
+use crate::{
+    cluster::nodes::SlotRange,
+    error::RrError,
+    resp::{
+        client::{node_password, RedisClient},
+        reply::Reply,
+    },
+};
+
+// Number of keys fetched and moved with each MIGRATE unless told
+// otherwise.
+pub const MIGRATE_BATCH_SIZE: usize = 100;
+
+// How long, in milliseconds, a single MIGRATE may take before the
+// source node gives up on it.
+const MIGRATE_TIMEOUT_MS: &str = "60000";
+
+/// Build the MIGRATE command moving the given keys to host:port. The
+/// password lets password protected targets accept the keys, and replace
+/// overwrites keys the target already holds.
+fn migrate_command(
+    target_host: &str,
+    target_port: u16,
+    keys: &[String],
+    password: Option<&str>,
+    replace: bool,
+) -> Vec<String> {
+    let mut command = vec![
+        "MIGRATE".to_string(),
+        target_host.to_string(),
+        target_port.to_string(),
+        String::new(),
+        "0".to_string(),
+        MIGRATE_TIMEOUT_MS.to_string(),
+    ];
+    if replace {
+        command.push("REPLACE".to_string());
+    }
+    if let Some(password) = password {
+        command.push("AUTH".to_string());
+        command.push(password.to_string());
+    }
+    command.push("KEYS".to_string());
+    command.extend(keys.iter().cloned());
+    command
+}
+
+/// Move every key of a slot from the source node to the node listening on
+/// target_host:target_port, in batches, passing along the password of the
+/// nodes. The target must own the slot or be importing it. A move
+/// resumed after an interruption may find keys already copied to the
+/// target, so it should replace them. Returns the number of keys moved.
+///
+/// # Arguments
+/// * `source` - Connection to the node currently holding the keys.
+/// * `target_host` - Host the target node listens on.
+/// * `target_port` - Port the target node listens on.
+/// * `slot` - The slot whose keys are moved.
+/// * `batch_size` - Number of keys moved with each MIGRATE.
+/// * `replace` - Whether to overwrite keys the target already holds.
+///
+/// # Examples
+/// ```
+/// let moved = move_slot_keys(&mut source, "127.0.0.1", 7001, 42, MIGRATE_BATCH_SIZE, false).expect("Failed to move keys.");
+/// ```
+pub fn move_slot_keys(
+    source: &mut RedisClient,
+    target_host: &str,
+    target_port: u16,
+    slot: u16,
+    batch_size: usize,
+    replace: bool,
+) -> Result<usize, RrError> {
+    let password = node_password();
+    let mut moved = 0;
+    loop {
+        let keys = match source.command(&[
+            "CLUSTER",
+            "GETKEYSINSLOT",
+            &slot.to_string(),
+            &batch_size.to_string(),
+        ])? {
+            Reply::Array(keys) | Reply::Set(keys) => keys
+                .iter()
+                .filter_map(|key| key.as_string())
+                .collect::<Vec<String>>(),
+            other => {
+                return Err(RrError::Protocol(
+                    format!(
+                        "Unexpected GETKEYSINSLOT reply from {}: {:?}",
+                        source.endpoint, other
+                    ),
+                    None,
+                ))
+            }
+        };
+        if keys.is_empty() {
+            return Ok(moved);
+        }
+
+        source.command(&migrate_command(
+            target_host,
+            target_port,
+            &keys,
+            password.as_deref(),
+            replace,
+        ))?;
+        moved += keys.len();
+    }
+}
+
+/// Give a slot to a node by running CLUSTER SETSLOT NODE on each of the
+/// given nodes, which also clears any MIGRATING or IMPORTING state they
+/// had for it. The new owner should come first.
+///
+/// # Arguments
+/// * `clients` - Connections to the nodes to tell about the new owner.
+/// * `slot` - The slot being assigned.
+/// * `node_id` - ID of the node the slot now belongs to.
+///
+/// # Examples
+/// ```
+/// set_slot_owner(&mut [target, source], 42, &target_id).expect("Failed to assign slot.");
+/// ```
+pub fn set_slot_owner(
+    clients: &mut [RedisClient],
+    slot: u16,
+    node_id: &str,
+) -> Result<(), RrError> {
+    for client in clients.iter_mut() {
+        client.command(&["CLUSTER", "SETSLOT", &slot.to_string(), "NODE", node_id])?;
+    }
+    Ok(())
+}
+
+/// Move a slot from one primary to another the way a reshard does: mark
+/// it importing on the target and migrating on the source, move its keys,
+/// then give it to the target. The target bumps its config epoch when it
+/// takes the slot, which spreads the new owner to the other nodes.
+/// Returns the number of keys moved.
+///
+/// # Arguments
+/// * `source` - Connection to the primary giving up the slot.
+/// * `target` - Connection to the primary taking the slot.
+/// * `target_host` - Host the target listens on, as the source reaches it.
+/// * `target_port` - Port the target listens on.
+/// * `slot` - The slot to move.
+/// * `batch_size` - Number of keys moved with each MIGRATE.
+/// * `resuming` - Whether the slot is already importing or migrating.
+///
+/// # Examples
+/// ```
+/// let moved = migrate_slot(&mut source, &mut target, "127.0.0.1", 7001, 42, MIGRATE_BATCH_SIZE, false).expect("Failed to move slot.");
+/// ```
+pub fn migrate_slot(
+    source: &mut RedisClient,
+    target: &mut RedisClient,
+    target_host: &str,
+    target_port: u16,
+    slot: u16,
+    batch_size: usize,
+    resuming: bool,
+) -> Result<usize, RrError> {
+    let source_id = node_id(source)?;
+    let target_id = node_id(target)?;
+    let slot_arg = slot.to_string();
+    target.command(&["CLUSTER", "SETSLOT", &slot_arg, "IMPORTING", &source_id])?;
+    source.command(&["CLUSTER", "SETSLOT", &slot_arg, "MIGRATING", &target_id])?;
+    let moved = move_slot_keys(source, target_host, target_port, slot, batch_size, resuming)?;
+    target.command(&["CLUSTER", "SETSLOT", &slot_arg, "NODE", &target_id])?;
+    source.command(&["CLUSTER", "SETSLOT", &slot_arg, "NODE", &target_id])?;
+    Ok(moved)
+}
+
+/// The ID of the node a client is connected to.
+///
+/// # Examples
+/// ```
+/// let id = node_id(&mut client).expect("Failed to read node ID.");
+/// ```
+pub fn node_id(client: &mut RedisClient) -> Result<String, RrError> {
+    match client.command(&["CLUSTER", "MYID"])?.as_string() {
+        Some(id) => Ok(id),
+        None => Err(RrError::Protocol(
+            format!("{} did not report its node ID.", client.endpoint),
+            None,
+        )),
+    }
+}
+
+/// Spread slots over primaries, filling the least loaded primary up to its
+/// fair share first and handing out contiguous ranges. The loads are
+/// updated with the slots handed out.
+///
+/// # Arguments
+/// * `slots` - The slots to hand out, in order.
+/// * `loads` - Port and number of slots served of every primary.
+///
+/// # Examples
+/// ```
+/// let ranges = spread_slots(&[0, 1, 2, 3], &mut vec![(7000, 10), (7001, 0)]);
+/// ```
+pub fn spread_slots(slots: &[u16], loads: &mut [(u16, usize)]) -> Vec<(SlotRange, u16)> {
+    let mut ranges: Vec<(SlotRange, u16)> = vec![];
+    if loads.is_empty() {
+        return ranges;
+    }
+
+    let total = loads.iter().map(|(_, load)| *load).sum::<usize>() + slots.len();
+    let target = total.div_ceil(loads.len());
+    let least_loaded = |loads: &[(u16, usize)]| {
+        (0..loads.len())
+            .min_by_key(|index| loads[*index].1)
+            .unwrap_or_default()
+    };
+    let mut current = least_loaded(loads);
+    for slot in slots.iter() {
+        if loads[current].1 >= target {
+            current = least_loaded(loads);
+        }
+        loads[current].1 += 1;
+        let port = loads[current].0;
+        match ranges.last_mut() {
+            Some((range, last_port)) if *last_port == port && range.last + 1 == *slot => {
+                range.last = *slot
+            }
+            _ => ranges.push((
+                SlotRange {
+                    first: *slot,
+                    last: *slot,
+                },
+                port,
+            )),
+        }
+    }
+    ranges
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_spread_slots_evenly() {
+        let slots = (0..9).collect::<Vec<u16>>();
+        let mut loads = vec![(7000, 0), (7001, 0), (7002, 0)];
+        let ranges = spread_slots(&slots, &mut loads);
+        assert_eq!(ranges.len(), 3);
+        assert_eq!(loads, vec![(7000, 3), (7001, 3), (7002, 3)]);
+        assert_eq!(ranges[2], (SlotRange { first: 6, last: 8 }, 7002));
+    }
+
+    #[test]
+    fn test_spread_slots_no_primaries() {
+        assert!(spread_slots(&[1, 2], &mut []).is_empty());
+    }
+
+    #[test]
+    fn test_migrate_command() {
+        let keys = vec!["a".to_string(), "b".to_string()];
+        assert_eq!(
+            migrate_command("127.0.0.1", 7001, &keys, None, false),
+            vec![
+                "MIGRATE",
+                "127.0.0.1",
+                "7001",
+                "",
+                "0",
+                "60000",
+                "KEYS",
+                "a",
+                "b"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_migrate_command_resumed_with_auth() {
+        let keys = vec!["a".to_string()];
+        assert_eq!(
+            migrate_command("127.0.0.1", 7001, &keys, Some("secret"), true),
+            vec![
+                "MIGRATE",
+                "127.0.0.1",
+                "7001",
+                "",
+                "0",
+                "60000",
+                "REPLACE",
+                "AUTH",
+                "secret",
+                "KEYS",
+                "a"
+            ]
+        );
+    }
+}
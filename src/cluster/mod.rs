@@ -1,2 +1,14 @@
 pub mod config;
+pub mod create;
+pub mod failover;
+pub mod fix;
+pub mod health;
+pub mod manifest;
+pub mod membership;
+pub mod migrate;
+pub mod nodes;
+pub mod parser;
+pub mod reshard;
 pub mod runtime;
+pub mod status;
+pub mod validate;
@@ -0,0 +1,213 @@
+use std::{fmt, str::FromStr};
+
+use serde::Serialize;
+
+use crate::error::RrError;
+
+/// A contiguous, inclusive range of hash slots owned by a node.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct SlotRange {
+    pub first: u16,
+    pub last: u16,
+}
+
+impl fmt::Display for SlotRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.first == self.last {
+            write!(f, "{}", self.first)
+        } else {
+            write!(f, "{}-{}", self.first, self.last)
+        }
+    }
+}
+
+/// One line of CLUSTER NODES: a node as seen by the node that answered.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClusterNode {
+    pub id: String,
+    pub address: String,
+    pub port: Option<u16>,
+    pub flags: Vec<String>,
+    pub primary_id: Option<String>,
+    pub config_epoch: u64,
+    pub link_state: String,
+    pub slots: Vec<SlotRange>,
+    /// Slots being moved away, with the ID of the node they go to.
+    pub migrating: Vec<(u16, String)>,
+    /// Slots being moved in, with the ID of the node they come from.
+    pub importing: Vec<(u16, String)>,
+}
+
+impl ClusterNode {
+    /// Whether this is the node that answered CLUSTER NODES.
+    pub fn is_myself(&self) -> bool {
+        self.has_flag("myself")
+    }
+
+    /// Whether the node serves slots rather than replicating another node.
+    pub fn is_primary(&self) -> bool {
+        self.has_flag("master")
+    }
+
+    /// Whether the node has the given flag, e.g. fail or fail?.
+    pub fn has_flag(&self, flag: &str) -> bool {
+        self.flags.iter().any(|f| f == flag)
+    }
+}
+
+fn parse_slot(value: &str, line: &str) -> Result<u16, RrError> {
+    u16::from_str(value).map_err(|err| {
+        RrError::Protocol(
+            format!("Invalid slot {} in CLUSTER NODES line: {}", value, line),
+            Some(Box::new(err)),
+        )
+    })
+}
+
+fn parse_node_line(line: &str) -> Result<ClusterNode, RrError> {
+    let fields = line.split_whitespace().collect::<Vec<&str>>();
+    if fields.len() < 8 {
+        return Err(RrError::Protocol(
+            format!("Malformed CLUSTER NODES line: {}", line),
+            None,
+        ));
+    }
+
+    // ip:port@cport, optionally followed by ,hostname on Redis 7.
+    let address = fields[1].split(',').next().unwrap_or_default();
+    let port = address
+        .split('@')
+        .next()
+        .and_then(|host_port| host_port.rsplit(':').next())
+        .and_then(|port| u16::from_str(port).ok());
+
+    let mut slots = vec![];
+    let mut migrating = vec![];
+    let mut importing = vec![];
+    for slot in fields[8..].iter() {
+        // Slots being migrated or imported are shown as [slot->-id] and
+        // [slot-<-id], separately from the slots the node owns.
+        if let Some(open) = slot.strip_prefix('[').and_then(|s| s.strip_suffix(']')) {
+            if let Some((slot, target)) = open.split_once("->-") {
+                migrating.push((parse_slot(slot, line)?, target.to_string()));
+            } else if let Some((slot, source)) = open.split_once("-<-") {
+                importing.push((parse_slot(slot, line)?, source.to_string()));
+            }
+            continue;
+        }
+        let range = match slot.split_once('-') {
+            Some((first, last)) => SlotRange {
+                first: parse_slot(first, line)?,
+                last: parse_slot(last, line)?,
+            },
+            None => {
+                let single = parse_slot(slot, line)?;
+                SlotRange {
+                    first: single,
+                    last: single,
+                }
+            }
+        };
+        slots.push(range);
+    }
+
+    Ok(ClusterNode {
+        id: fields[0].to_string(),
+        address: address.to_string(),
+        port,
+        flags: fields[2].split(',').map(|f| f.to_string()).collect(),
+        primary_id: match fields[3] {
+            "-" => None,
+            id => Some(id.to_string()),
+        },
+        config_epoch: u64::from_str(fields[6]).unwrap_or_default(),
+        link_state: fields[7].to_string(),
+        slots,
+        migrating,
+        importing,
+    })
+}
+
+/// Parse the output of CLUSTER NODES, one node per line.
+///
+/// # Arguments
+/// * `output` - The text CLUSTER NODES replied with.
+///
+/// # Examples
+/// ```
+/// let nodes = parse_cluster_nodes(&reply.as_string().unwrap_or_default())?;
+/// let myself = nodes.iter().find(|node| node.is_myself());
+/// ```
+pub fn parse_cluster_nodes(output: &str) -> Result<Vec<ClusterNode>, RrError> {
+    output
+        .lines()
+        .map(|line| line.trim())
+        .filter(|line| !line.is_empty())
+        .map(parse_node_line)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    const SAMPLE_NODES: &str = "\
+07c37dfeb235213a872192d90877d0cd55635b91 127.0.0.1:7004@17004 slave e7d1eecce10fd6bb5eb35b9f99a514335d9ba9ca 0 1426238317239 4 connected
+67ed2db8d677e59ec4a4cefb06858cf2a1a89fa1 127.0.0.1:7002@17002,node-2 master - 0 1426238316232 2 connected 5461-10922
+e7d1eecce10fd6bb5eb35b9f99a514335d9ba9ca 127.0.0.1:7000@17000 myself,master - 0 0 1 connected 0-5460 [5461->-67ed2db8d677e59ec4a4cefb06858cf2a1a89fa1]
+6ec23923021cf3ffec47632106199cb7f496ce01 127.0.0.1:7005@17005 slave,fail 67ed2db8d677e59ec4a4cefb06858cf2a1a89fa1 0 1426238316232 5 disconnected
+824fe116063bc5fcf9f4ffd895bc17aee7731ac3 127.0.0.1:7006@17006 master - 0 1426238317741 3 connected 10923 10924-16383
+";
+
+    #[test]
+    fn test_parse_cluster_nodes() {
+        let nodes = parse_cluster_nodes(SAMPLE_NODES).unwrap();
+        assert_eq!(nodes.len(), 5);
+
+        let myself = nodes.iter().find(|node| node.is_myself()).unwrap();
+        assert_eq!(myself.port, Some(7000));
+        assert!(myself.is_primary());
+        assert_eq!(
+            myself.slots,
+            vec![SlotRange {
+                first: 0,
+                last: 5460
+            }]
+        );
+        assert_eq!(
+            myself.migrating,
+            vec![(5461, "67ed2db8d677e59ec4a4cefb06858cf2a1a89fa1".to_string())]
+        );
+        assert!(myself.importing.is_empty());
+
+        assert_eq!(nodes[1].address, "127.0.0.1:7002@17002");
+        assert_eq!(nodes[1].port, Some(7002));
+        assert_eq!(
+            nodes[0].primary_id.as_deref(),
+            Some("e7d1eecce10fd6bb5eb35b9f99a514335d9ba9ca")
+        );
+        assert_eq!(nodes[3].flags, vec!["slave", "fail"]);
+        assert_eq!(nodes[3].link_state, "disconnected");
+    }
+
+    #[test]
+    fn test_parse_cluster_nodes_single_slots() {
+        let nodes = parse_cluster_nodes(SAMPLE_NODES).unwrap();
+        let ranges = nodes[4]
+            .slots
+            .iter()
+            .map(|range| range.to_string())
+            .collect::<Vec<String>>();
+        assert_eq!(ranges, vec!["10923", "10924-16383"]);
+    }
+
+    #[test]
+    fn test_parse_cluster_nodes_malformed() {
+        assert!(parse_cluster_nodes("abc 127.0.0.1:7000@17000 master").is_err());
+        assert!(
+            parse_cluster_nodes("abc 127.0.0.1:7000@17000 master - 0 0 1 connected 0-99999")
+                .is_err()
+        );
+    }
+}
@@ -0,0 +1,547 @@
+use std::{
+    collections::BTreeMap,
+    error::Error,
+    fmt, fs,
+    path::{Path, PathBuf},
+};
+
+/// A single directive read from a config file, along with where it
+/// came from so problems can be traced back to the source line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfDirective {
+    pub name: String,
+    pub args: Vec<String>,
+    pub file: PathBuf,
+    pub line: usize,
+}
+
+impl ConfDirective {
+    /// The first argument of the directive, which is the whole value
+    /// for the single valued directives most files are made of.
+    pub fn value(&self) -> Option<&str> {
+        self.args.first().map(|arg| arg.as_str())
+    }
+}
+
+/// A parse failure, pointing at the file and line that caused it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfError {
+    pub file: PathBuf,
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ConfError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.line > 0 {
+            write!(f, "{}:{}: {}", self.file.display(), self.line, self.message)
+        } else {
+            write!(f, "{}: {}", self.file.display(), self.message)
+        }
+    }
+}
+
+impl Error for ConfError {}
+
+/// Every directive of a config file, in the order they appear.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RedisConf {
+    pub directives: Vec<ConfDirective>,
+}
+
+impl RedisConf {
+    /// Get the directive that takes effect for the given name. Like
+    /// Redis itself, the last occurrence wins.
+    ///
+    /// # Arguments
+    /// * `name` - Directive name, matched case-insensitively.
+    ///
+    /// # Examples
+    /// ```
+    /// let port = conf.get("port").expect("No port configured.");
+    /// ```
+    pub fn get(&self, name: &str) -> Option<&ConfDirective> {
+        self.directives
+            .iter()
+            .rev()
+            .find(|directive| directive.name.eq_ignore_ascii_case(name))
+    }
+
+    /// Get every occurrence of a directive, for the ones Redis lets
+    /// repeat such as `save` or `rename-command`.
+    ///
+    /// # Arguments
+    /// * `name` - Directive name, matched case-insensitively.
+    ///
+    /// # Examples
+    /// ```
+    /// let save_points = conf.get_all("save");
+    /// ```
+    pub fn get_all(&self, name: &str) -> Vec<&ConfDirective> {
+        self.directives
+            .iter()
+            .filter(|directive| directive.name.eq_ignore_ascii_case(name))
+            .collect()
+    }
+
+    /// Get the value of the directive that takes effect.
+    ///
+    /// # Arguments
+    /// * `name` - Directive name, matched case-insensitively.
+    ///
+    /// # Examples
+    /// ```
+    /// let port = conf.value("port").unwrap_or("6379");
+    /// ```
+    pub fn value(&self, name: &str) -> Option<&str> {
+        self.get(name).and_then(|directive| directive.value())
+    }
+
+    /// Get the directive in effect for every name set in the config,
+    /// applying the same last-wins rule as Redis.
+    ///
+    /// # Examples
+    /// ```
+    /// for (name, directive) in conf.effective() {
+    ///     println!("{} {:?}", name, directive.args);
+    /// }
+    /// ```
+    pub fn effective(&self) -> BTreeMap<String, &ConfDirective> {
+        self.directives
+            .iter()
+            .map(|directive| (directive.name.clone(), directive))
+            .collect()
+    }
+}
+
+fn hex_digit(c: u8) -> Option<u8> {
+    (c as char).to_digit(16).map(|digit| digit as u8)
+}
+
+/// Split a config line into arguments following the rules Redis uses
+/// for its own config files: double quotes support \n, \r, \t, \b, \a
+/// and \xHH escapes, single quotes only \', and a closing quote must be
+/// followed by whitespace or the end of the line.
+///
+/// # Arguments
+/// * `line` - The line to split, without its line terminator.
+///
+/// # Examples
+/// ```
+/// let args = split_args(r#"rename-command "CONFIG" """#).expect("Unbalanced quotes.");
+/// assert_eq!(args, vec!["rename-command", "CONFIG", ""]);
+/// ```
+pub fn split_args(line: &str) -> Result<Vec<String>, String> {
+    let bytes = line.as_bytes();
+    let mut args = vec![];
+    let mut pos = 0;
+
+    loop {
+        while pos < bytes.len() && bytes[pos].is_ascii_whitespace() {
+            pos += 1;
+        }
+        if pos >= bytes.len() {
+            return Ok(args);
+        }
+
+        let mut current: Vec<u8> = vec![];
+        let mut in_double = false;
+        let mut in_single = false;
+        loop {
+            let c = bytes.get(pos).copied();
+            if in_double {
+                match c {
+                    None => return Err("Unbalanced quotes in configuration line.".to_string()),
+                    Some(b'\\') => {
+                        let next = bytes.get(pos + 1).copied();
+                        let hex = (
+                            bytes.get(pos + 2).copied().and_then(hex_digit),
+                            bytes.get(pos + 3).copied().and_then(hex_digit),
+                        );
+                        match (next, hex) {
+                            (Some(b'x'), (Some(high), Some(low))) => {
+                                current.push(high * 16 + low);
+                                pos += 3;
+                            }
+                            (Some(escaped), _) => {
+                                current.push(match escaped {
+                                    b'n' => b'\n',
+                                    b'r' => b'\r',
+                                    b't' => b'\t',
+                                    b'b' => 0x08,
+                                    b'a' => 0x07,
+                                    other => other,
+                                });
+                                pos += 1;
+                            }
+                            (None, _) => {
+                                return Err("Unbalanced quotes in configuration line.".to_string())
+                            }
+                        }
+                    }
+                    Some(b'"') => {
+                        if bytes
+                            .get(pos + 1)
+                            .is_some_and(|next| !next.is_ascii_whitespace())
+                        {
+                            return Err(
+                                "Closing quote must be followed by a space or nothing at all."
+                                    .to_string(),
+                            );
+                        }
+                        pos += 1;
+                        break;
+                    }
+                    Some(other) => current.push(other),
+                }
+            } else if in_single {
+                match c {
+                    None => return Err("Unbalanced quotes in configuration line.".to_string()),
+                    Some(b'\\') if bytes.get(pos + 1) == Some(&b'\'') => {
+                        current.push(b'\'');
+                        pos += 1;
+                    }
+                    Some(b'\'') => {
+                        if bytes
+                            .get(pos + 1)
+                            .is_some_and(|next| !next.is_ascii_whitespace())
+                        {
+                            return Err(
+                                "Closing quote must be followed by a space or nothing at all."
+                                    .to_string(),
+                            );
+                        }
+                        pos += 1;
+                        break;
+                    }
+                    Some(other) => current.push(other),
+                }
+            } else {
+                match c {
+                    None => break,
+                    Some(space) if space.is_ascii_whitespace() => break,
+                    Some(b'"') => in_double = true,
+                    Some(b'\'') => in_single = true,
+                    Some(other) => current.push(other),
+                }
+            }
+            pos += 1;
+        }
+        args.push(String::from_utf8_lossy(&current).to_string());
+    }
+}
+
+/// Parse the text of a config file. Blank lines and lines starting
+/// with # are skipped, directive names are lowercased and every other
+/// directive is kept in order, repeats included.
+///
+/// # Arguments
+/// * `content` - Text of the config file.
+/// * `file` - Path the text was read from, used in errors.
+///
+/// # Examples
+/// ```
+/// let conf = parse_conf_str("port 7000\nsave 900 1\n", Path::new("node.conf")).expect("Failed to parse.");
+/// ```
+pub fn parse_conf_str(content: &str, file: &Path) -> Result<RedisConf, ConfError> {
+    let mut conf = RedisConf::default();
+    for (index, raw_line) in content.lines().enumerate() {
+        let line = raw_line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let mut args = split_args(line).map_err(|message| ConfError {
+            file: file.to_path_buf(),
+            line: index + 1,
+            message,
+        })?;
+        if args.is_empty() {
+            continue;
+        }
+        let name = args.remove(0).to_lowercase();
+        conf.directives.push(ConfDirective {
+            name,
+            args,
+            file: file.to_path_buf(),
+            line: index + 1,
+        });
+    }
+    Ok(conf)
+}
+
+/// Read and parse the config file at the given path.
+///
+/// # Arguments
+/// * `file` - Path of the config file.
+///
+/// # Examples
+/// ```
+/// let conf = parse_conf_file(Path::new("/usr/local/etc/redis/cluster/7000.conf")).expect("Failed to parse.");
+/// ```
+pub fn parse_conf_file(file: &Path) -> Result<RedisConf, ConfError> {
+    match fs::read(file) {
+        Ok(bytes) => parse_conf_str(&String::from_utf8_lossy(&bytes), file),
+        Err(err) => Err(ConfError {
+            file: file.to_path_buf(),
+            line: 0,
+            message: err.to_string(),
+        }),
+    }
+}
+
+fn expand_include(pattern: &str, directive: &ConfDirective) -> Result<Vec<PathBuf>, ConfError> {
+    let include_error = |message: String| ConfError {
+        file: directive.file.clone(),
+        line: directive.line,
+        message,
+    };
+
+    // Redis only treats an include as a pattern when it holds a `*`,
+    // and a pattern that matches nothing is not an error.
+    if !pattern.contains('*') {
+        return Ok(vec![PathBuf::from(pattern)]);
+    }
+    match glob::glob(pattern) {
+        Ok(paths) => {
+            let mut matched = paths
+                .collect::<Result<Vec<PathBuf>, glob::GlobError>>()
+                .map_err(|err| include_error(format!("Failed to expand {}: {}", pattern, err)))?;
+            matched.sort();
+            Ok(matched)
+        }
+        Err(err) => Err(include_error(format!(
+            "Invalid include pattern {}: {}",
+            pattern, err
+        ))),
+    }
+}
+
+fn load_with_includes(file: &Path, stack: &mut Vec<PathBuf>) -> Result<RedisConf, ConfError> {
+    let canonical = fs::canonicalize(file).map_err(|err| ConfError {
+        file: file.to_path_buf(),
+        line: 0,
+        message: err.to_string(),
+    })?;
+    if let Some(start) = stack.iter().position(|seen| *seen == canonical) {
+        let chain = stack[start..]
+            .iter()
+            .chain(std::iter::once(&canonical))
+            .map(|path| path.display().to_string())
+            .collect::<Vec<String>>();
+        return Err(ConfError {
+            file: file.to_path_buf(),
+            line: 0,
+            message: format!("Include cycle: {}", chain.join(" -> ")),
+        });
+    }
+
+    stack.push(canonical);
+    let mut merged = RedisConf::default();
+    for directive in parse_conf_file(file)?.directives.into_iter() {
+        if directive.name != "include" {
+            merged.directives.push(directive);
+            continue;
+        }
+
+        if directive.args.len() != 1 {
+            return Err(ConfError {
+                file: directive.file.clone(),
+                line: directive.line,
+                message: "include takes exactly one path.".to_string(),
+            });
+        }
+        for included in expand_include(&directive.args[0], &directive)?.iter() {
+            let included_conf = load_with_includes(included, stack).map_err(|err| {
+                if err.line == 0
+                    && err.file == *included
+                    && !err.message.starts_with("Include cycle")
+                {
+                    ConfError {
+                        file: directive.file.clone(),
+                        line: directive.line,
+                        message: format!(
+                            "Failed to include {}: {}",
+                            included.display(),
+                            err.message
+                        ),
+                    }
+                } else {
+                    err
+                }
+            })?;
+            merged.directives.extend(included_conf.directives);
+        }
+    }
+    stack.pop();
+    Ok(merged)
+}
+
+/// Read a config file and splice the contents of every file it pulls in
+/// with `include` into the place of the include, recursively. Include
+/// paths may hold `*` wildcards and are resolved the way redis-server
+/// resolves them, relative to the working directory. Cycles are errors.
+///
+/// # Arguments
+/// * `file` - Path of the top level config file.
+///
+/// # Examples
+/// ```
+/// let conf = load_conf_file(Path::new("/usr/local/etc/redis/cluster/7000.conf")).expect("Failed to load.");
+/// let port = conf.value("port");
+/// ```
+pub fn load_conf_file(file: &Path) -> Result<RedisConf, ConfError> {
+    load_with_includes(file, &mut vec![])
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    // Write the given files into a fresh directory, returning its path.
+    fn write_test_files(dir: &str, files: &[(&str, String)]) -> PathBuf {
+        let dir_path = PathBuf::from(dir);
+        let _ = fs::remove_dir_all(&dir_path);
+        fs::create_dir_all(&dir_path).expect("Failed to create test directory.");
+        for (name, content) in files.iter() {
+            fs::write(dir_path.join(name), content).expect("Failed to write test file.");
+        }
+        dir_path
+    }
+
+    #[test]
+    fn test_split_args_plain() {
+        let args = split_args("  save   900 1 ").unwrap();
+        assert_eq!(args, vec!["save", "900", "1"]);
+    }
+
+    #[test]
+    fn test_split_args_quotes_and_escapes() {
+        let args = split_args(r#"requirepass "a b\"c\x41\n" 'it\'s' """#).unwrap();
+        assert_eq!(args, vec!["requirepass", "a b\"cA\n", "it's", ""]);
+    }
+
+    #[test]
+    fn test_split_args_unbalanced() {
+        assert!(split_args(r#"dir "/tmp/data"#).is_err());
+        assert!(split_args("dir '/tmp/data").is_err());
+        assert!(split_args(r#"dir "/tmp"data"#).is_err());
+    }
+
+    #[test]
+    fn test_parse_conf_str_directives() {
+        let content = "# comment\n\nPort 7000\nsave 900 1\nsave 300 10\n  # indented comment\nport 7001\nrename-command CONFIG \"\"\n";
+        let conf = parse_conf_str(content, Path::new("node.conf")).unwrap();
+
+        assert_eq!(conf.directives.len(), 5);
+        assert_eq!(conf.value("PORT"), Some("7001"));
+        assert_eq!(conf.get("port").unwrap().line, 7);
+        assert_eq!(conf.get_all("save").len(), 2);
+        assert_eq!(conf.get_all("save")[1].args, vec!["300", "10"]);
+        assert_eq!(conf.get("rename-command").unwrap().args, vec!["CONFIG", ""]);
+        assert_eq!(conf.value("missing"), None);
+    }
+
+    #[test]
+    fn test_parse_conf_str_error_line() {
+        let content = "port 7000\ndir \"/tmp\n";
+        let err = parse_conf_str(content, Path::new("node.conf")).unwrap_err();
+        assert_eq!(err.line, 2);
+        assert!(err.to_string().starts_with("node.conf:2:"));
+    }
+
+    #[test]
+    fn test_parse_conf_file_missing() {
+        let err = parse_conf_file(Path::new("./does-not-exist")).unwrap_err();
+        assert_eq!(err.line, 0);
+    }
+
+    #[test]
+    fn test_load_conf_file_includes_last_wins() {
+        let dir = "./test_include_last_wins";
+        let dir_path = write_test_files(
+            dir,
+            &[
+                (
+                    "common.conf",
+                    "cluster-enabled yes\nport 6379\nappendonly no\n".to_string(),
+                ),
+                (
+                    "node.conf",
+                    format!("appendonly yes\ninclude {}/common.conf\nport 7000\n", dir),
+                ),
+            ],
+        );
+
+        let conf = load_conf_file(&dir_path.join("node.conf")).unwrap();
+        assert_eq!(conf.value("port"), Some("7000"));
+        assert_eq!(conf.value("cluster-enabled"), Some("yes"));
+        assert_eq!(conf.value("appendonly"), Some("no"));
+        assert!(conf.get("include").is_none());
+        assert_eq!(
+            conf.get("cluster-enabled").unwrap().file,
+            dir_path.join("common.conf")
+        );
+        assert_eq!(conf.effective().len(), 3);
+
+        fs::remove_dir_all(&dir_path).expect("Failed to remove test directory.");
+    }
+
+    #[test]
+    fn test_load_conf_file_glob_include() {
+        let dir = "./test_include_glob";
+        let dir_path = write_test_files(
+            dir,
+            &[
+                ("b.inc", "maxmemory 2mb\n".to_string()),
+                ("a.inc", "maxmemory 1mb\nappendonly yes\n".to_string()),
+                (
+                    "node.conf",
+                    format!("include {}/*.inc\ninclude {}/none-*.inc\n", dir, dir),
+                ),
+            ],
+        );
+
+        let conf = load_conf_file(&dir_path.join("node.conf")).unwrap();
+        assert_eq!(conf.value("maxmemory"), Some("2mb"));
+        assert_eq!(conf.value("appendonly"), Some("yes"));
+
+        fs::remove_dir_all(&dir_path).expect("Failed to remove test directory.");
+    }
+
+    #[test]
+    fn test_load_conf_file_include_cycle() {
+        let dir = "./test_include_cycle";
+        let dir_path = write_test_files(
+            dir,
+            &[
+                ("a.conf", format!("include {}/b.conf\n", dir)),
+                ("b.conf", format!("port 7000\ninclude {}/a.conf\n", dir)),
+            ],
+        );
+
+        let err = load_conf_file(&dir_path.join("a.conf")).unwrap_err();
+        assert!(err.message.starts_with("Include cycle"));
+
+        fs::remove_dir_all(&dir_path).expect("Failed to remove test directory.");
+    }
+
+    #[test]
+    fn test_load_conf_file_missing_include() {
+        let dir = "./test_include_missing";
+        let dir_path = write_test_files(
+            dir,
+            &[(
+                "node.conf",
+                format!("port 7000\ninclude {}/gone.conf\n", dir),
+            )],
+        );
+
+        let err = load_conf_file(&dir_path.join("node.conf")).unwrap_err();
+        assert_eq!(err.file, dir_path.join("node.conf"));
+        assert_eq!(err.line, 2);
+
+        fs::remove_dir_all(&dir_path).expect("Failed to remove test directory.");
+    }
+}
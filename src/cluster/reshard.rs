@@ -0,0 +1,440 @@
+use std::collections::{BTreeMap, HashMap};
+
+use serde::Serialize;
+
+use crate::{
+    cluster::{
+        fix::{plan_fix, FixAction},
+        health::{collapse_slots, inspect_cluster, myself},
+        migrate::migrate_slot,
+        nodes::SlotRange,
+        status::NodeView,
+    },
+    error::RrError,
+    resp::client::connect_node,
+};
+
+/// The slots each primary serves, by port.
+pub type PrimarySlots = BTreeMap<u16, Vec<u16>>;
+
+// How many slots to move between two progress lines.
+const PROGRESS_INTERVAL: usize = 100;
+
+/// Slots to move from one primary to another, by port.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SlotMove {
+    pub from: u16,
+    pub to: u16,
+    pub slots: Vec<u16>,
+}
+
+/// Slots moved from one primary to another, as reported.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct MovedSlots {
+    pub from: u16,
+    pub to: u16,
+    pub slots: Vec<SlotRange>,
+}
+
+/// What a reshard or rebalance did. Resumed slots are the moves an
+/// earlier, interrupted run left open, which were finished first.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ReshardReport {
+    pub resumed: usize,
+    pub slots_moved: usize,
+    pub keys_moved: usize,
+    pub moves: Vec<MovedSlots>,
+}
+
+/// The slots every working primary serves by its own account, by port.
+fn primary_slots(views: &[(u16, NodeView)]) -> PrimarySlots {
+    views
+        .iter()
+        .filter_map(|(port, view)| myself(view).map(|node| (*port, node)))
+        .filter(|(_, node)| node.is_primary() && !node.has_flag("fail"))
+        .map(|(port, node)| {
+            let slots = node
+                .slots
+                .iter()
+                .flat_map(|range| range.first..=range.last)
+                .collect::<Vec<u16>>();
+            (port, slots)
+        })
+        .collect()
+}
+
+/// Find the moves an interrupted run left open, which can be finished
+/// where they stopped. Anything else fix would repair has to be repaired
+/// before slots are moved around.
+fn resumable_moves(views: &[(u16, NodeView)]) -> Result<Vec<SlotMove>, RrError> {
+    plan_fix(views)?
+        .into_iter()
+        .map(|action| match action {
+            FixAction::CompleteMigration {
+                slot,
+                source,
+                target,
+            } => Ok(SlotMove {
+                from: source,
+                to: target,
+                slots: vec![slot],
+            }),
+            other => Err(RrError::ClusterHealth(
+                format!(
+                    "The cluster needs repairs before slots can be moved ({}). Run rr cluster fix first.",
+                    other
+                ),
+                None,
+            )),
+        })
+        .collect()
+}
+
+/// Apply moves to the slots served by each primary.
+fn apply_moves(primaries: &mut PrimarySlots, moves: &[SlotMove]) {
+    for slot_move in moves.iter() {
+        if let Some(slots) = primaries.get_mut(&slot_move.from) {
+            slots.retain(|slot| !slot_move.slots.contains(slot));
+        }
+        if let Some(slots) = primaries.get_mut(&slot_move.to) {
+            slots.extend(slot_move.slots.iter());
+            slots.sort_unstable();
+        }
+    }
+}
+
+/// Plan moving a number of slots from one primary to another, taking the
+/// source's lowest slots first.
+///
+/// # Arguments
+/// * `primaries` - Slots served by each primary, by port.
+/// * `from` - Port of the primary giving up slots.
+/// * `to` - Port of the primary taking the slots.
+/// * `count` - Number of slots to move.
+///
+/// # Examples
+/// ```
+/// let moves = plan_reshard(&primaries, 7000, 7003, 1000).expect("Failed to plan reshard.");
+/// ```
+pub fn plan_reshard(
+    primaries: &PrimarySlots,
+    from: u16,
+    to: u16,
+    count: usize,
+) -> Result<Vec<SlotMove>, RrError> {
+    if from == to {
+        return Err(RrError::Config(
+            "The source and target of a reshard must differ.".to_string(),
+            None,
+        ));
+    }
+    if !primaries.contains_key(&to) {
+        return Err(RrError::Config(
+            format!("No working primary is running on port {}.", to),
+            None,
+        ));
+    }
+    let slots = match primaries.get(&from) {
+        Some(slots) => slots,
+        None => {
+            return Err(RrError::Config(
+                format!("No working primary is running on port {}.", from),
+                None,
+            ))
+        }
+    };
+    if count > slots.len() {
+        return Err(RrError::Config(
+            format!(
+                "The primary on port {} only serves {} slots.",
+                from,
+                slots.len()
+            ),
+            None,
+        ));
+    }
+
+    Ok(match count {
+        0 => vec![],
+        _ => vec![SlotMove {
+            from,
+            to,
+            slots: slots[..count].to_vec(),
+        }],
+    })
+}
+
+/// Plan moves that leave every primary serving its share of the slots,
+/// in proportion to its weight. Primaries without a weight weigh 1, and
+/// a weight of 0 moves all slots off a primary. Slots that do not divide
+/// evenly stay with the primaries already serving the most, so a balanced
+/// cluster needs no moves.
+///
+/// # Arguments
+/// * `primaries` - Slots served by each primary, by port.
+/// * `weights` - Weight of the primaries whose share should differ.
+///
+/// # Examples
+/// ```
+/// let moves = plan_rebalance(&primaries, &HashMap::from([(7000, 2.0)])).expect("Failed to plan rebalance.");
+/// ```
+pub fn plan_rebalance(
+    primaries: &PrimarySlots,
+    weights: &HashMap<u16, f64>,
+) -> Result<Vec<SlotMove>, RrError> {
+    if let Some(port) = weights.keys().find(|port| !primaries.contains_key(port)) {
+        return Err(RrError::Config(
+            format!("No working primary is running on port {}.", port),
+            None,
+        ));
+    }
+    let weight_of = |port: &u16| weights.get(port).copied().unwrap_or(1.0);
+    let total_weight = primaries.keys().map(weight_of).sum::<f64>();
+    if total_weight <= 0.0 {
+        return Err(RrError::Config(
+            "At least one primary needs a weight above 0.".to_string(),
+            None,
+        ));
+    }
+
+    let total = primaries.values().map(|slots| slots.len()).sum::<usize>();
+    let mut targets = primaries
+        .keys()
+        .map(|port| {
+            let share = total as f64 * weight_of(port) / total_weight;
+            (*port, share.floor() as usize)
+        })
+        .collect::<BTreeMap<u16, usize>>();
+    let mut by_load = primaries
+        .iter()
+        .filter(|(port, _)| weight_of(port) > 0.0)
+        .map(|(port, slots)| (slots.len(), *port))
+        .collect::<Vec<(usize, u16)>>();
+    by_load.sort_unstable_by(|a, b| b.cmp(a));
+    let remainder = total - targets.values().sum::<usize>();
+    for (_, port) in by_load.iter().cycle().take(remainder) {
+        *targets.entry(*port).or_default() += 1;
+    }
+
+    // Donors give up their highest slots, receivers take them in port
+    // order until each reaches its target.
+    let mut surplus = primaries
+        .iter()
+        .filter(|(port, slots)| slots.len() > targets[port])
+        .map(|(port, slots)| (*port, slots[targets[port]..].to_vec()))
+        .collect::<Vec<(u16, Vec<u16>)>>();
+    let mut moves: Vec<SlotMove> = vec![];
+    for (port, slots) in primaries.iter() {
+        let mut deficit = targets[port].saturating_sub(slots.len());
+        while deficit > 0 {
+            let (from, spare) = match surplus.iter_mut().find(|(_, spare)| !spare.is_empty()) {
+                Some((from, spare)) => (*from, spare),
+                None => break,
+            };
+            let taken = spare.len().min(deficit);
+            let slots = spare.drain(..taken).collect::<Vec<u16>>();
+            deficit -= taken;
+            moves.push(SlotMove {
+                from,
+                to: *port,
+                slots,
+            });
+        }
+    }
+    Ok(moves)
+}
+
+/// Move the planned slots one by one, logging progress as it goes.
+/// Moves resumed from an interrupted run overwrite keys the target
+/// already received. Returns the number of keys moved.
+fn run_moves(
+    cluster_host: &str,
+    moves: &[SlotMove],
+    batch_size: usize,
+    resuming: bool,
+) -> Result<usize, RrError> {
+    let total = moves.iter().map(|m| m.slots.len()).sum::<usize>();
+    let mut done = 0;
+    let mut keys_moved = 0;
+    for slot_move in moves.iter() {
+        let mut source = connect_node(cluster_host, slot_move.from)?;
+        let mut target = connect_node(cluster_host, slot_move.to)?;
+        for slot in slot_move.slots.iter() {
+            keys_moved += migrate_slot(
+                &mut source,
+                &mut target,
+                cluster_host,
+                slot_move.to,
+                *slot,
+                batch_size,
+                resuming,
+            )?;
+            done += 1;
+            if done % PROGRESS_INTERVAL == 0 || done == total {
+                info!(
+                    "Moved {}/{} slots and {} keys so far.",
+                    done, total, keys_moved
+                );
+            }
+        }
+    }
+    Ok(keys_moved)
+}
+
+fn run_plan(
+    cluster_host: &str,
+    resumed: Vec<SlotMove>,
+    moves: Vec<SlotMove>,
+    batch_size: usize,
+) -> Result<ReshardReport, RrError> {
+    if !resumed.is_empty() {
+        info!(
+            "Finishing {} slot moves left open by an interrupted run.",
+            resumed.len()
+        );
+    }
+    let mut keys_moved = run_moves(cluster_host, &resumed, batch_size, true)?;
+    keys_moved += run_moves(cluster_host, &moves, batch_size, false)?;
+    Ok(ReshardReport {
+        resumed: resumed.len(),
+        slots_moved: moves.iter().map(|m| m.slots.len()).sum(),
+        keys_moved,
+        moves: moves
+            .iter()
+            .map(|m| MovedSlots {
+                from: m.from,
+                to: m.to,
+                slots: collapse_slots(&m.slots),
+            })
+            .collect(),
+    })
+}
+
+fn inspect_primaries(cluster_host: &String) -> Result<(PrimarySlots, Vec<SlotMove>), RrError> {
+    let (_, views) = inspect_cluster(cluster_host)?;
+    let resumed = resumable_moves(&views)?;
+    let mut primaries = primary_slots(&views);
+    apply_moves(&mut primaries, &resumed);
+    Ok((primaries, resumed))
+}
+
+/// Move a number of slots, with their keys, from one recorded primary to
+/// another. Moves an interrupted run left open are finished first, and
+/// those going the same way count towards the number of slots, so running
+/// the same reshard again picks up where it stopped.
+///
+/// # Arguments
+/// * `cluster_host` - Host the nodes are listening on.
+/// * `from` - Port of the primary giving up slots.
+/// * `to` - Port of the primary taking the slots.
+/// * `count` - Number of slots to move.
+/// * `batch_size` - Number of keys moved with each MIGRATE.
+///
+/// # Examples
+/// ```
+/// let sample_host = "127.0.0.1".to_string();
+/// let report = reshard_cluster(&sample_host, 7000, 7003, 1000, MIGRATE_BATCH_SIZE).expect("Failed to reshard.");
+/// ```
+pub fn reshard_cluster(
+    cluster_host: &String,
+    from: u16,
+    to: u16,
+    count: usize,
+    batch_size: usize,
+) -> Result<ReshardReport, RrError> {
+    let (primaries, resumed) = inspect_primaries(cluster_host)?;
+    let already_moved = resumed
+        .iter()
+        .filter(|m| m.from == from && m.to == to)
+        .count();
+    let moves = plan_reshard(&primaries, from, to, count.saturating_sub(already_moved))?;
+    run_plan(cluster_host, resumed, moves, batch_size)
+}
+
+/// Move slots between the recorded primaries until each serves its share,
+/// in proportion to its weight. Moves an interrupted run left open are
+/// finished first, so running the rebalance again picks up where it
+/// stopped.
+///
+/// # Arguments
+/// * `cluster_host` - Host the nodes are listening on.
+/// * `weights` - Weight of the primaries whose share should differ.
+/// * `batch_size` - Number of keys moved with each MIGRATE.
+///
+/// # Examples
+/// ```
+/// let sample_host = "127.0.0.1".to_string();
+/// let report = rebalance_cluster(&sample_host, &HashMap::new(), MIGRATE_BATCH_SIZE).expect("Failed to rebalance.");
+/// ```
+pub fn rebalance_cluster(
+    cluster_host: &String,
+    weights: &HashMap<u16, f64>,
+    batch_size: usize,
+) -> Result<ReshardReport, RrError> {
+    let (primaries, resumed) = inspect_primaries(cluster_host)?;
+    let moves = plan_rebalance(&primaries, weights)?;
+    run_plan(cluster_host, resumed, moves, batch_size)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn primaries(counts: &[(u16, usize)]) -> PrimarySlots {
+        let mut next = 0;
+        counts
+            .iter()
+            .map(|(port, count)| {
+                let slots = (next..next + *count as u16).collect::<Vec<u16>>();
+                next += *count as u16;
+                (*port, slots)
+            })
+            .collect()
+    }
+
+    fn counts_after(primaries: &PrimarySlots, moves: &[SlotMove]) -> Vec<usize> {
+        let mut primaries = primaries.clone();
+        apply_moves(&mut primaries, moves);
+        primaries.values().map(|slots| slots.len()).collect()
+    }
+
+    #[test]
+    fn test_plan_reshard() {
+        let nodes = primaries(&[(7000, 10), (7001, 10)]);
+        let moves = plan_reshard(&nodes, 7000, 7001, 3).unwrap();
+        assert_eq!(
+            moves,
+            vec![SlotMove {
+                from: 7000,
+                to: 7001,
+                slots: vec![0, 1, 2]
+            }]
+        );
+        assert!(plan_reshard(&nodes, 7000, 7001, 11).is_err());
+        assert!(plan_reshard(&nodes, 7000, 7005, 1).is_err());
+    }
+
+    #[test]
+    fn test_plan_rebalance_balanced() {
+        let nodes = primaries(&[(7000, 5461), (7001, 5461), (7002, 5462)]);
+        assert!(plan_rebalance(&nodes, &HashMap::new()).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_plan_rebalance_new_primary() {
+        let nodes = primaries(&[(7000, 5461), (7001, 5461), (7002, 5462), (7003, 0)]);
+        let moves = plan_rebalance(&nodes, &HashMap::new()).unwrap();
+        assert_eq!(counts_after(&nodes, &moves), vec![4096, 4096, 4096, 4096]);
+        assert!(moves.iter().all(|m| m.to == 7003));
+    }
+
+    #[test]
+    fn test_plan_rebalance_weights() {
+        let nodes = primaries(&[(7000, 6), (7001, 6), (7002, 6)]);
+        let weights = HashMap::from([(7000, 2.0), (7002, 0.0)]);
+        let moves = plan_rebalance(&nodes, &weights).unwrap();
+        assert_eq!(counts_after(&nodes, &moves), vec![12, 6, 0]);
+        assert!(plan_rebalance(&nodes, &HashMap::from([(7005, 1.0)])).is_err());
+    }
+}
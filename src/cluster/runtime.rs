@@ -1,269 +1,911 @@
 use std::{
-    borrow::BorrowMut,
-    collections::HashSet,
-    path::PathBuf,
-    process::{Command, Stdio},
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+    process::{Child, Command},
+    str::FromStr,
+    thread,
+    time::{Duration, Instant},
 };
 
 use mocktopus::macros::mockable;
 
 use crate::{
-    cluster::config::read_conf_file,
-    local::pid::{get_currently_running_pids, write_data_to_pid_file, PIDEntry},
+    cluster::{
+        config::read_conf_file,
+        create::{create_cluster, plan_replicas},
+        migrate::node_id,
+    },
+    error::RrError,
+    local::{
+        cluster::current_cluster,
+        logs::{node_log_path, open_node_log, tail_lines, STARTUP_LOG_LINES},
+        pid::{get_currently_running_pids, write_data_to_pid_file, NodeRole, PIDEntry},
+        process::{is_process_alive, process_info, signal_process, wait_for_exit},
+        state::check_ports_unclaimed,
+    },
+    resp::client::{connect_node, connect_node_with_password},
 };
 
-#[inline]
-fn spawn_server_process(conf_file: String) -> Result<u32, ()> {
-    match Command::new("redis-server")
-        .arg(conf_file)
-        .stdout(Stdio::null())
-        .stderr(Stdio::null())
+// The redis-server executable run when no other is configured,
+// looked up on the PATH.
+pub const DEFAULT_REDIS_SERVER: &str = "redis-server";
+
+// How long freshly spawned servers get to accept connections and
+// answer PING, unless told otherwise.
+pub const READY_TIMEOUT: Duration = Duration::from_secs(30);
+
+// Delay between checks on a freshly spawned server.
+const READY_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Start redis-server with a configuration file, appending its stdout and
+/// stderr to the node's log file. Any `logfile` the configuration sets is
+/// overridden so the server logs to stdout, keeping startup failures in
+/// the captured log. Returns the child process and the path of its log.
+///
+/// # Arguments
+/// * `binary` - The redis-server executable to run.
+/// * `conf_file` - Configuration file to start the server with.
+/// * `port` - Port the server listens on, which names its log file.
+///
+/// # Examples
+/// ```
+/// let (child, log_path) = spawn_server_process(Path::new(DEFAULT_REDIS_SERVER), "7000.conf".to_string(), 7000).expect("Failed to spawn server.");
+/// ```
+#[mockable]
+pub fn spawn_server_process(
+    binary: &Path,
+    conf_file: String,
+    port: u16,
+) -> Result<(Child, PathBuf), RrError> {
+    let log_path = node_log_path(&current_cluster(), port)?;
+    let stdout = open_node_log(&log_path)?;
+    let stderr = stdout.try_clone().map_err(|err| {
+        RrError::State(
+            format!("Failed to open log file {}.", log_path.display()),
+            Some(Box::new(err)),
+        )
+    })?;
+    match Command::new(binary)
+        .arg(&conf_file)
+        .arg("--logfile")
+        .arg("")
+        .stdout(stdout)
+        .stderr(stderr)
         .spawn()
     {
-        Ok(child) => Ok(child.id()),
-        Err(msg) => {
-            error!("{}", msg);
-            Err(())
-        }
+        Ok(child) => Ok((child, log_path)),
+        Err(err) => Err(RrError::Process(
+            format!("Process with conf {} failed to spawn.", conf_file),
+            Some(Box::new(err)),
+        )),
     }
 }
 
-#[inline]
-fn spawn_cluster_process(
-    cluster_host: &String,
-    pid_entries: &HashSet<PIDEntry>,
-) -> Result<u32, ()> {
-    let cluster_string = pid_entries
-        .iter()
-        .map(|entry| format!("{}:{}", cluster_host, entry.port))
-        .collect::<Vec<String>>();
-
-    let mut root_command = Command::new("redis-cli");
-    let mut command = root_command.borrow_mut();
-    command = command.arg("--cluster").arg("create");
-
-    for entry in cluster_string.iter() {
-        command = command.arg(entry);
+/// Whether a server accepts connections and answers PING. A server
+/// still loading its dataset replies with a LOADING error and is not
+/// ready yet.
+///
+/// # Arguments
+/// * `cluster_host` - Host the server is listening on.
+/// * `port` - Port the server is listening on.
+/// * `password` - Password of the server, if it needs one.
+///
+/// # Examples
+/// ```
+/// let ready = is_node_ready("127.0.0.1", 7000, None);
+/// ```
+#[mockable]
+pub fn is_node_ready(cluster_host: &str, port: u16, password: Option<&str>) -> bool {
+    let reply = connect_node_with_password(cluster_host, port, password)
+        .and_then(|mut client| client.command(&["PING"]));
+    match reply {
+        Ok(_) => true,
+        Err(err) => {
+            debug!("Server on port {} is not ready yet: {}", port, err);
+            false
+        }
     }
+}
 
-    match command.stdout(Stdio::null()).stderr(Stdio::null()).spawn() {
-        Ok(child) => Ok(child.id()),
-        Err(msg) => {
-            error!("{}", msg);
-            Err(())
+/// Wait until a freshly spawned server answers PING. A server that exits
+/// before then failed to start, and the error names its configuration
+/// file and shows the end of its log. Returns the server's PID.
+///
+/// # Arguments
+/// * `child` - The spawned server process.
+/// * `conf_file` - Configuration file the server was started with.
+/// * `log_path` - File the server's output is written to.
+/// * `cluster_host` - Host the server is listening on.
+/// * `port` - Port the server is listening on.
+/// * `password` - Password of the server, if it needs one.
+/// * `deadline` - When to give up on the server.
+///
+/// # Examples
+/// ```
+/// let pid = wait_until_ready(&mut child, "7000.conf", &log_path, "127.0.0.1", 7000, None, Instant::now() + READY_TIMEOUT)?;
+/// ```
+pub fn wait_until_ready(
+    child: &mut Child,
+    conf_file: &str,
+    log_path: &Path,
+    cluster_host: &str,
+    port: u16,
+    password: Option<&str>,
+    deadline: Instant,
+) -> Result<u32, RrError> {
+    loop {
+        match child.try_wait() {
+            Ok(Some(status)) => {
+                let tail = tail_lines(log_path, STARTUP_LOG_LINES)
+                    .unwrap_or_default()
+                    .join("\n");
+                return Err(RrError::Process(
+                    format!(
+                        "Process with conf {} exited at startup ({}). Last lines of {}:\n{}",
+                        conf_file,
+                        status,
+                        log_path.display(),
+                        tail
+                    ),
+                    None,
+                ));
+            }
+            Ok(None) if is_node_ready(cluster_host, port, password) => return Ok(child.id()),
+            Ok(None) if Instant::now() >= deadline => {
+                return Err(RrError::Process(
+                    format!(
+                    "Process with conf {} is running but did not answer PING on port {} in time.",
+                    conf_file, port
+                ),
+                    None,
+                ))
+            }
+            Ok(None) => thread::sleep(READY_POLL_INTERVAL),
+            Err(err) => {
+                return Err(RrError::Process(
+                    format!("Failed to check on process with conf {}.", conf_file),
+                    Some(Box::new(err)),
+                ))
+            }
         }
     }
 }
 
-#[inline]
-fn kill_current_processes(pid_set: &HashSet<PIDEntry>) -> Result<(), ()> {
-    let mut root_command = Command::new("kill");
-    let mut command = root_command.borrow_mut();
-    for pid in pid_set.iter() {
-        command = command.arg(pid.pid.to_string());
+/// How servers are asked to stop before being killed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StopMethod {
+    /// SHUTDOWN, saving only if the server is configured to save.
+    Shutdown,
+    /// SHUTDOWN SAVE.
+    ShutdownSave,
+    /// SHUTDOWN NOSAVE.
+    ShutdownNoSave,
+    /// SIGTERM sent to the process.
+    Terminate,
+}
+
+// How long servers get to exit after being asked to stop, unless told
+// otherwise.
+pub const STOP_TIMEOUT: Duration = Duration::from_secs(10);
+
+// How long to wait for a server to exit after SIGKILL.
+const KILL_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Which servers stopped, which of them had to be killed, and which are
+/// still running.
+#[derive(Debug, Default)]
+pub struct StopOutcome {
+    pub stopped: HashSet<PIDEntry>,
+    pub killed: HashSet<PIDEntry>,
+    pub survivors: HashSet<PIDEntry>,
+}
+
+fn terminate(entry: &PIDEntry, reason: String) -> Result<(), RrError> {
+    warn!("{} Sending SIGTERM to PID {}.", reason, entry.pid);
+    signal_process(entry.pid, "TERM")
+}
+
+/// Ask a server to stop. SHUTDOWN closes the connection once the server
+/// exits, so only an error reply means it refused; servers that cannot
+/// be reached or refuse get SIGTERM instead.
+fn request_stop(cluster_host: &str, entry: &PIDEntry, method: StopMethod) -> Result<(), RrError> {
+    let command = match method {
+        StopMethod::Shutdown => vec!["SHUTDOWN"],
+        StopMethod::ShutdownSave => vec!["SHUTDOWN", "SAVE"],
+        StopMethod::ShutdownNoSave => vec!["SHUTDOWN", "NOSAVE"],
+        StopMethod::Terminate => return signal_process(entry.pid, "TERM"),
+    };
+    let port = entry.port;
+    match connect_node(cluster_host, port) {
+        Ok(mut client) => match client.pipeline(&[command]) {
+            Ok(replies) => match replies.into_iter().next().map(|reply| reply.into_result()) {
+                Some(Err(msg)) => terminate(
+                    entry,
+                    format!("Server on port {} refused to shut down: {}.", port, msg),
+                ),
+                _ => Ok(()),
+            },
+            Err(_) => Ok(()),
+        },
+        Err(err) => terminate(
+            entry,
+            format!("Server on port {} could not be reached: {}.", port, err),
+        ),
     }
+}
 
-    match command.stdout(Stdio::null()).stderr(Stdio::null()).spawn() {
-        Ok(_) => Ok(()),
-        Err(msg) => {
-            error!("{}", msg);
-            Err(())
+/// Stop servers: ask each to stop, wait for them to exit until the timeout
+/// runs out, then send SIGKILL to the ones still running.
+///
+/// # Arguments
+/// * `cluster_host` - Host the servers are listening on.
+/// * `entries` - The servers to stop.
+/// * `method` - How to ask the servers to stop.
+/// * `timeout` - How long the servers get to exit before being killed.
+///
+/// # Examples
+/// ```
+/// let outcome = stop_processes("127.0.0.1", &get_currently_running_pids()?, StopMethod::Shutdown, STOP_TIMEOUT);
+/// ```
+pub fn stop_processes(
+    cluster_host: &str,
+    entries: &HashSet<PIDEntry>,
+    method: StopMethod,
+    timeout: Duration,
+) -> StopOutcome {
+    for entry in entries.iter().filter(|entry| is_process_alive(entry.pid)) {
+        if let Err(err) = request_stop(cluster_host, entry, method) {
+            error!("{}", err);
         }
     }
-}
 
-#[derive(Debug)]
-struct ServerConf {
-    pub conf_path: PathBuf,
-    pub conf_port: String,
-}
+    let mut outcome = StopOutcome::default();
+    let deadline = Instant::now() + timeout;
+    let (stopped, lingering): (HashSet<PIDEntry>, HashSet<PIDEntry>) = entries
+        .iter()
+        .cloned()
+        .partition(|entry| wait_for_exit(entry.pid, deadline));
+    outcome.stopped = stopped;
 
-pub fn start_cluster(cluster_host: &String, conf_files: Vec<String>) -> Result<(), String> {
-    match get_currently_running_pids() {
-        Ok(pids) => {
-            if pids.len() != 0 {
-                return Err("Servers are already running. If you wish to restart, first stop the cluster.".to_string());
-            }
-            let valid_processes: HashSet<PIDEntry> = conf_files.iter()
-                                        .map(|path_str| PathBuf::from(path_str))
-                                        .filter(|path| path.exists())
-                                        .map(|file| {
-                                            match read_conf_file(&file) {
-                                                Ok(conf_content) => {
-                                                    if let Some(port_val) = conf_content.get("port") {
-                                                        Ok(ServerConf{
-                                                            conf_path: file.clone(),
-                                                            conf_port: port_val.clone()
-                                                         })
-                                                    } else {
-                                                        Err(())
-                                                    }
-                                                },
-                                                Err(_) => Err(())
-                                            }
-                                        })
-                                        .filter(|file_result| file_result.is_ok())
-                                        .map(|conf| {
-                                            let conf_obj = conf.unwrap();
-                                            let file_path = conf_obj.conf_path.into_os_string().into_string().unwrap_or(String::from("INVALID/"));
-                                            match spawn_server_process(file_path.clone()) {
-                                                Ok(child_pid) => {
-                                                    info!("Process with conf {} successfully started with PID: {}.", file_path, child_pid);
-                                                    Ok(PIDEntry{
-                                                        port: conf_obj.conf_port.clone(),
-                                                        pid: child_pid
-                                                    })
-                                                },
-                                                Err(_) => {
-                                                    error!("Process with conf {} failed to spawn.", file_path);
-                                                    Err(())
-                                                }
-                                            }
-                                        })
-                                        .filter(|entry| entry.is_ok())
-                                        .map(|entry| entry.unwrap())
-                                        .collect();
-            if valid_processes.len() <= 0 {
-                Err("No valid configuration files were found.".to_string())
-            } else {
-                if let Err(msg) = write_data_to_pid_file(&valid_processes) {
-                    error!("{}", msg);
-                }
+    for entry in lingering.iter() {
+        warn!(
+            "Server on port {} did not exit within {} seconds, sending SIGKILL.",
+            entry.port,
+            timeout.as_secs()
+        );
+        if let Err(err) = signal_process(entry.pid, "KILL") {
+            error!("{}", err);
+        }
+    }
+    let deadline = Instant::now() + KILL_TIMEOUT;
+    for entry in lingering.into_iter() {
+        if wait_for_exit(entry.pid, deadline) {
+            outcome.stopped.insert(entry.clone());
+            outcome.killed.insert(entry);
+        } else {
+            outcome.survivors.insert(entry);
+        }
+    }
+    outcome
+}
 
-                match spawn_cluster_process(cluster_host, &valid_processes) {
-                    Ok(_) => {
-                        info!("Prmary cluster node started.");
-                        Ok(())
-                    },
-                    Err(_) => Err("Failed to spawn main process for cluster.".to_string())
-                }
-            }
-        },
-        Err(_msg) => Err("Failed to assess current run state of system. Either manually delete $HOME/.rr/servers.pid or ensure all processes are stopped.".to_string())
+fn log_error(err: &RrError) {
+    error!("{}", err);
+    for cause in err.causes() {
+        error!("caused by: {}", cause);
     }
 }
 
-pub fn stop_cluster() -> Result<(), String> {
-    match get_currently_running_pids() {
-        Ok(pids) => {
-            if pids.len() <= 0 {
-                return Err("No servers are running.".to_string());
+/// Stop servers started by this process after a failed start, giving them
+/// a moment to exit on SIGTERM before killing them, and clear the PID
+/// file. The servers are reaped through their handles, as they would
+/// linger as zombies and look alive otherwise.
+///
+/// # Arguments
+/// * `children` - The server processes started so far.
+///
+/// # Examples
+/// ```
+/// roll_back(vec![child]);
+/// ```
+fn roll_back(mut children: Vec<Child>) {
+    warn!("Stopping the {} servers already started.", children.len());
+    for child in children.iter_mut() {
+        if let Ok(None) = child.try_wait() {
+            if let Err(err) = signal_process(child.id(), "TERM") {
+                log_error(&err);
             }
-
-            match kill_current_processes(&pids) {
-                Ok(_) => {
-                    let empty_set: HashSet<PIDEntry> = HashSet::new();
-                    write_data_to_pid_file(&empty_set)
-                },
-                Err(_) => Err("Failed to kill all server processes.".to_string())
+        }
+    }
+    let deadline = Instant::now() + KILL_TIMEOUT;
+    for child in children.iter_mut() {
+        while let Ok(None) = child.try_wait() {
+            if Instant::now() >= deadline {
+                warn!("Killing process {} with SIGKILL.", child.id());
+                let _ = child.kill();
+                let _ = child.wait();
+                break;
             }
-        },
-        Err(_msg) => Err("Failed to assess current run state of system. Either manually delete $HOME/.rr/servers.pid or ensure all processes are stopped.".to_string())
+            thread::sleep(READY_POLL_INTERVAL);
+        }
+    }
+    if let Err(err) = write_data_to_pid_file(&HashSet::new()) {
+        log_error(&err);
     }
 }
 
-/// Spawns a Redis health check with a given endpoint in the format
-/// host:port. Waits for the child process to finish and forwards the out.
+/// Version of a redis-server executable, as it reports it.
 ///
 /// # Arguments
-/// * `health_endpoint` - host:port format endpoint used to check health.
-///                       Should be an existing running Redis server.
+/// * `binary` - The redis-server executable to ask.
 ///
 /// # Examples
 /// ```
-/// let sample_endpoint = "127.0.0.1:7000".to_string();
-/// spawn_health_check_process(&sample_endpoint).expect("Failed to spawn child health process.");
+/// let version = redis_server_version(Path::new(DEFAULT_REDIS_SERVER));
 /// ```
 #[mockable]
-#[inline]
-fn spawn_health_check_process(health_endpoint: &String) -> Result<(), String> {
-    match Command::new("redis-cli")
-        .arg("--cluster")
-        .arg("check")
-        .arg(health_endpoint)
-        .spawn()
-    {
-        Ok(mut child) => match child.wait() {
-            Ok(_) => Ok(()),
+pub fn redis_server_version(binary: &Path) -> Option<String> {
+    let output = Command::new(binary).arg("--version").output().ok()?;
+    parse_server_version(&String::from_utf8_lossy(&output.stdout))
+}
+
+fn parse_server_version(text: &str) -> Option<String> {
+    text.split_whitespace()
+        .find_map(|field| field.strip_prefix("v="))
+        .map(|version| version.to_string())
+}
+
+/// Fill in the cluster node ID of every server that reports one. Servers
+/// that cannot be asked are left as they are.
+fn with_node_ids(
+    cluster_host: &str,
+    entries: HashSet<PIDEntry>,
+    password: Option<&str>,
+) -> HashSet<PIDEntry> {
+    entries
+        .into_iter()
+        .map(|entry| PIDEntry {
+            node_id: connect_node_with_password(cluster_host, entry.port, password)
+                .and_then(|mut client| node_id(&mut client))
+                .ok(),
+            ..entry
+        })
+        .collect()
+}
+
+#[derive(Debug)]
+struct ServerConf {
+    pub conf_path: PathBuf,
+    pub conf_port: u16,
+    pub data_dir: Option<PathBuf>,
+}
+
+pub fn start_cluster(
+    cluster_host: &String,
+    binary: &Path,
+    conf_files: Vec<String>,
+    replicas: usize,
+    password: Option<&str>,
+    ready_timeout: Duration,
+    keep_partial: bool,
+) -> Result<HashSet<PIDEntry>, RrError> {
+    let pids = get_currently_running_pids().map_err(|err| {
+        RrError::State(
+            format!(
+                "Failed to assess current run state of system. Either manually delete $HOME/.rr/clusters/{}/state.json or ensure all processes are stopped.",
+                current_cluster()
+            ),
+            Some(Box::new(err)),
+        )
+    })?;
+    if !pids.is_empty() {
+        return Err(RrError::State(
+            format!(
+                "Servers of cluster \"{}\" are already running. If you wish to restart, first stop the cluster, or pass --cluster to start another one.",
+                current_cluster()
+            ),
+            None,
+        ));
+    }
+    let server_confs: Vec<ServerConf> = conf_files
+        .iter()
+        .map(PathBuf::from)
+        .filter(|path| path.exists())
+        .filter_map(|file| {
+            let conf_content = read_conf_file(&file).ok()?;
+            match conf_content.value("port").map(u16::from_str) {
+                Some(Ok(port)) => Some(ServerConf {
+                    conf_path: file.clone(),
+                    conf_port: port,
+                    data_dir: conf_content.value("dir").map(PathBuf::from),
+                }),
+                _ => None,
+            }
+        })
+        .collect();
+    if server_confs.is_empty() {
+        return Err(RrError::Config(
+            "No valid configuration files were found.".to_string(),
+            None,
+        ));
+    }
+
+    let ports = server_confs
+        .iter()
+        .map(|conf| conf.conf_port)
+        .collect::<Vec<u16>>();
+    let ports_lock = check_ports_unclaimed(&current_cluster(), &ports)?;
+    let roles: HashMap<u16, NodeRole> = plan_replicas(&ports, replicas)?
+        .into_iter()
+        .map(|(port, primary)| match primary {
+            Some(_) => (port, NodeRole::Replica),
+            None => (port, NodeRole::Primary),
+        })
+        .collect();
+    let data_dirs: HashMap<u16, Option<PathBuf>> = server_confs
+        .iter()
+        .map(|conf| (conf.conf_port, conf.data_dir.clone()))
+        .collect();
+    let version = redis_server_version(binary);
+    // Start every server before checking on any, so they get ready side
+    // by side and share one deadline.
+    let mut spawned: Vec<(u16, String, Child, PathBuf)> = vec![];
+    let mut failed: Vec<String> = vec![];
+    for conf_obj in server_confs.into_iter() {
+        let file_path = conf_obj
+            .conf_path
+            .into_os_string()
+            .into_string()
+            .unwrap_or(String::from("INVALID/"));
+        match spawn_server_process(binary, file_path.clone(), conf_obj.conf_port) {
+            Ok((child, log_path)) => spawned.push((conf_obj.conf_port, file_path, child, log_path)),
             Err(err) => {
-                error!("Failed to run check command with error: {}", err);
-                Err("Failed check command.".to_string())
+                log_error(&err);
+                failed.push(file_path);
             }
-        },
-        Err(_) => Err("Failed to spawn check process.".to_string()),
+        }
+    }
+    let deadline = Instant::now() + ready_timeout;
+    let mut valid_processes: HashSet<PIDEntry> = HashSet::new();
+    let mut running: HashSet<PIDEntry> = HashSet::new();
+    for (port, file_path, child, log_path) in spawned.iter_mut() {
+        let ready = wait_until_ready(
+            child,
+            file_path,
+            log_path,
+            cluster_host,
+            *port,
+            password,
+            deadline,
+        );
+        // Only look the process up once it has had time to exec, so the
+        // recorded executable and start time are the server's own.
+        let process = process_info(child.id());
+        let entry = PIDEntry {
+            port: *port,
+            pid: child.id(),
+            role: roles[port],
+            start_time: process.as_ref().map(|info| info.start_time),
+            conf: Some(PathBuf::from(file_path.as_str())),
+            data_dir: data_dirs[port].clone(),
+            log: Some(log_path.clone()),
+            binary: process.and_then(|info| info.exe),
+            version: version.clone(),
+            ..Default::default()
+        };
+        match ready {
+            Ok(child_pid) => {
+                info!(
+                    "Process with conf {} is ready with PID: {}.",
+                    file_path, child_pid
+                );
+                valid_processes.insert(entry.clone());
+                running.insert(entry);
+            }
+            Err(err) => {
+                log_error(&err);
+                failed.push(file_path.clone());
+                if let Ok(None) = child.try_wait() {
+                    running.insert(entry);
+                }
+            }
+        }
+    }
+    let children = spawned
+        .into_iter()
+        .map(|(_, _, child, _)| child)
+        .collect::<Vec<Child>>();
+
+    if !failed.is_empty() && !keep_partial {
+        roll_back(children);
+        return Err(RrError::Process(
+            format!(
+                "Servers with conf {} did not become ready, so the cluster was not created and the servers already started were stopped. Pass --keep-partial to keep them running.",
+                failed.join(", ")
+            ),
+            None,
+        ));
+    }
+    if valid_processes.is_empty() {
+        return Err(RrError::Process(
+            format!(
+                "No configured server became ready. Servers with conf {} failed.",
+                failed.join(", ")
+            ),
+            None,
+        ));
+    }
+    if let Err(msg) = write_data_to_pid_file(&running) {
+        error!("{}", msg);
+    }
+    drop(ports_lock);
+    if !failed.is_empty() {
+        warn!(
+            "Creating a cluster from the {} servers that became ready.",
+            valid_processes.len()
+        );
+    }
+    match create_cluster(cluster_host, &valid_processes, password) {
+        Ok(_) => {
+            info!("Cluster created from {} nodes.", valid_processes.len());
+            let recorded = with_node_ids(cluster_host, running, password);
+            if let Err(msg) = write_data_to_pid_file(&recorded) {
+                error!("{}", msg);
+            }
+            Ok(recorded
+                .into_iter()
+                .filter(|entry| valid_processes.iter().any(|ready| ready.port == entry.port))
+                .collect())
+        }
+        Err(err) if keep_partial => Err(RrError::ClusterHealth(
+            "Failed to create cluster. The servers are left running.".to_string(),
+            Some(Box::new(err)),
+        )),
+        Err(err) => {
+            roll_back(children);
+            Err(RrError::ClusterHealth(
+                "Failed to create cluster, so the servers already started were stopped. Pass --keep-partial to keep them running.".to_string(),
+                Some(Box::new(err)),
+            ))
+        }
     }
 }
 
-/// Given a cluster host, get the currently running server processes and
-/// use one to run a redis-cli --cluster check command.
+/// Stop every recorded server, escalating to SIGKILL for those that do
+/// not exit in time. Only the servers that are gone are dropped from the
+/// PID file; if any survive, they stay recorded and an error is returned.
 ///
 /// # Arguments
-/// * `cluster_host` - String representing the target cluster host.
+/// * `cluster_host` - Host the servers are listening on.
+/// * `method` - How to ask the servers to stop.
+/// * `timeout` - How long the servers get to exit before being killed.
 ///
 /// # Examples
 /// ```
-/// let sample_host = "localhost".to_string();
-/// check_cluster_health(&sample_host).expect("Failed to run server health check.");
+/// let sample_host = "127.0.0.1".to_string();
+/// let outcome = stop_cluster(&sample_host, StopMethod::Shutdown, STOP_TIMEOUT).expect("Failed to stop cluster.");
 /// ```
-pub fn check_cluster_health(cluster_host: &String) -> Result<(), String> {
-    match get_currently_running_pids() {
-        Ok(pids) => {
-            let pids_as_vector = pids.iter().collect::<Vec<&PIDEntry>>();
-            let captain_pid = pids_as_vector.first();
-            match captain_pid {
-                Some(pid) => {
-                    let health_endpoint = format!("{}:{}", cluster_host, pid.port);
-                    spawn_health_check_process(&health_endpoint)
-                }
-                None => Err("There are no currently running server processes.".to_string()),
-            }
-        }
-        Err(msg) => Err(msg),
+pub fn stop_cluster(
+    cluster_host: &str,
+    method: StopMethod,
+    timeout: Duration,
+) -> Result<StopOutcome, RrError> {
+    let pids = match get_currently_running_pids() {
+        Ok(pids) => pids,
+        Err(err) => return Err(RrError::State(format!("Failed to assess current run state of system. Either manually delete $HOME/.rr/clusters/{}/state.json or ensure all processes are stopped.", current_cluster()), Some(Box::new(err)))),
+    };
+    if pids.is_empty() {
+        return Err(RrError::State("No servers are running.".to_string(), None));
+    }
+
+    let outcome = stop_processes(cluster_host, &pids, method, timeout);
+    write_data_to_pid_file(&outcome.survivors)?;
+    if !outcome.survivors.is_empty() {
+        let mut ports = outcome
+            .survivors
+            .iter()
+            .map(|entry| entry.port)
+            .collect::<Vec<u16>>();
+        ports.sort_unstable();
+        let ports = ports
+            .iter()
+            .map(|port| port.to_string())
+            .collect::<Vec<String>>();
+        return Err(RrError::Process(
+            format!(
+                "Servers on ports {} are still running after SIGKILL.",
+                ports.join(", ")
+            ),
+            None,
+        ));
     }
+    Ok(outcome)
 }
 
 #[cfg(test)]
 mod tests {
 
+    use std::{
+        cell::RefCell,
+        fs,
+        io::{BufReader, Write},
+        net::TcpListener,
+        rc::Rc,
+        thread,
+    };
+
     use mocktopus::mocking::{MockResult, Mockable};
 
+    use crate::{
+        local::lock::StateLock,
+        resp::{
+            client::select_password,
+            reply::{read_reply, Reply},
+        },
+    };
+
     use super::*;
 
+    fn entries() -> HashSet<PIDEntry> {
+        [(7000, 10), (7001, 11)]
+            .iter()
+            .map(|(port, pid)| PIDEntry {
+                port: *port,
+                pid: *pid,
+                role: NodeRole::Primary,
+                ..Default::default()
+            })
+            .collect()
+    }
+
+    // Processes exit once they receive one of the given signals and
+    // every signal sent is recorded.
+    fn mock_processes(exits_on: &'static [&'static str]) -> Rc<RefCell<Vec<(u32, String)>>> {
+        let signals = Rc::new(RefCell::new(vec![]));
+        let sent = signals.clone();
+        signal_process.mock_safe(move |pid, signal| {
+            sent.borrow_mut().push((pid, signal.to_string()));
+            MockResult::Return(Ok(()))
+        });
+        let received = signals.clone();
+        is_process_alive.mock_safe(move |pid| {
+            MockResult::Return(
+                !received
+                    .borrow()
+                    .iter()
+                    .any(|(p, signal)| *p == pid && exits_on.contains(&signal.as_str())),
+            )
+        });
+        signals
+    }
+
     #[test]
-    fn check_cluster_health_failed_pid_check() {
-        get_currently_running_pids
-            .mock_safe(|| MockResult::Return(Err("Failed to get PIDs".to_string())));
+    fn test_wait_until_ready_shows_log_tail() {
+        is_node_ready.mock_safe(|_, _, _| MockResult::Return(false));
+        let log_path = PathBuf::from("startup-test.log");
+        let log = open_node_log(&log_path).unwrap();
+        let mut child = Command::new("sh")
+            .arg("-c")
+            .arg("echo '*** FATAL CONFIG FILE ERROR ***' >&2; exit 1")
+            .stderr(log)
+            .spawn()
+            .unwrap();
 
-        let sample_host = "localhost".to_string();
-        let cluster_result = check_cluster_health(&sample_host);
-        assert!(cluster_result.is_err());
+        let result = wait_until_ready(
+            &mut child,
+            "7000.conf",
+            &log_path,
+            "127.0.0.1",
+            7000,
+            None,
+            Instant::now() + Duration::from_secs(5),
+        );
+        fs::remove_file(&log_path).unwrap();
+        match result {
+            Err(RrError::Process(msg, _)) => {
+                assert!(msg.contains("7000.conf"));
+                assert!(msg.contains("FATAL CONFIG FILE ERROR"));
+            }
+            other => panic!("Unexpected result: {:?}", other),
+        }
     }
 
     #[test]
-    fn check_cluster_health_empty_pid_set() {
-        get_currently_running_pids.mock_safe(|| MockResult::Return(Ok(HashSet::new())));
+    fn test_wait_until_ready_polls_until_ping() {
+        let checks = Rc::new(RefCell::new(0));
+        let counted = checks.clone();
+        is_node_ready.mock_safe(move |_, port, _| {
+            assert_eq!(port, 7000);
+            *counted.borrow_mut() += 1;
+            MockResult::Return(*counted.borrow() >= 3)
+        });
+        let mut child = Command::new("sleep").arg("30").spawn().unwrap();
+        let pid = wait_until_ready(
+            &mut child,
+            "7000.conf",
+            Path::new("unused.log"),
+            "127.0.0.1",
+            7000,
+            None,
+            Instant::now() + Duration::from_secs(5),
+        );
+        child.kill().unwrap();
+        assert_eq!(pid.unwrap(), child.id());
+        assert_eq!(*checks.borrow(), 3);
+    }
+
+    #[test]
+    fn test_wait_until_ready_times_out() {
+        is_node_ready.mock_safe(|_, _, _| MockResult::Return(false));
+        let mut child = Command::new("sleep").arg("30").spawn().unwrap();
+        let result = wait_until_ready(
+            &mut child,
+            "7000.conf",
+            Path::new("unused.log"),
+            "127.0.0.1",
+            7000,
+            None,
+            Instant::now(),
+        );
+        child.kill().unwrap();
+        assert!(matches!(result, Err(RrError::Process(..))));
+    }
+
+    #[test]
+    fn test_stop_processes_terminate() {
+        let signals = mock_processes(&["TERM"]);
+        let outcome = stop_processes(
+            "127.0.0.1",
+            &entries(),
+            StopMethod::Terminate,
+            Duration::from_secs(1),
+        );
+        assert_eq!(outcome.stopped.len(), 2);
+        assert!(outcome.killed.is_empty());
+        assert!(signals.borrow().iter().all(|(_, signal)| signal == "TERM"));
+    }
+
+    #[test]
+    fn test_request_stop_uses_selected_password() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        // A node that only takes SHUTDOWN once authenticated.
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let request = read_reply(&mut reader).unwrap();
+            let auth = Reply::Array(vec![
+                Reply::Bulk(b"AUTH".to_vec()),
+                Reply::Bulk(b"s3cret pass".to_vec()),
+            ]);
+            if request == auth {
+                stream.write_all(b"+OK\r\n").unwrap();
+                read_reply(&mut reader).unwrap();
+            } else {
+                stream
+                    .write_all(b"-NOAUTH Authentication required.\r\n")
+                    .unwrap();
+            }
+        });
+        let signals = mock_processes(&[]);
+        let entry = PIDEntry {
+            port,
+            pid: 10,
+            ..Default::default()
+        };
 
-        let sample_host = "localhost".to_string();
-        let cluster_result = check_cluster_health(&sample_host);
-        assert!(cluster_result.is_err());
+        select_password(Some("s3cret pass".to_string())).unwrap();
+        let result = request_stop("127.0.0.1", &entry, StopMethod::Shutdown);
+        select_password(None).unwrap();
+        assert!(result.is_ok());
+        assert!(signals.borrow().is_empty());
     }
 
     #[test]
-    fn check_cluster_health_process_should_start() {
-        get_currently_running_pids.mock_safe(|| {
-            let mut test_set: HashSet<PIDEntry> = HashSet::new();
-            test_set.insert(PIDEntry {
-                port: "7000".to_string(),
-                pid: 1234,
-            });
-
-            MockResult::Return(Ok(test_set))
+    fn test_stop_processes_escalates_to_kill() {
+        let signals = mock_processes(&["KILL"]);
+        let outcome = stop_processes(
+            "127.0.0.1",
+            &entries(),
+            StopMethod::Terminate,
+            Duration::from_millis(0),
+        );
+        assert_eq!(outcome.stopped.len(), 2);
+        assert_eq!(outcome.killed.len(), 2);
+        assert!(outcome.survivors.is_empty());
+        assert_eq!(signals.borrow().len(), 4);
+    }
+
+    #[test]
+    fn test_stop_cluster_keeps_survivors() {
+        get_currently_running_pids.mock_safe(|| MockResult::Return(Ok(entries())));
+        signal_process.mock_safe(|_, _| MockResult::Return(Ok(())));
+        is_process_alive.mock_safe(|pid| MockResult::Return(pid == 11));
+        write_data_to_pid_file.mock_safe(|pids| {
+            assert_eq!(pids.len(), 1);
+            assert!(pids.iter().all(|entry| entry.pid == 11));
+            MockResult::Return(Ok(()))
         });
 
-        spawn_health_check_process.mock_safe(|_| MockResult::Return(Ok(())));
+        let result = stop_cluster("127.0.0.1", StopMethod::Terminate, Duration::from_millis(0));
+        assert!(matches!(result, Err(RrError::Process(..))));
+    }
 
-        let sample_host = "localhost".to_string();
-        let cluster_result = check_cluster_health(&sample_host);
-        assert!(cluster_result.is_ok());
+    #[test]
+    fn test_roll_back_stops_children() {
+        write_data_to_pid_file.mock_safe(|pids| {
+            assert!(pids.is_empty());
+            MockResult::Return(Ok(()))
+        });
+        let children = vec![
+            Command::new("sleep").arg("30").spawn().unwrap(),
+            Command::new("sh")
+                .arg("-c")
+                .arg("trap '' TERM; while true; do sleep 0.1; done")
+                .spawn()
+                .unwrap(),
+        ];
+        let pids = children
+            .iter()
+            .map(|child| child.id())
+            .collect::<Vec<u32>>();
+        // Let the shell install its trap before it is signalled.
+        thread::sleep(Duration::from_millis(200));
+        roll_back(children);
+        for pid in pids {
+            assert!(!is_process_alive(pid));
+        }
+    }
+
+    #[test]
+    fn test_start_cluster_rolls_back_failed_create() {
+        let conf_paths = ["rollback-7000.conf", "rollback-7001.conf"];
+        for (port, path) in [7000, 7001].iter().zip(conf_paths.iter()) {
+            fs::write(path, format!("port {}\n", port)).unwrap();
+        }
+        get_currently_running_pids.mock_safe(|| MockResult::Return(Ok(HashSet::new())));
+        check_ports_unclaimed.mock_safe(|_, _| MockResult::Return(Ok(StateLock::default())));
+        spawn_server_process.mock_safe(|_, _, port| {
+            let child = Command::new("sleep").arg("30").spawn().unwrap();
+            MockResult::Return(Ok((child, PathBuf::from(format!("{}.log", port)))))
+        });
+        is_node_ready.mock_safe(|_, _, _| MockResult::Return(true));
+        create_cluster.mock_safe(|_, _, _| {
+            MockResult::Return(Err(RrError::ClusterHealth("MEET failed".to_string(), None)))
+        });
+        let written = Rc::new(RefCell::new(vec![]));
+        let recorded = written.clone();
+        write_data_to_pid_file.mock_safe(move |pids| {
+            recorded.borrow_mut().push(pids.clone());
+            MockResult::Return(Ok(()))
+        });
+
+        let result = start_cluster(
+            &"127.0.0.1".to_string(),
+            Path::new(DEFAULT_REDIS_SERVER),
+            conf_paths.iter().map(|path| path.to_string()).collect(),
+            0,
+            None,
+            READY_TIMEOUT,
+            false,
+        );
+        for path in conf_paths.iter() {
+            fs::remove_file(path).unwrap();
+        }
+        assert!(matches!(result, Err(RrError::ClusterHealth(..))));
+        let written = written.borrow();
+        assert_eq!(written.first().map(|pids| pids.len()), Some(2));
+        let last = written.last().unwrap();
+        assert!(last.is_empty());
+        for entry in written[0].iter() {
+            assert!(!is_process_alive(entry.pid));
+        }
+    }
+
+    #[test]
+    fn test_parse_server_version() {
+        let text = "Redis server v=7.2.4 sha=00000000:0 malloc=jemalloc-5.3.0 bits=64 build=68b2f1e4b5b4a5a1\n";
+        assert_eq!(parse_server_version(text), Some("7.2.4".to_string()));
+        assert_eq!(parse_server_version(""), None);
     }
 }
@@ -0,0 +1,319 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+    str::FromStr,
+};
+
+use mocktopus::macros::mockable;
+use serde::Serialize;
+
+use crate::{
+    cluster::nodes::{parse_cluster_nodes, ClusterNode, SlotRange},
+    error::RrError,
+    local::{
+        pid::{get_currently_running_pids, NodeRole, PIDEntry},
+        process::is_process_alive,
+    },
+    resp::{client::connect_node, reply::parse_info_fields},
+};
+
+/// Whether a recorded node is running and taking part in the cluster.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum NodeHealth {
+    Ok,
+    /// The recorded process is no longer running.
+    Dead,
+    /// The process is running but does not answer.
+    Unreachable,
+    /// The node answers but is not a member of the cluster.
+    NotInCluster,
+}
+
+impl fmt::Display for NodeHealth {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NodeHealth::Ok => f.pad("ok"),
+            NodeHealth::Dead => f.pad("dead"),
+            NodeHealth::Unreachable => f.pad("unreachable"),
+            NodeHealth::NotInCluster => f.pad("not-in-cluster"),
+        }
+    }
+}
+
+/// The status of one recorded node. Everything read from the node
+/// itself is None when it could not be queried.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct NodeStatus {
+    pub port: u16,
+    pub pid: u32,
+    pub role: NodeRole,
+    pub health: NodeHealth,
+    pub node_id: Option<String>,
+    pub slots: Vec<SlotRange>,
+    pub connected_replicas: Option<u64>,
+    pub used_memory: Option<u64>,
+    pub uptime_seconds: Option<u64>,
+    pub problem: Option<String>,
+}
+
+/// What a node says about itself and the cluster.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NodeView {
+    pub cluster_nodes: Vec<ClusterNode>,
+    pub info: HashMap<String, String>,
+}
+
+/// Ask a node for its view of the cluster and its INFO fields. Nodes
+/// without cluster support come back with no cluster nodes.
+///
+/// # Arguments
+/// * `cluster_host` - Host the node is listening on.
+/// * `port` - Port of the node to query.
+///
+/// # Examples
+/// ```
+/// let sample_host = "127.0.0.1".to_string();
+/// let view = query_node(&sample_host, 7000).expect("Failed to query node.");
+/// ```
+#[mockable]
+pub fn query_node(cluster_host: &String, port: u16) -> Result<NodeView, RrError> {
+    let mut client = connect_node(cluster_host, port)?;
+    let replies = client.pipeline(&[vec!["CLUSTER", "NODES"], vec!["INFO"]])?;
+    let texts = replies
+        .into_iter()
+        .map(|reply| reply.into_result().ok().and_then(|r| r.as_string()))
+        .collect::<Vec<Option<String>>>();
+    let cluster_nodes = match &texts[0] {
+        Some(output) => parse_cluster_nodes(output)?,
+        None => vec![],
+    };
+    match &texts[1] {
+        Some(info) => Ok(NodeView {
+            cluster_nodes,
+            info: parse_info_fields(info),
+        }),
+        None => Err(RrError::Protocol(
+            format!("{}:{} did not answer INFO.", cluster_host, port),
+            None,
+        )),
+    }
+}
+
+fn info_count(info: &HashMap<String, String>, name: &str) -> Option<u64> {
+    info.get(name).and_then(|v| u64::from_str(v).ok())
+}
+
+fn node_status(
+    cluster_host: &String,
+    entry: &PIDEntry,
+) -> Result<(NodeStatus, Option<NodeView>), RrError> {
+    let port = entry.port;
+    let mut status = NodeStatus {
+        port,
+        pid: entry.pid,
+        role: entry.role,
+        health: NodeHealth::Ok,
+        node_id: None,
+        slots: vec![],
+        connected_replicas: None,
+        used_memory: None,
+        uptime_seconds: None,
+        problem: None,
+    };
+
+    if !is_process_alive(entry.pid) {
+        status.health = NodeHealth::Dead;
+        status.problem = Some(format!("Process {} is not running.", entry.pid));
+        return Ok((status, None));
+    }
+
+    let view = match query_node(cluster_host, port) {
+        Ok(view) => view,
+        Err(err) => {
+            status.health = NodeHealth::Unreachable;
+            status.problem = Some(err.to_string());
+            return Ok((status, None));
+        }
+    };
+    status.connected_replicas = info_count(&view.info, "connected_slaves");
+    status.used_memory = info_count(&view.info, "used_memory");
+    status.uptime_seconds = info_count(&view.info, "uptime_in_seconds");
+    match view.cluster_nodes.iter().find(|node| node.is_myself()) {
+        Some(myself) => {
+            status.node_id = Some(myself.id.clone());
+            status.slots = myself.slots.clone();
+            status.role = if myself.is_primary() {
+                NodeRole::Primary
+            } else {
+                NodeRole::Replica
+            };
+        }
+        None => {
+            status.health = NodeHealth::NotInCluster;
+            status.problem = Some("Cluster support is not enabled on the node.".to_string());
+        }
+    }
+    Ok((status, Some(view)))
+}
+
+/// Get the status of every recorded node: whether its process is alive,
+/// what it reports about itself, and whether the other nodes know it.
+/// Nodes are ordered by port.
+///
+/// # Arguments
+/// * `cluster_host` - Host the nodes are listening on.
+///
+/// # Examples
+/// ```
+/// let sample_host = "127.0.0.1".to_string();
+/// let statuses = cluster_status(&sample_host).expect("Failed to read cluster status.");
+/// ```
+pub fn cluster_status(cluster_host: &String) -> Result<Vec<NodeStatus>, RrError> {
+    let pids = get_currently_running_pids()?;
+    if pids.is_empty() {
+        return Err(RrError::State(
+            "There are no currently running server processes.".to_string(),
+            None,
+        ));
+    }
+
+    let mut entries = pids.iter().collect::<Vec<&PIDEntry>>();
+    entries.sort_unstable_by_key(|entry| entry.port);
+    let mut results = entries
+        .iter()
+        .map(|entry| node_status(cluster_host, entry))
+        .collect::<Result<Vec<(NodeStatus, Option<NodeView>)>, RrError>>()?;
+
+    // A node that knows only itself while the others never heard of it
+    // was started but never joined the cluster.
+    let views = results
+        .iter()
+        .filter_map(|(status, view)| view.as_ref().map(|v| (status.node_id.clone(), v)))
+        .filter(|(node_id, _)| node_id.is_some())
+        .collect::<Vec<(Option<String>, &NodeView)>>();
+    if views.len() > 1 {
+        let seen_by_others = |node_id: &str| {
+            views.iter().any(|(viewer_id, view)| {
+                viewer_id.as_deref() != Some(node_id)
+                    && view.cluster_nodes.iter().any(|node| node.id == node_id)
+            })
+        };
+        let strangers = results
+            .iter()
+            .filter_map(|(status, _)| status.node_id.clone())
+            .filter(|node_id| !seen_by_others(node_id))
+            .collect::<HashSet<String>>();
+        for (status, _) in results.iter_mut() {
+            if let Some(node_id) = &status.node_id {
+                if strangers.contains(node_id) {
+                    status.health = NodeHealth::NotInCluster;
+                    status.problem = Some("No other node knows about this node.".to_string());
+                }
+            }
+        }
+    }
+
+    Ok(results.into_iter().map(|(status, _)| status).collect())
+}
+
+#[cfg(test)]
+mod tests {
+
+    use mocktopus::mocking::{MockResult, Mockable};
+
+    use super::*;
+
+    fn mock_nodes(ports: &'static [(u16, u32)]) {
+        get_currently_running_pids.mock_safe(move || {
+            MockResult::Return(Ok(ports
+                .iter()
+                .map(|(port, pid)| PIDEntry {
+                    port: *port,
+                    pid: *pid,
+                    role: NodeRole::Primary,
+                    ..Default::default()
+                })
+                .collect()))
+        });
+    }
+
+    // Each node's view of the cluster, keyed by port. Port 7002 only
+    // knows about itself.
+    fn mock_views() {
+        query_node.mock_safe(|_, port| {
+            let nodes = match port {
+                7000 => "aaa 127.0.0.1:7000@17000 myself,master - 0 0 1 connected 0-8191\nbbb 127.0.0.1:7001@17001 master - 0 0 2 connected 8192-16383\n",
+                7001 => "aaa 127.0.0.1:7000@17000 master - 0 0 1 connected 0-8191\nbbb 127.0.0.1:7001@17001 myself,master - 0 0 2 connected 8192-16383\n",
+                _ => "ccc 127.0.0.1:7002@17002 myself,master - 0 0 0 connected\n",
+            };
+            MockResult::Return(Ok(NodeView {
+                cluster_nodes: parse_cluster_nodes(nodes).unwrap(),
+                info: parse_info_fields(
+                    "connected_slaves:0\r\nused_memory:1024\r\nuptime_in_seconds:60\r\n",
+                ),
+            }))
+        });
+    }
+
+    #[test]
+    fn test_cluster_status_healthy() {
+        mock_nodes(&[(7001, 11), (7000, 10)]);
+        is_process_alive.mock_safe(|_| MockResult::Return(true));
+        mock_views();
+
+        let statuses = cluster_status(&"127.0.0.1".to_string()).unwrap();
+        assert_eq!(statuses.len(), 2);
+        assert_eq!(statuses[0].port, 7000);
+        assert_eq!(statuses[0].health, NodeHealth::Ok);
+        assert_eq!(statuses[0].node_id.as_deref(), Some("aaa"));
+        assert_eq!(
+            statuses[0].slots,
+            vec![SlotRange {
+                first: 0,
+                last: 8191
+            }]
+        );
+        assert_eq!(statuses[1].used_memory, Some(1024));
+        assert_eq!(statuses[1].uptime_seconds, Some(60));
+    }
+
+    #[test]
+    fn test_cluster_status_flags_dead_and_stranger() {
+        mock_nodes(&[(7000, 10), (7001, 11), (7002, 12), (7003, 13)]);
+        is_process_alive.mock_safe(|pid| MockResult::Return(pid != 13));
+        mock_views();
+
+        let statuses = cluster_status(&"127.0.0.1".to_string()).unwrap();
+        let health = statuses
+            .iter()
+            .map(|s| s.health)
+            .collect::<Vec<NodeHealth>>();
+        assert_eq!(
+            health,
+            vec![
+                NodeHealth::Ok,
+                NodeHealth::Ok,
+                NodeHealth::NotInCluster,
+                NodeHealth::Dead
+            ]
+        );
+        assert!(statuses[3].problem.is_some());
+    }
+
+    #[test]
+    fn test_cluster_status_unreachable() {
+        mock_nodes(&[(7000, 10)]);
+        is_process_alive.mock_safe(|_| MockResult::Return(true));
+        query_node.mock_safe(|_, _| {
+            MockResult::Return(Err(RrError::Protocol(
+                "Connection refused".to_string(),
+                None,
+            )))
+        });
+
+        let statuses = cluster_status(&"127.0.0.1".to_string()).unwrap();
+        assert_eq!(statuses[0].health, NodeHealth::Unreachable);
+        assert_eq!(statuses[0].node_id, None);
+    }
+}
@@ -0,0 +1,490 @@
+use std::{
+    collections::HashMap,
+    env, fmt, fs,
+    path::{Path, PathBuf},
+    process,
+    str::FromStr,
+};
+
+use serde::Serialize;
+
+use crate::{
+    cluster::{
+        config::{aggregate_config_files, CLUSTER_BUS_PORT_OFFSET},
+        parser::{load_conf_file, ConfDirective, RedisConf},
+    },
+    error::RrError,
+};
+
+/// How serious a problem found in a config file is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Warning => write!(f, "warning"),
+            Severity::Error => write!(f, "error"),
+        }
+    }
+}
+
+/// A single problem found in a config file. A line of 0 means the
+/// problem is with the file as a whole rather than one directive.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ConfFinding {
+    pub severity: Severity,
+    pub file: PathBuf,
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ConfFinding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.line > 0 {
+            write!(f, "{}:{}: {}", self.file.display(), self.line, self.message)
+        } else {
+            write!(f, "{}: {}", self.file.display(), self.message)
+        }
+    }
+}
+
+// Directives Redis lets appear several times, each occurrence adding to
+// the ones before rather than replacing them.
+const REPEATABLE_DIRECTIVES: [&str; 5] = [
+    "save",
+    "rename-command",
+    "loadmodule",
+    "user",
+    "client-output-buffer-limit",
+];
+
+/// The settings of one node that have to be unique across the cluster.
+struct NodeSettings {
+    file: PathBuf,
+    port: Option<(u16, usize)>,
+    bus_port: Option<(u32, usize)>,
+    cluster_config_file: (PathBuf, usize),
+    db_file: (PathBuf, usize),
+}
+
+fn finding(severity: Severity, file: &Path, line: usize, message: String) -> ConfFinding {
+    ConfFinding {
+        severity,
+        file: file.to_path_buf(),
+        line,
+        message,
+    }
+}
+
+fn directive_line(directive: Option<&ConfDirective>) -> usize {
+    directive.map_or(0, |directive| directive.line)
+}
+
+/// Resolve the directory a node works in. Relative paths are resolved
+/// against the working directory, as redis-server does.
+fn resolve_node_dir(conf: &RedisConf) -> PathBuf {
+    let dir = PathBuf::from(conf.value("dir").unwrap_or("."));
+    if dir.is_absolute() {
+        dir
+    } else {
+        env::current_dir().unwrap_or_default().join(dir)
+    }
+}
+
+/// Check the node can write to its data directory by creating and
+/// removing a probe file, which also accounts for ACLs and mounts.
+fn is_dir_writable(dir: &Path) -> bool {
+    let probe = dir.join(format!(".rr-write-check-{}", process::id()));
+    match fs::write(&probe, b"") {
+        Ok(_) => fs::remove_file(&probe).is_ok(),
+        Err(_) => false,
+    }
+}
+
+/// Check a single parsed config on its own, returning the findings and
+/// the settings that need to be compared against the other nodes.
+fn check_node_conf(file: &Path, conf: &RedisConf, findings: &mut Vec<ConfFinding>) -> NodeSettings {
+    for (name, directive) in conf.effective().iter() {
+        let occurrences = conf.get_all(name);
+        if occurrences.len() > 1 && !REPEATABLE_DIRECTIVES.contains(&name.as_str()) {
+            findings.push(finding(
+                Severity::Warning,
+                &directive.file,
+                directive.line,
+                format!(
+                    "{} is set {} times; only this last value takes effect.",
+                    name,
+                    occurrences.len()
+                ),
+            ));
+        }
+    }
+
+    let port_directive = conf.get("port");
+    let port = match port_directive.and_then(|directive| directive.value()) {
+        Some(value) => match u16::from_str(value) {
+            Ok(port) if port > 0 => Some((port, directive_line(port_directive))),
+            _ => {
+                findings.push(finding(
+                    Severity::Error,
+                    file,
+                    directive_line(port_directive),
+                    format!("port {} is not a valid TCP port.", value),
+                ));
+                None
+            }
+        },
+        None => {
+            findings.push(finding(
+                Severity::Error,
+                file,
+                0,
+                "No port is set; every node needs its own port.".to_string(),
+            ));
+            None
+        }
+    };
+
+    let cluster_enabled = conf.get("cluster-enabled");
+    if !cluster_enabled
+        .and_then(|directive| directive.value())
+        .is_some_and(|value| value.eq_ignore_ascii_case("yes"))
+    {
+        findings.push(finding(
+            Severity::Error,
+            file,
+            directive_line(cluster_enabled),
+            "cluster-enabled yes is not set.".to_string(),
+        ));
+    }
+
+    let cluster_port = conf.get("cluster-port");
+    let bus_port = match cluster_port.and_then(|directive| directive.value()) {
+        Some(value) if value != "0" => match u16::from_str(value) {
+            Ok(bus_port) => Some((bus_port as u32, directive_line(cluster_port))),
+            Err(_) => {
+                findings.push(finding(
+                    Severity::Error,
+                    file,
+                    directive_line(cluster_port),
+                    format!("cluster-port {} is not a valid TCP port.", value),
+                ));
+                None
+            }
+        },
+        _ => port.map(|(port, line)| (port as u32 + CLUSTER_BUS_PORT_OFFSET as u32, line)),
+    };
+    if let Some((bus_port, line)) = bus_port {
+        if bus_port > u16::MAX as u32 {
+            findings.push(finding(
+                Severity::Error,
+                file,
+                line,
+                format!(
+                    "Cluster bus port {} is out of range; use a port below {} or set cluster-port.",
+                    bus_port,
+                    u16::MAX - CLUSTER_BUS_PORT_OFFSET + 1
+                ),
+            ));
+        }
+    }
+
+    let dir_directive = conf.get("dir");
+    let node_dir = resolve_node_dir(conf);
+    if !node_dir.is_dir() {
+        findings.push(finding(
+            Severity::Error,
+            file,
+            directive_line(dir_directive),
+            format!("dir {} does not exist.", node_dir.display()),
+        ));
+    } else if !is_dir_writable(&node_dir) {
+        findings.push(finding(
+            Severity::Error,
+            file,
+            directive_line(dir_directive),
+            format!("dir {} is not writable.", node_dir.display()),
+        ));
+    }
+
+    let nodes_file = conf.get("cluster-config-file");
+    let db_file = conf.get("dbfilename");
+    NodeSettings {
+        file: file.to_path_buf(),
+        port,
+        bus_port,
+        cluster_config_file: (
+            node_dir.join(nodes_file.and_then(|d| d.value()).unwrap_or("nodes.conf")),
+            directive_line(nodes_file),
+        ),
+        db_file: (
+            node_dir.join(db_file.and_then(|d| d.value()).unwrap_or("dump.rdb")),
+            directive_line(db_file),
+        ),
+    }
+}
+
+/// Report every setting that is claimed by more than one node. The first
+/// node to claim a value keeps it, every later one gets an error.
+fn check_unique<T, F>(nodes: &[NodeSettings], what: &str, key: F, findings: &mut Vec<ConfFinding>)
+where
+    T: Eq + std::hash::Hash + fmt::Display,
+    F: Fn(&NodeSettings) -> Option<(T, usize)>,
+{
+    let mut owners: HashMap<T, &Path> = HashMap::new();
+    for node in nodes.iter() {
+        if let Some((value, line)) = key(node) {
+            if let Some(owner) = owners.get(&value) {
+                findings.push(finding(
+                    Severity::Error,
+                    &node.file,
+                    line,
+                    format!("{} {} is also used by {}.", what, value, owner.display()),
+                ));
+            } else {
+                owners.insert(value, &node.file);
+            }
+        }
+    }
+}
+
+/// Lint every config file under the base directory, the same set of
+/// files start would use, and return what was found. Problems with a
+/// single file are reported against it; settings that collide between
+/// nodes are reported against every node after the first to use them.
+///
+/// # Arguments
+/// * `base_dir` - Directory holding the node config files.
+///
+/// # Examples
+/// ```
+/// let base_path = PathBuf::from("/usr/local/etc/redis/cluster");
+/// let findings = validate_conf_dir(&base_path).expect("Failed to walk config directory.");
+/// ```
+pub fn validate_conf_dir(base_dir: &PathBuf) -> Result<Vec<ConfFinding>, RrError> {
+    let mut findings = vec![];
+    let mut nodes = vec![];
+    let mut conf_files = aggregate_config_files(base_dir)?;
+    conf_files.sort();
+    for conf_file in conf_files.iter() {
+        let file = PathBuf::from(conf_file);
+        match load_conf_file(&file) {
+            Ok(conf) => nodes.push(check_node_conf(&file, &conf, &mut findings)),
+            Err(err) => findings.push(finding(Severity::Error, &err.file, err.line, err.message)),
+        }
+    }
+
+    check_unique(&nodes, "port", |node| node.port, &mut findings);
+    check_unique(
+        &nodes,
+        "cluster-config-file",
+        |node| {
+            let (path, line) = &node.cluster_config_file;
+            Some((path.display().to_string(), *line))
+        },
+        &mut findings,
+    );
+    check_unique(
+        &nodes,
+        "dir/dbfilename",
+        |node| {
+            let (path, line) = &node.db_file;
+            Some((path.display().to_string(), *line))
+        },
+        &mut findings,
+    );
+
+    // Bus ports must not land on another node's client or bus port.
+    let client_ports = nodes
+        .iter()
+        .filter_map(|node| node.port.map(|(port, _)| (port as u32, &node.file)))
+        .collect::<HashMap<u32, &PathBuf>>();
+    for node in nodes.iter() {
+        if let Some((bus_port, line)) = node.bus_port {
+            if let Some(owner) = client_ports.get(&bus_port) {
+                findings.push(finding(
+                    Severity::Error,
+                    &node.file,
+                    line,
+                    format!(
+                        "Cluster bus port {} collides with the client port of {}.",
+                        bus_port,
+                        owner.display()
+                    ),
+                ));
+            }
+        }
+    }
+    check_unique(
+        &nodes,
+        "Cluster bus port",
+        |node| node.bus_port,
+        &mut findings,
+    );
+
+    if nodes.is_empty() && findings.is_empty() {
+        findings.push(finding(
+            Severity::Warning,
+            base_dir,
+            0,
+            "No configuration files found.".to_string(),
+        ));
+    }
+
+    findings.sort_by(|a, b| (&a.file, a.line).cmp(&(&b.file, b.line)));
+    Ok(findings)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    // Write the given config files into a fresh directory, returning its path.
+    fn write_confs(dir: &str, files: &[(&str, &str)]) -> PathBuf {
+        let dir_path = PathBuf::from(dir);
+        let _ = fs::remove_dir_all(&dir_path);
+        fs::create_dir_all(dir_path.join("data")).expect("Failed to create test directory.");
+        for (name, content) in files.iter() {
+            fs::write(dir_path.join(name), content).expect("Failed to write test file.");
+        }
+        dir_path
+    }
+
+    fn messages(findings: &[ConfFinding]) -> Vec<String> {
+        findings.iter().map(|f| f.to_string()).collect()
+    }
+
+    #[test]
+    fn test_validate_conf_dir_clean() {
+        let dir = write_confs(
+            "./test_validate_clean",
+            &[
+                (
+                    "a.conf",
+                    "port 7000\ncluster-enabled yes\ndir ./test_validate_clean/data\ncluster-config-file nodes-7000.conf\ndbfilename 7000.rdb\n",
+                ),
+                (
+                    "b.conf",
+                    "port 7001\ncluster-enabled yes\ndir ./test_validate_clean/data\ncluster-config-file nodes-7001.conf\ndbfilename 7001.rdb\n",
+                ),
+            ],
+        );
+
+        let findings = validate_conf_dir(&dir).unwrap();
+        assert!(findings.is_empty(), "{:?}", messages(&findings));
+
+        fs::remove_dir_all(&dir).expect("Failed to remove test directory.");
+    }
+
+    #[test]
+    fn test_validate_conf_dir_collisions() {
+        let dir = write_confs(
+            "./test_validate_collisions",
+            &[
+                (
+                    "a.conf",
+                    "port 7000\ncluster-enabled yes\ndir ./test_validate_collisions/data\n",
+                ),
+                (
+                    "b.conf",
+                    "cluster-enabled yes\ndir ./test_validate_collisions/data\nport 7000\n",
+                ),
+                (
+                    "c.conf",
+                    "port 17000\ncluster-enabled no\ndir ./test_validate_collisions/missing\ncluster-config-file c.conf\n",
+                ),
+            ],
+        );
+
+        let findings = validate_conf_dir(&dir).unwrap();
+        let found = messages(&findings);
+        assert!(findings.iter().all(|f| f.severity == Severity::Error));
+        assert!(found
+            .iter()
+            .any(|m| m.contains("b.conf:3: port 7000 is also used by")));
+        let data_dir = env::current_dir().unwrap().join(dir.join("data"));
+        let collision = findings
+            .iter()
+            .find(|f| f.message.starts_with("cluster-config-file"))
+            .expect("Missing cluster-config-file collision.");
+        assert_eq!(collision.file, dir.join("b.conf"));
+        assert_eq!(collision.line, 0);
+        assert_eq!(
+            collision.message,
+            format!(
+                "cluster-config-file {} is also used by {}.",
+                data_dir.join("nodes.conf").display(),
+                dir.join("a.conf").display()
+            )
+        );
+        let collision = findings
+            .iter()
+            .find(|f| f.message.starts_with("dir/dbfilename"))
+            .expect("Missing dir/dbfilename collision.");
+        assert_eq!(collision.file, dir.join("b.conf"));
+        assert_eq!(collision.line, 0);
+        assert_eq!(
+            collision.message,
+            format!(
+                "dir/dbfilename {} is also used by {}.",
+                data_dir.join("dump.rdb").display(),
+                dir.join("a.conf").display()
+            )
+        );
+        assert!(found
+            .iter()
+            .any(|m| m.contains("c.conf:2: cluster-enabled yes is not set.")));
+        assert!(found.iter().any(|m| m.contains("c.conf:3: dir")));
+        assert!(found
+            .iter()
+            .any(|m| m.contains("a.conf:1: Cluster bus port 17000 collides with the client port")));
+
+        fs::remove_dir_all(&dir).expect("Failed to remove test directory.");
+    }
+
+    #[test]
+    fn test_validate_conf_dir_parse_errors_and_missing_port() {
+        let dir = write_confs(
+            "./test_validate_parse",
+            &[
+                (
+                    "a.conf",
+                    "cluster-enabled yes\ndir ./test_validate_parse/data\nsave 60 1\nsave 30 5\nappendonly no\nappendonly yes\n",
+                ),
+                ("b.conf", "port 7001\nrequirepass \"oops\n"),
+                ("c.conf", "port 60000\ncluster-enabled yes\ndir ./test_validate_parse/data\ndbfilename c.rdb\ncluster-config-file c-nodes.conf\n"),
+            ],
+        );
+
+        let findings = validate_conf_dir(&dir).unwrap();
+        let found = messages(&findings);
+        let warnings = findings
+            .iter()
+            .filter(|f| f.severity == Severity::Warning)
+            .collect::<Vec<&ConfFinding>>();
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].line, 6);
+        assert!(found
+            .iter()
+            .any(|m| m.ends_with("a.conf: No port is set; every node needs its own port.")));
+        assert!(found
+            .iter()
+            .any(|m| m.contains("b.conf:2: Unbalanced quotes")));
+        assert!(found
+            .iter()
+            .any(|m| m.contains("c.conf:1: Cluster bus port 70000 is out of range")));
+
+        fs::remove_dir_all(&dir).expect("Failed to remove test directory.");
+    }
+
+    #[test]
+    fn test_validate_conf_dir_not_a_directory() {
+        assert!(validate_conf_dir(&PathBuf::from("./Cargo.toml")).is_err());
+    }
+}
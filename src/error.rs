@@ -0,0 +1,146 @@
+use std::{error::Error, fmt};
+
+/// The underlying error that led to an RrError, if there was one.
+pub type ErrorSource = Box<dyn Error + Send + Sync + 'static>;
+
+/// Every way an rr command can fail. Each variant carries a message
+/// describing what rr was doing and, when the failure came from
+/// somewhere else, the error that caused it.
+#[derive(Debug)]
+pub enum RrError {
+    /// A config file, config directory or command line option is wrong.
+    Config(String, Option<ErrorSource>),
+    /// A redis-server process could not be started, signalled or found.
+    Process(String, Option<ErrorSource>),
+    /// The state rr keeps about running servers could not be used, or
+    /// its output could not be written.
+    State(String, Option<ErrorSource>),
+    /// Talking to a node failed or it sent back something unexpected.
+    Protocol(String, Option<ErrorSource>),
+    /// The cluster itself is unhealthy or could not be brought up.
+    ClusterHealth(String, Option<ErrorSource>),
+}
+
+impl RrError {
+    /// The process exit code for the error, taken from sysexits.h
+    /// so scripts can tell the kinds of failure apart.
+    ///
+    /// # Examples
+    /// ```
+    /// let err = RrError::Config("No port set.".to_string(), None);
+    /// assert_eq!(err.exit_code(), 78);
+    /// ```
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            RrError::ClusterHealth(_, _) => 69,
+            RrError::Process(_, _) => 71,
+            RrError::State(_, _) => 74,
+            RrError::Protocol(_, _) => 76,
+            RrError::Config(_, _) => 78,
+        }
+    }
+
+    /// A short name for the kind of error, used in JSON output.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            RrError::Config(_, _) => "config",
+            RrError::Process(_, _) => "process",
+            RrError::State(_, _) => "state",
+            RrError::Protocol(_, _) => "protocol",
+            RrError::ClusterHealth(_, _) => "cluster-health",
+        }
+    }
+
+    /// The message describing what went wrong.
+    pub fn message(&self) -> &str {
+        match self {
+            RrError::Config(message, _)
+            | RrError::Process(message, _)
+            | RrError::State(message, _)
+            | RrError::Protocol(message, _)
+            | RrError::ClusterHealth(message, _) => message,
+        }
+    }
+
+    /// Every error in the chain below this one, closest cause first.
+    ///
+    /// # Examples
+    /// ```
+    /// for cause in err.causes() {
+    ///     error!("caused by: {}", cause);
+    /// }
+    /// ```
+    pub fn causes(&self) -> Vec<String> {
+        let mut causes = vec![];
+        let mut current = self.source();
+        while let Some(cause) = current {
+            causes.push(cause.to_string());
+            current = cause.source();
+        }
+        causes
+    }
+}
+
+impl fmt::Display for RrError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message())
+    }
+}
+
+impl Error for RrError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            RrError::Config(_, source)
+            | RrError::Process(_, source)
+            | RrError::State(_, source)
+            | RrError::Protocol(_, source)
+            | RrError::ClusterHealth(_, source) => source
+                .as_ref()
+                .map(|source| source.as_ref() as &(dyn Error + 'static)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::io;
+
+    use super::*;
+
+    #[test]
+    fn test_exit_codes_are_distinct() {
+        let errors = [
+            RrError::Config(String::new(), None),
+            RrError::Process(String::new(), None),
+            RrError::State(String::new(), None),
+            RrError::Protocol(String::new(), None),
+            RrError::ClusterHealth(String::new(), None),
+        ];
+        let mut codes = errors.iter().map(|e| e.exit_code()).collect::<Vec<i32>>();
+        codes.sort_unstable();
+        codes.dedup();
+        assert_eq!(codes.len(), errors.len());
+        assert!(!codes.contains(&0) && !codes.contains(&1));
+    }
+
+    #[test]
+    fn test_source_chain() {
+        let root = io::Error::new(io::ErrorKind::PermissionDenied, "permission denied");
+        let inner = RrError::State(
+            "Failed to write servers.pid.".to_string(),
+            Some(Box::new(root)),
+        );
+        let outer = RrError::Process(
+            "Failed to stop the cluster.".to_string(),
+            Some(Box::new(inner)),
+        );
+
+        assert_eq!(outer.to_string(), "Failed to stop the cluster.");
+        assert_eq!(
+            outer.causes(),
+            vec!["Failed to write servers.pid.", "permission denied"]
+        );
+        assert_eq!(outer.exit_code(), 71);
+    }
+}
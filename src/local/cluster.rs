@@ -0,0 +1,201 @@
+use std::{fs, path::PathBuf, sync::Mutex};
+
+use mocktopus::macros::mockable;
+
+use crate::{error::RrError, local::dotdir::get_or_create_local_config_dir};
+
+// Name of the cluster used when no other is selected.
+pub const DEFAULT_CLUSTER_NAME: &str = "default";
+
+// Name of the directory, inside the config directory, holding a
+// directory per cluster with its state and node logs.
+const CLUSTERS_DIR_NAME: &str = "clusters";
+
+// The cluster this invocation works on, chosen with --cluster.
+static SELECTED_CLUSTER: Mutex<Option<String>> = Mutex::new(None);
+
+/// Check a cluster name can name a directory: letters, digits, dashes
+/// and underscores only.
+fn validate_cluster_name(name: &str) -> Result<(), RrError> {
+    let valid = !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if !valid {
+        return Err(RrError::Config(
+            format!(
+                "Invalid cluster name \"{}\". Use letters, digits, dashes and underscores.",
+                name
+            ),
+            None,
+        ));
+    }
+    Ok(())
+}
+
+/// Choose the cluster the rest of this invocation works on.
+///
+/// # Arguments
+/// * `name` - Name of the cluster.
+///
+/// # Examples
+/// ```
+/// select_cluster("payments").expect("Invalid cluster name.");
+/// ```
+pub fn select_cluster(name: &str) -> Result<(), RrError> {
+    validate_cluster_name(name)?;
+    match SELECTED_CLUSTER.lock() {
+        Ok(mut selected) => {
+            *selected = Some(name.to_string());
+            Ok(())
+        }
+        Err(_) => Err(RrError::State(
+            "Failed to select the cluster.".to_string(),
+            None,
+        )),
+    }
+}
+
+/// Get the name of the cluster this invocation works on, the default
+/// cluster unless another was selected.
+///
+/// # Examples
+/// ```
+/// let state = read_cluster_state(&current_cluster()).expect("Failed to read state.");
+/// ```
+#[mockable]
+pub fn current_cluster() -> String {
+    SELECTED_CLUSTER
+        .lock()
+        .ok()
+        .and_then(|selected| selected.clone())
+        .unwrap_or_else(|| DEFAULT_CLUSTER_NAME.to_string())
+}
+
+fn clusters_dir() -> Result<PathBuf, RrError> {
+    Ok(get_or_create_local_config_dir()?.join(CLUSTERS_DIR_NAME))
+}
+
+/// Check rr knows a cluster, so commands working on an existing cluster
+/// never create one from a mistyped name. The default cluster is always
+/// known, others once they have been started.
+///
+/// # Arguments
+/// * `cluster` - Name of the cluster.
+///
+/// # Examples
+/// ```
+/// check_cluster_known("payments").expect("Unknown cluster.");
+/// ```
+pub fn check_cluster_known(cluster: &str) -> Result<(), RrError> {
+    if cluster == DEFAULT_CLUSTER_NAME || clusters_dir()?.join(cluster).is_dir() {
+        return Ok(());
+    }
+    Err(RrError::Config(
+        format!(
+            "Unknown cluster \"{}\". Run rr cluster ls to list the known clusters.",
+            cluster
+        ),
+        None,
+    ))
+}
+
+/// Get the directory of a cluster's state and node logs, creating it if
+/// needed.
+///
+/// # Arguments
+/// * `cluster` - Name of the cluster.
+///
+/// # Examples
+/// ```
+/// let cluster_dir = get_or_create_cluster_dir(DEFAULT_CLUSTER_NAME).expect("Failed to create cluster directory.");
+/// ```
+pub fn get_or_create_cluster_dir(cluster: &str) -> Result<PathBuf, RrError> {
+    let cluster_dir = clusters_dir()?.join(cluster);
+    if let Err(err) = fs::create_dir_all(&cluster_dir) {
+        return Err(RrError::State(
+            format!(
+                "Failed to create cluster directory {}.",
+                cluster_dir.display()
+            ),
+            Some(Box::new(err)),
+        ));
+    }
+    Ok(cluster_dir)
+}
+
+/// List the name of every cluster rr has a directory for, sorted.
+///
+/// # Examples
+/// ```
+/// let clusters = list_clusters().expect("Failed to list clusters.");
+/// ```
+pub fn list_clusters() -> Result<Vec<String>, RrError> {
+    let clusters_dir = clusters_dir()?;
+    if !clusters_dir.exists() {
+        return Ok(vec![]);
+    }
+    let entries = fs::read_dir(&clusters_dir).map_err(|err| {
+        RrError::State(
+            format!(
+                "Failed to read cluster directory {}.",
+                clusters_dir.display()
+            ),
+            Some(Box::new(err)),
+        )
+    })?;
+    let mut clusters = entries
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.path().is_dir())
+        .filter_map(|entry| entry.file_name().into_string().ok())
+        .filter(|name| validate_cluster_name(name).is_ok())
+        .collect::<Vec<String>>();
+    clusters.sort_unstable();
+    Ok(clusters)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::env;
+
+    use mocktopus::mocking::{MockResult, Mockable};
+
+    use super::*;
+
+    #[test]
+    fn test_validate_cluster_name() {
+        assert!(validate_cluster_name("default").is_ok());
+        assert!(validate_cluster_name("payments-api_2").is_ok());
+        for name in ["", "..", "a/b", "with space"] {
+            assert!(matches!(
+                validate_cluster_name(name),
+                Err(RrError::Config(..))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_list_clusters() {
+        let config_dir = env::temp_dir().join(format!("rr-clusters-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&config_dir);
+        fs::create_dir_all(&config_dir).unwrap();
+        let mocked_dir = config_dir.clone();
+        get_or_create_local_config_dir
+            .mock_safe(move || MockResult::Return(Ok(mocked_dir.clone())));
+
+        assert!(list_clusters().unwrap().is_empty());
+        get_or_create_cluster_dir("search").unwrap();
+        get_or_create_cluster_dir("default").unwrap();
+        fs::write(config_dir.join(CLUSTERS_DIR_NAME).join("notes.txt"), "").unwrap();
+        assert_eq!(list_clusters().unwrap(), vec!["default", "search"]);
+        assert!(check_cluster_known("search").is_ok());
+        assert!(check_cluster_known("default").is_ok());
+        assert!(matches!(
+            check_cluster_known("serach"),
+            Err(RrError::Config(..))
+        ));
+        assert_eq!(list_clusters().unwrap(), vec!["default", "search"]);
+        fs::remove_dir_all(config_dir).unwrap();
+    }
+}
@@ -5,6 +5,8 @@ use std::{
 
 use mocktopus::macros::mockable;
 
+use crate::error::RrError;
+
 // The hidden directory name of the directory
 // to be used to store configuration items.
 const CONFIG_DIR_NAME: &str = ".rr";
@@ -47,19 +49,25 @@ fn create_homepath_dir(dir: &PathBuf) -> io::Result<()> {
 /// get_or_create_local_config_dir().expect("Failed to get or create the homepat config directory.");
 /// ```
 #[mockable]
-pub fn get_or_create_local_config_dir() -> Result<PathBuf, String> {
+pub fn get_or_create_local_config_dir() -> Result<PathBuf, RrError> {
     match get_home_dir() {
         Some(dir) => {
             let home_path = Path::new(&dir).join(CONFIG_DIR_NAME);
             if !home_path.exists() {
-                if create_homepath_dir(&home_path).is_err() {
-                    return Err("Failed to create non-existent config directory .rr.".to_string());
+                if let Err(err) = create_homepath_dir(&home_path) {
+                    return Err(RrError::State(
+                        "Failed to create non-existent config directory .rr.".to_string(),
+                        Some(Box::new(err)),
+                    ));
                 }
             }
 
             Ok(home_path)
         }
-        None => Err("No value found for $HOME, cannot create CLI context.".to_string()),
+        None => Err(RrError::State(
+            "No value found for $HOME, cannot create CLI context.".to_string(),
+            None,
+        )),
     }
 }
 
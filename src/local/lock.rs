@@ -0,0 +1,261 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    os::unix::io::AsRawFd,
+    path::Path,
+    process,
+    sync::atomic::{AtomicBool, Ordering},
+    thread,
+    time::{Duration, Instant},
+};
+
+use mocktopus::macros::mockable;
+
+use crate::{
+    error::RrError,
+    local::{dotdir::get_or_create_local_config_dir, state::state_file_path},
+};
+
+// How long to wait for another rr operation on the same cluster to
+// finish before giving up.
+pub const LOCK_TIMEOUT: Duration = Duration::from_secs(60);
+
+// Delay between attempts to take a lock held by another rr operation.
+const LOCK_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+// Name of the file, inside the config directory, locking the ports
+// claimed by every cluster.
+const CLUSTERS_LOCK_FILE_NAME: &str = "clusters.lock";
+
+// Whether this process holds a state lock, so nested operations do not
+// wait on their own lock.
+static LOCK_HELD: AtomicBool = AtomicBool::new(false);
+
+// Whether this process holds the lock on the ports of every cluster.
+static CLUSTERS_LOCK_HELD: AtomicBool = AtomicBool::new(false);
+
+/// An exclusive lock on rr's state, released when dropped. Only one rr
+/// process at a time may hold it, so reading the state, acting on the
+/// servers and writing the state back happen as one step. The default
+/// lock holds nothing, as when a process takes a lock it already holds.
+#[derive(Debug, Default)]
+pub struct StateLock {
+    file: Option<File>,
+    held: Option<&'static AtomicBool>,
+}
+
+impl Drop for StateLock {
+    fn drop(&mut self) {
+        // Closing the file releases the lock.
+        if let (Some(_), Some(held)) = (self.file.take(), self.held) {
+            held.store(false, Ordering::SeqCst);
+        }
+    }
+}
+
+/// Take the lock without waiting. Returns whether it was free.
+fn try_flock(file: &File) -> io::Result<bool> {
+    // Safe as the descriptor stays open for the duration of the call.
+    match unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) } {
+        0 => Ok(true),
+        _ => {
+            let err = io::Error::last_os_error();
+            match err.kind() {
+                io::ErrorKind::WouldBlock => Ok(false),
+                _ => Err(err),
+            }
+        }
+    }
+}
+
+/// PID of the rr process holding a lock, as it recorded it.
+fn lock_holder(lock_path: &Path) -> String {
+    fs::read_to_string(lock_path)
+        .ok()
+        .map(|text| text.trim().to_string())
+        .filter(|pid| !pid.is_empty())
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
+/// Whether this process holds the lock on a cluster's state.
+///
+/// # Examples
+/// ```
+/// if holds_state_lock() {
+///     write_data_to_pid_file(&current)?;
+/// }
+/// ```
+#[mockable]
+pub fn holds_state_lock() -> bool {
+    LOCK_HELD.load(Ordering::SeqCst)
+}
+
+/// Take the lock on the given file, waiting for the rr process holding it
+/// to finish, and record this process as its holder.
+fn take_lock(
+    lock_path: &Path,
+    timeout: Duration,
+    held: &'static AtomicBool,
+) -> Result<StateLock, RrError> {
+    let lock_error = |err: io::Error| {
+        RrError::State(
+            format!("Failed to lock {}.", lock_path.display()),
+            Some(Box::new(err)),
+        )
+    };
+    // The holder's PID is only cleared once the lock is ours.
+    let mut file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(lock_path)
+        .map_err(lock_error)?;
+
+    let deadline = Instant::now() + timeout;
+    let mut warned = false;
+    while !try_flock(&file).map_err(lock_error)? {
+        let holder = lock_holder(lock_path);
+        if Instant::now() >= deadline {
+            return Err(RrError::State(
+                format!(
+                    "Another rr operation is in progress (pid {}) and did not finish within {} seconds.",
+                    holder,
+                    timeout.as_secs()
+                ),
+                None,
+            ));
+        }
+        if !warned {
+            warn!(
+                "Another rr operation is in progress (pid {}). Waiting for it to finish.",
+                holder
+            );
+            warned = true;
+        }
+        thread::sleep(LOCK_POLL_INTERVAL);
+    }
+
+    file.set_len(0)
+        .and_then(|_| writeln!(file, "{}", process::id()))
+        .map_err(lock_error)?;
+    held.store(true, Ordering::SeqCst);
+    Ok(StateLock {
+        file: Some(file),
+        held: Some(held),
+    })
+}
+
+/// Lock a cluster's state against other rr processes, waiting for the
+/// operation holding it to finish. Taking the lock again from the process
+/// holding it returns at once.
+///
+/// # Arguments
+/// * `cluster` - Name of the cluster whose state to lock.
+/// * `timeout` - How long to wait for another operation to finish.
+///
+/// # Examples
+/// ```
+/// let _lock = lock_state(&current_cluster(), LOCK_TIMEOUT)?;
+/// ```
+pub fn lock_state(cluster: &str, timeout: Duration) -> Result<StateLock, RrError> {
+    if holds_state_lock() {
+        return Ok(StateLock::default());
+    }
+    take_lock(
+        &state_file_path(cluster)?.with_extension("lock"),
+        timeout,
+        &LOCK_HELD,
+    )
+}
+
+/// Lock the ports claimed by every cluster against other rr processes,
+/// so checking a cluster's ports are free and recording its servers on
+/// them happen as one step. Taking the lock again from the process
+/// holding it returns at once.
+///
+/// # Arguments
+/// * `timeout` - How long to wait for another operation to finish.
+///
+/// # Examples
+/// ```
+/// let _lock = lock_clusters(LOCK_TIMEOUT)?;
+/// ```
+pub fn lock_clusters(timeout: Duration) -> Result<StateLock, RrError> {
+    if CLUSTERS_LOCK_HELD.load(Ordering::SeqCst) {
+        return Ok(StateLock::default());
+    }
+    take_lock(
+        &get_or_create_local_config_dir()?.join(CLUSTERS_LOCK_FILE_NAME),
+        timeout,
+        &CLUSTERS_LOCK_HELD,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::{env, process::Command};
+
+    use mocktopus::mocking::{MockResult, Mockable};
+
+    use crate::local::dotdir::get_or_create_local_config_dir;
+
+    use super::*;
+
+    #[test]
+    fn test_lock_state_waits_for_other_process() {
+        let config_dir = env::temp_dir().join(format!("rr-lock-{}", process::id()));
+        fs::create_dir_all(&config_dir).unwrap();
+        let mocked_dir = config_dir.clone();
+        get_or_create_local_config_dir
+            .mock_safe(move || MockResult::Return(Ok(mocked_dir.clone())));
+        let lock_path = state_file_path("default").unwrap().with_extension("lock");
+        fs::write(&lock_path, "4242\n").unwrap();
+
+        // -o keeps the lock from being inherited by sleep, so killing
+        // flock releases it.
+        let mut holder = Command::new("flock")
+            .arg("-o")
+            .arg(&lock_path)
+            .arg("sleep")
+            .arg("5")
+            .spawn()
+            .unwrap();
+        thread::sleep(Duration::from_millis(200));
+        let result = lock_state("default", Duration::from_millis(300));
+        holder.kill().unwrap();
+        holder.wait().unwrap();
+        match result {
+            Err(RrError::State(msg, _)) => assert!(msg.contains("(pid 4242)")),
+            other => panic!("Unexpected result: {:?}", other),
+        }
+
+        let lock = lock_state("default", Duration::from_millis(300)).unwrap();
+        assert!(holds_state_lock());
+        assert_eq!(lock_holder(&lock_path), process::id().to_string());
+        assert!(lock_state("default", Duration::ZERO).is_ok());
+        drop(lock);
+        assert!(!holds_state_lock());
+        fs::remove_dir_all(config_dir).unwrap();
+    }
+
+    #[test]
+    fn test_lock_clusters() {
+        let config_dir = env::temp_dir().join(format!("rr-clusters-lock-{}", process::id()));
+        fs::create_dir_all(&config_dir).unwrap();
+        let mocked_dir = config_dir.clone();
+        get_or_create_local_config_dir
+            .mock_safe(move || MockResult::Return(Ok(mocked_dir.clone())));
+
+        let lock = lock_clusters(Duration::ZERO).unwrap();
+        assert_eq!(
+            lock_holder(&config_dir.join(CLUSTERS_LOCK_FILE_NAME)),
+            process::id().to_string()
+        );
+        assert!(lock_clusters(Duration::ZERO).is_ok());
+        drop(lock);
+        assert!(!CLUSTERS_LOCK_HELD.load(Ordering::SeqCst));
+        fs::remove_dir_all(config_dir).unwrap();
+    }
+}
@@ -0,0 +1,398 @@
+use std::{
+    fs,
+    io::{BufRead, BufReader, Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use mocktopus::macros::mockable;
+use serde::Serialize;
+
+use crate::{error::RrError, local::cluster::get_or_create_cluster_dir};
+
+// Name of the directory, inside a cluster's directory, holding
+// the output of every node.
+const LOG_DIR_NAME: &str = "logs";
+
+// Number of log lines shown when a node fails to start.
+pub const STARTUP_LOG_LINES: usize = 20;
+
+/// Get the path of the file a node's stdout and stderr are written to,
+/// creating the cluster's log directory if needed.
+///
+/// # Arguments
+/// * `cluster` - Name of the cluster the node belongs to.
+/// * `port` - Port of the node.
+///
+/// # Examples
+/// ```
+/// let log_path = node_log_path(DEFAULT_CLUSTER_NAME, 7000).expect("Failed to create log directory.");
+/// ```
+pub fn node_log_path(cluster: &str, port: u16) -> Result<PathBuf, RrError> {
+    Ok(get_or_create_log_dir(cluster)?.join(format!("{}.log", port)))
+}
+
+fn get_or_create_log_dir(cluster: &str) -> Result<PathBuf, RrError> {
+    let log_dir = get_or_create_cluster_dir(cluster)?.join(LOG_DIR_NAME);
+    if let Err(err) = fs::create_dir_all(&log_dir) {
+        return Err(RrError::State(
+            format!("Failed to create log directory {}.", log_dir.display()),
+            Some(Box::new(err)),
+        ));
+    }
+    Ok(log_dir)
+}
+
+/// List the log file of every node of a cluster that was ever started,
+/// running or not, ordered by port.
+///
+/// # Arguments
+/// * `cluster` - Name of the cluster.
+///
+/// # Examples
+/// ```
+/// let logs = list_node_logs(DEFAULT_CLUSTER_NAME).expect("Failed to list logs.");
+/// ```
+pub fn list_node_logs(cluster: &str) -> Result<Vec<(u16, PathBuf)>, RrError> {
+    let log_dir = get_or_create_log_dir(cluster)?;
+    let entries = fs::read_dir(&log_dir).map_err(|err| {
+        RrError::State(
+            format!("Failed to read log directory {}.", log_dir.display()),
+            Some(Box::new(err)),
+        )
+    })?;
+    let mut logs = entries
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "log"))
+        .filter_map(|path| {
+            let port = u16::from_str(path.file_stem()?.to_str()?).ok()?;
+            Some((port, path))
+        })
+        .collect::<Vec<(u16, PathBuf)>>();
+    logs.sort_unstable();
+    Ok(logs)
+}
+
+/// Open a node's log file for appending, creating it if needed.
+///
+/// # Arguments
+/// * `log_path` - Path of the log file.
+///
+/// # Examples
+/// ```
+/// let log_file = open_node_log(&log_path).expect("Failed to open log file.");
+/// ```
+pub fn open_node_log(log_path: &Path) -> Result<fs::File, RrError> {
+    fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(log_path)
+        .map_err(|err| {
+            RrError::State(
+                format!("Failed to open log file {}.", log_path.display()),
+                Some(Box::new(err)),
+            )
+        })
+}
+
+/// Read the last lines of a log file.
+///
+/// # Arguments
+/// * `log_path` - Path of the log file.
+/// * `count` - Number of lines to read from the end.
+///
+/// # Examples
+/// ```
+/// let lines = tail_lines(&log_path, STARTUP_LOG_LINES).expect("Failed to read log file.");
+/// ```
+pub fn tail_lines(log_path: &Path, count: usize) -> Result<Vec<String>, RrError> {
+    let file = fs::File::open(log_path).map_err(|err| {
+        RrError::State(
+            format!("Failed to read log file {}.", log_path.display()),
+            Some(Box::new(err)),
+        )
+    })?;
+    let lines = BufReader::new(file)
+        .lines()
+        .map_while(Result::ok)
+        .collect::<Vec<String>>();
+    Ok(lines[lines.len().saturating_sub(count)..].to_vec())
+}
+
+/// One line of a node's log. Lines Redis writes without a timestamp,
+/// such as its startup banner, take the timestamp of the line before.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct LogLine {
+    pub port: u16,
+    /// Milliseconds since the epoch, in the local time Redis logs in.
+    pub timestamp: Option<i64>,
+    pub text: String,
+}
+
+// Days from 1970-01-01 to the given date of the proleptic Gregorian
+// calendar.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = if year >= 0 { year } else { year - 399 } / 400;
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+/// Parse the timestamp Redis starts its log lines with, as in
+/// `4711:M 17 Oct 2026 10:15:42.123 * Ready to accept connections`.
+///
+/// # Arguments
+/// * `line` - The log line.
+///
+/// # Examples
+/// ```
+/// let timestamp = parse_log_timestamp("4711:M 17 Oct 2026 10:15:42.123 * Ready");
+/// ```
+pub fn parse_log_timestamp(line: &str) -> Option<i64> {
+    let fields = line.split_whitespace().take(5).collect::<Vec<&str>>();
+    if fields.len() < 5 || !fields[0].contains(':') {
+        return None;
+    }
+    let day = i64::from_str(fields[1]).ok()?;
+    let month = [
+        "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+    ]
+    .iter()
+    .position(|name| *name == fields[2])? as i64
+        + 1;
+    let year = i64::from_str(fields[3]).ok()?;
+    let (clock, millis) = fields[4].split_once('.')?;
+    let clock = clock
+        .split(':')
+        .map(i64::from_str)
+        .collect::<Result<Vec<i64>, _>>()
+        .ok()?;
+    if clock.len() != 3 {
+        return None;
+    }
+    let seconds =
+        days_from_civil(year, month, day) * 86400 + clock[0] * 3600 + clock[1] * 60 + clock[2];
+    Some(seconds * 1000 + i64::from_str(millis).ok()?)
+}
+
+/// The current time in milliseconds since the epoch, shifted to local
+/// time so it compares with the timestamps Redis logs.
+///
+/// # Examples
+/// ```
+/// let an_hour_ago = local_now_millis() - 3_600_000;
+/// ```
+#[mockable]
+pub fn local_now_millis() -> i64 {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_millis() as i64)
+        .unwrap_or_default();
+    // Redis logs in local time; localtime_r knows the offset without
+    // pulling in a time zone database.
+    let seconds = (now / 1000) as libc::time_t;
+    let mut local = unsafe { std::mem::zeroed::<libc::tm>() };
+    let offset = if unsafe { libc::localtime_r(&seconds, &mut local) }.is_null() {
+        0
+    } else {
+        local.tm_gmtoff
+    };
+    now + offset * 1000
+}
+
+/// A node's log file, read incrementally so it can be followed.
+#[derive(Debug)]
+pub struct NodeLog {
+    pub port: u16,
+    pub path: PathBuf,
+    offset: u64,
+    last_timestamp: Option<i64>,
+}
+
+impl NodeLog {
+    pub fn new(port: u16, path: PathBuf) -> NodeLog {
+        NodeLog {
+            port,
+            path,
+            offset: 0,
+            last_timestamp: None,
+        }
+    }
+
+    /// Read the complete lines written since the last read. A file that
+    /// shrank was truncated and is read again from the start.
+    ///
+    /// # Examples
+    /// ```
+    /// let lines = NodeLog::new(7000, log_path).read_new().expect("Failed to read log.");
+    /// ```
+    pub fn read_new(&mut self) -> Result<Vec<LogLine>, RrError> {
+        let read_error = |err| {
+            RrError::State(
+                format!("Failed to read log file {}.", self.path.display()),
+                Some(Box::new(err)),
+            )
+        };
+        let mut file = fs::File::open(&self.path).map_err(read_error)?;
+        let length = file.metadata().map_err(read_error)?.len();
+        if length < self.offset {
+            self.offset = 0;
+        }
+        file.seek(SeekFrom::Start(self.offset))
+            .map_err(read_error)?;
+        let mut content = vec![];
+        file.read_to_end(&mut content).map_err(read_error)?;
+
+        // A line still being written is left for the next read.
+        let complete = match content.iter().rposition(|byte| *byte == b'\n') {
+            Some(end) => end + 1,
+            None => 0,
+        };
+        self.offset += complete as u64;
+        Ok(String::from_utf8_lossy(&content[..complete])
+            .lines()
+            .map(|text| {
+                if let Some(timestamp) = parse_log_timestamp(text) {
+                    self.last_timestamp = Some(timestamp);
+                }
+                LogLine {
+                    port: self.port,
+                    timestamp: self.last_timestamp,
+                    text: text.to_string(),
+                }
+            })
+            .collect())
+    }
+}
+
+/// Merge the lines of several logs into one timeline ordered by
+/// timestamp. Lines of one log keep their order, and lines without any
+/// timestamp come first.
+///
+/// # Arguments
+/// * `logs` - The lines of every log.
+///
+/// # Examples
+/// ```
+/// let timeline = merge_timelines(vec![first.read_new()?, second.read_new()?]);
+/// ```
+pub fn merge_timelines(logs: Vec<Vec<LogLine>>) -> Vec<LogLine> {
+    let mut timeline = logs.into_iter().flatten().collect::<Vec<LogLine>>();
+    timeline.sort_by_key(|line| line.timestamp);
+    timeline
+}
+
+#[cfg(test)]
+mod tests {
+
+    use mocktopus::mocking::{MockResult, Mockable};
+
+    use crate::local::dotdir::get_or_create_local_config_dir;
+
+    use super::*;
+
+    #[test]
+    fn test_node_log_path() {
+        get_or_create_local_config_dir
+            .mock_safe(|| MockResult::Return(Ok(PathBuf::from("./logs-test"))));
+
+        let log_path = node_log_path("sample", 7000).unwrap();
+        assert_eq!(
+            log_path,
+            PathBuf::from("./logs-test/clusters/sample/logs/7000.log")
+        );
+        assert!(log_path.parent().unwrap().is_dir());
+
+        fs::remove_dir_all("./logs-test").expect("Failed to delete test directory.");
+    }
+
+    #[test]
+    fn test_tail_lines() {
+        let log_path = PathBuf::from("tail-test.log");
+        fs::write(&log_path, "one\ntwo\nthree\n").unwrap();
+
+        assert_eq!(tail_lines(&log_path, 2).unwrap(), vec!["two", "three"]);
+        assert_eq!(tail_lines(&log_path, 10).unwrap().len(), 3);
+
+        fs::remove_file(&log_path).unwrap();
+        assert!(tail_lines(&log_path, 2).is_err());
+    }
+
+    #[test]
+    fn test_list_node_logs() {
+        get_or_create_local_config_dir
+            .mock_safe(|| MockResult::Return(Ok(PathBuf::from("./list-logs-test"))));
+        let log_dir = PathBuf::from("./list-logs-test/clusters/sample/logs");
+        fs::create_dir_all(&log_dir).unwrap();
+        for name in ["7001.log", "7000.log", "notes.txt", "other.log"] {
+            fs::write(log_dir.join(name), "").unwrap();
+        }
+
+        let logs = list_node_logs("sample").unwrap();
+        fs::remove_dir_all("./list-logs-test").expect("Failed to delete test directory.");
+        assert_eq!(
+            logs.iter().map(|(port, _)| *port).collect::<Vec<u16>>(),
+            vec![7000, 7001]
+        );
+    }
+
+    #[test]
+    fn test_parse_log_timestamp() {
+        let timestamp = parse_log_timestamp("4711:M 01 Jan 1970 00:01:02.345 * Ready").unwrap();
+        assert_eq!(timestamp, 62345);
+        assert_eq!(
+            parse_log_timestamp("4711:S 29 Feb 2024 12:00:00.000 # x"),
+            Some(1709208000000)
+        );
+        assert_eq!(parse_log_timestamp("                _._"), None);
+        assert_eq!(
+            parse_log_timestamp("4711:M 17 Foo 2026 10:15:42.123 *"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_node_log_read_new() {
+        let log_path = PathBuf::from("follow-test.log");
+        fs::write(
+            &log_path,
+            "banner\n1:M 01 Jan 1970 00:00:01.000 * one\ncontinued\npartial",
+        )
+        .unwrap();
+        let mut log = NodeLog::new(7000, log_path.clone());
+
+        let lines = log.read_new().unwrap();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0].timestamp, None);
+        assert_eq!(lines[2].timestamp, Some(1000));
+
+        let mut file = fs::OpenOptions::new().append(true).open(&log_path).unwrap();
+        std::io::Write::write_all(&mut file, b" line\n").unwrap();
+        let lines = log.read_new().unwrap();
+        fs::remove_file(&log_path).unwrap();
+        assert_eq!(lines.len(), 1);
+        assert_eq!(lines[0].text, "partial line");
+        assert_eq!(lines[0].timestamp, Some(1000));
+    }
+
+    #[test]
+    fn test_merge_timelines() {
+        let line = |port, timestamp, text: &str| LogLine {
+            port,
+            timestamp,
+            text: text.to_string(),
+        };
+        let timeline = merge_timelines(vec![
+            vec![line(7000, Some(1), "a"), line(7000, Some(5), "b")],
+            vec![line(7001, None, "banner"), line(7001, Some(3), "c")],
+        ]);
+        let texts = timeline
+            .iter()
+            .map(|l| l.text.as_str())
+            .collect::<Vec<&str>>();
+        assert_eq!(texts, vec!["banner", "a", "c", "b"]);
+    }
+}
@@ -1,2 +1,7 @@
+pub mod cluster;
 pub mod dotdir;
+pub mod lock;
+pub mod logs;
 pub mod pid;
+pub mod process;
+pub mod state;
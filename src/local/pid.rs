@@ -1,286 +1,383 @@
-use std::{
-    collections::HashSet,
-    fs,
-    hash::{Hash, Hasher},
-    io::{self, BufRead, BufReader, Write},
-    path::PathBuf,
-    str::FromStr,
-};
+use std::{collections::HashSet, fmt, path::PathBuf, str::FromStr};
 
 use mocktopus::macros::mockable;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::RrError,
+    local::{
+        cluster::current_cluster,
+        lock::holds_state_lock,
+        process::{process_info, ProcessInfo},
+        state::{read_cluster_state, remove_cluster_state, write_cluster_state, ClusterState},
+    },
+};
 
-use crate::local::dotdir::get_or_create_local_config_dir;
-
-// Name of the PID file used to store PIDs
-// of servers currently running.
-const SERVER_PID_FILE_NAME: &str = "servers.pid";
-
-/// Create the PID file given the path.
-///
-/// # Arguments
-/// * `dir` - The path destination for the PID file.
-///
-/// # Examples
-/// ```
-/// create_pid_file(PathBuf::from("./servers.pid")).expect("Failed to create file.");
-/// ```
-#[mockable]
-#[inline]
-fn create_pid_file(dir: &PathBuf) -> io::Result<fs::File> {
-    fs::File::create(dir)
+/// The part a node plays in the cluster it was started in.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NodeRole {
+    #[default]
+    Primary,
+    Replica,
 }
 
-/// Open an existing PID file given it's target directory.
-///
-/// # Arguments
-/// * `dir` - The path destination of the PID file.
-///
-/// # Examples
-/// ```
-/// open_pid_file(PathBuf::from("./servers.pid")).expect("Failed to open file.");
-/// ```
-#[mockable]
-#[inline]
-fn open_pid_file(dir: &PathBuf) -> io::Result<fs::File> {
-    fs::File::open(dir)
+impl fmt::Display for NodeRole {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NodeRole::Primary => f.pad("primary"),
+            NodeRole::Replica => f.pad("replica"),
+        }
+    }
 }
 
-/// Gets and creates if not exists the file used to store PIDs
-/// of currently running Redis servers.
-///
-/// # Examples
-/// ```
-/// get_or_create_pid_file().expect("Failed to get PID file handler.");
-/// ```
-#[mockable]
-fn get_or_create_pid_file() -> Result<fs::File, String> {
-    let config_file_path = get_or_create_local_config_dir()?;
-    let server_pid_path = config_file_path.join(SERVER_PID_FILE_NAME);
-    if !server_pid_path.exists() {
-        match create_pid_file(&server_pid_path) {
-            Ok(file) => Ok(file),
-            Err(_) => Err("Failed to create lock of server PIDs".to_string()),
-        }
-    } else {
-        match open_pid_file(&server_pid_path) {
-            Ok(file) => Ok(file),
-            Err(v) => {
-                println!("{:?}", v);
-                Err("Failed to open PID lock file.".to_string())
-            }
+impl FromStr for NodeRole {
+    type Err = RrError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "primary" => Ok(NodeRole::Primary),
+            "replica" => Ok(NodeRole::Replica),
+            _ => Err(RrError::State(format!("Unknown node role: {}", s), None)),
         }
     }
 }
 
-/// Stores an entry in the PIDs file, hashed
-/// by the port number to distinguish between the several
-/// servers that could be running at a time.
-#[derive(Debug, PartialEq, Eq)]
+/// Everything recorded about a server rr started, kept in the cluster's
+/// state document. Fields other than the port, PID and role may be
+/// missing from records migrated from older releases.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(default)]
 pub struct PIDEntry {
-    pub port: String,
+    pub port: u16,
     pub pid: u32,
+    pub role: NodeRole,
+    /// Cluster node ID, once the server has joined the cluster.
+    pub node_id: Option<String>,
+    /// When the server started, in clock ticks since boot, telling it
+    /// apart from a later process given the same PID.
+    pub start_time: Option<u64>,
+    /// Configuration file the server was started with.
+    pub conf: Option<PathBuf>,
+    /// Directory the server keeps its data in.
+    pub data_dir: Option<PathBuf>,
+    /// File the server's stdout and stderr are written to.
+    pub log: Option<PathBuf>,
+    /// The redis-server executable the server runs.
+    pub binary: Option<PathBuf>,
+    /// Version of redis-server the server runs.
+    pub version: Option<String>,
 }
 
-impl Hash for PIDEntry {
-    fn hash<H: Hasher>(&self, state: &mut H) {
-        self.pid.hash(state);
+/// Parse a line of the servers.pid file older releases recorded running
+/// servers in, which holds the port and PID separated by whitespace.
+/// Those releases only ever started primaries.
+pub fn parse_pid_line(line: &str) -> Option<PIDEntry> {
+    let vector_entries: Vec<&str> = line.split_whitespace().collect();
+    if vector_entries.len() != 2 {
+        return None;
     }
+    Some(PIDEntry {
+        port: <u16 as FromStr>::from_str(vector_entries[0]).ok()?,
+        pid: <u32 as FromStr>::from_str(vector_entries[1]).ok()?,
+        ..Default::default()
+    })
 }
 
-/// Get a set of the running PIDs and the matching port numbers.
+// Longest command name the kernel keeps for a process.
+const COMM_LEN: usize = 15;
+
+/// Whether a process runs the executable an entry was recorded with.
+/// The command name stands in for the executable when the latter may not
+/// be read. Entries recorded without an executable are matched against
+/// redis-server.
+fn runs_recorded_binary(entry: &PIDEntry, info: &ProcessInfo) -> bool {
+    let binary = match &entry.binary {
+        Some(binary) => binary,
+        None => return info.program().starts_with("redis-server"),
+    };
+    match (&info.exe, binary.file_name()) {
+        (Some(exe), _) => exe == binary,
+        (None, Some(name)) => {
+            let name = name.to_string_lossy();
+            name.as_bytes()[..name.len().min(COMM_LEN)] == *info.comm.as_bytes()
+        }
+        (None, None) => false,
+    }
+}
+
+/// Check a running process is the server an entry was recorded for: it
+/// must run the recorded executable, started at the recorded time, and
+/// mention its configuration file or port on its command line. Redis
+/// rewrites its process title to show the address it listens on, which
+/// usually hides the configuration file. Returns why the process does
+/// not match.
+fn check_recorded_process(entry: &PIDEntry, info: &ProcessInfo) -> Result<(), String> {
+    if !runs_recorded_binary(entry, info) {
+        return Err(format!("it runs {}", info.program()));
+    }
+    if let Some(start_time) = entry.start_time {
+        if start_time != info.start_time {
+            return Err("it started after the server was recorded".to_string());
+        }
+    }
+    let listen_addr = format!(":{}", entry.port);
+    let conf = entry.conf.as_ref().map(|conf| conf.display().to_string());
+    let mentioned = info.cmdline.iter().any(|arg| {
+        arg.contains(&listen_addr)
+            || conf
+                .as_ref()
+                .is_some_and(|conf| arg.contains(conf.as_str()))
+    });
+    if !mentioned {
+        return Err(format!(
+            "its command line mentions neither port {} nor its configuration file",
+            entry.port
+        ));
+    }
+    Ok(())
+}
+
+/// Whether the server an entry was recorded for is still running.
+///
+/// # Arguments
+/// * `entry` - The recorded server.
+///
+/// # Examples
+/// ```
+/// let running = is_recorded_server_running(&entry);
+/// ```
+#[mockable]
+pub fn is_recorded_server_running(entry: &PIDEntry) -> bool {
+    process_info(entry.pid).is_some_and(|info| check_recorded_process(entry, &info).is_ok())
+}
+
+/// Get the servers recorded in the cluster's state document.
+///
+/// Entries whose PID now belongs to another process, for example after a
+/// reboot, are stale: they are reported and never returned, so no
+/// unrelated process is ever signalled, and dropped from the state
+/// document when this process holds the state lock. Entries whose process
+/// is gone are kept so the dead servers can be reported.
 ///
 /// # Examples
 /// ```
 /// get_currently_running_pids().expect("Failed to get pids.");
 /// ```
 #[mockable]
-pub fn get_currently_running_pids() -> Result<HashSet<PIDEntry>, String> {
-    match get_or_create_pid_file() {
-        Ok(file_handler) => {
-            let reader = BufReader::new(file_handler);
-            Ok(reader
-                .lines()
-                .filter(|line| line.is_ok())
-                .map(|line| {
-                    let line_str = line.unwrap();
-                    let vector_entries: Vec<&str> = line_str.split_whitespace().collect();
-                    if vector_entries.len() != 2 {
-                        Err(())
-                    } else {
-                        let pid_result = <u32 as FromStr>::from_str(vector_entries[1]);
-                        match pid_result {
-                            Ok(pid) => Ok(PIDEntry {
-                                port: vector_entries[0].to_string(),
-                                pid: pid,
-                            }),
-                            Err(_) => Err(()),
-                        }
-                    }
-                })
-                .filter(|entry| entry.is_ok())
-                .map(|entry| entry.unwrap())
-                .collect::<HashSet<PIDEntry>>())
-        }
-        Err(msg) => Err(msg),
+pub fn get_currently_running_pids() -> Result<HashSet<PIDEntry>, RrError> {
+    let entries = match read_cluster_state(&current_cluster())? {
+        Some(state) => state.nodes,
+        None => return Ok(HashSet::new()),
+    };
+    let (current, stale): (HashSet<PIDEntry>, HashSet<PIDEntry>) =
+        entries.into_iter().partition(|entry| {
+            match process_info(entry.pid).map(|info| check_recorded_process(entry, &info)) {
+                Some(Err(reason)) => {
+                    warn!(
+                        "Dropping the stale record of the server on port {}: PID {} belongs to another process, as {}.",
+                        entry.port, entry.pid, reason
+                    );
+                    false
+                }
+                _ => true,
+            }
+        });
+    if !stale.is_empty() && holds_state_lock() {
+        write_data_to_pid_file(&current)?;
     }
+    Ok(current)
 }
 
-/// Writes the given PIDs to a lock file, indicating that
-/// there are currently node servers running.
+/// Record the given servers in the cluster's state document, replacing
+/// the servers recorded before. The document keeps the time the cluster
+/// was created and is removed once no server is left.
 ///
 /// # Arguments
-/// * `pid_set` - Set of PIDs to write to the lock file.
+/// * `pid_set` - The servers to record.
 ///
 /// # Examples
 /// ```
-/// let pid_set: HashSet<PIDEntry> = HashSet::new();
-/// pid_set.insert(PIDEnry{ port: "7000", pid: 9 });
-/// write_data_to_pid_file(pid_set).expect("Failed to write PIDs to save file.");
+/// let mut pid_set: HashSet<PIDEntry> = HashSet::new();
+/// pid_set.insert(PIDEntry { port: 7000, pid: 9, ..Default::default() });
+/// write_data_to_pid_file(&pid_set).expect("Failed to record servers.");
 /// ```
 #[mockable]
-pub fn write_data_to_pid_file(pid_set: &HashSet<PIDEntry>) -> Result<(), String> {
-    let config_file_path = get_or_create_local_config_dir()?;
-    let server_pid_path = config_file_path.join(SERVER_PID_FILE_NAME);
-    match create_pid_file(&server_pid_path) {
-        Ok(mut file_handler) => {
-            let file_str: String = pid_set
-                .iter()
-                .map(|entry| format!("{} {}\n", entry.port, entry.pid))
-                .collect();
-
-            match file_handler.write_all(file_str.as_bytes()) {
-                Ok(_) => Ok(()),
-                Err(_) => Err("Failed to write pid locks.".to_string()),
-            }
-        }
-        Err(_) => Err("Failed to create lock of PIDs.".to_string()),
+pub fn write_data_to_pid_file(pid_set: &HashSet<PIDEntry>) -> Result<(), RrError> {
+    let cluster = current_cluster();
+    if pid_set.is_empty() {
+        return remove_cluster_state(&cluster);
     }
+    let mut nodes = pid_set.iter().cloned().collect::<Vec<PIDEntry>>();
+    nodes.sort_unstable_by_key(|entry| entry.port);
+    let state = match read_cluster_state(&cluster)? {
+        Some(state) => ClusterState { nodes, ..state },
+        None => ClusterState::new(&cluster, nodes),
+    };
+    write_cluster_state(&state)
 }
 
 #[cfg(test)]
 mod tests {
 
+    use std::fs;
+
     use mocktopus::mocking::{MockResult, Mockable};
 
-    use super::*;
+    use crate::local::cluster::DEFAULT_CLUSTER_NAME;
 
-    #[test]
-    fn test_get_or_create_pid_file_needs_creation_success() {
-        let sample_path = PathBuf::from("./testdir");
-        get_or_create_local_config_dir
-            .mock_safe(move || MockResult::Return(Ok(sample_path.clone())));
-        create_pid_file.mock_safe(move |_| MockResult::Return(fs::File::create("test.txt")));
+    use super::*;
 
-        let file_result = get_or_create_pid_file();
-        assert!(file_result.is_ok());
+    fn redis_info(start_time: u64, cmdline: &str) -> ProcessInfo {
+        ProcessInfo {
+            exe: Some(PathBuf::from("/usr/bin/redis-server")),
+            comm: "redis-server".to_string(),
+            cmdline: cmdline.split(' ').map(|arg| arg.to_string()).collect(),
+            start_time,
+        }
+    }
 
-        fs::remove_file("test.txt").expect("Failed to remove temporary file.");
+    fn entry(port: u16, pid: u32) -> PIDEntry {
+        PIDEntry {
+            port,
+            pid,
+            ..Default::default()
+        }
     }
 
     #[test]
-    fn test_get_or_create_pid_file_needs_creation_failure() {
-        let sample_path = PathBuf::from("./testdir");
-        get_or_create_local_config_dir
-            .mock_safe(move || MockResult::Return(Ok(sample_path.clone())));
-        create_pid_file.mock_safe(|_| {
-            MockResult::Return(io::Result::Err(io::Error::from(
-                io::ErrorKind::PermissionDenied,
-            )))
-        });
-
-        let file_result = get_or_create_pid_file();
-        assert!(file_result.is_err());
+    fn test_parse_pid_line() {
+        let text = fs::read_to_string("./test/tests.pid").expect("Failed to read test file.");
+        let pids = text
+            .lines()
+            .filter_map(parse_pid_line)
+            .collect::<HashSet<PIDEntry>>();
+        assert_eq!(pids.len(), 3);
+        assert!(pids.contains(&entry(4, 5)));
+        assert!(pids.contains(&entry(3, 5)));
+        assert_eq!(parse_pid_line("7000 12 replica"), None);
     }
 
     #[test]
-    fn test_get_or_create_pid_file_already_exists_success() {
-        let sample_path = PathBuf::from(".");
-        let _ = fs::File::create(SERVER_PID_FILE_NAME).expect("Failed to create test file.");
-        get_or_create_local_config_dir
-            .mock_safe(move || MockResult::Return(Ok(sample_path.clone())));
-
-        let file_result = get_or_create_pid_file();
-        assert!(file_result.is_ok());
-
-        fs::remove_file(SERVER_PID_FILE_NAME).expect("Failed to remove temporary file.");
+    fn test_get_currently_running_pids_nothing_recorded() {
+        read_cluster_state.mock_safe(|_| MockResult::Return(Ok(None)));
+        assert!(get_currently_running_pids().unwrap().is_empty());
     }
 
     #[test]
-    fn test_get_or_create_pid_file_already_exists_failure() {
-        let sample_path = PathBuf::from(".");
-        let _ = fs::File::create(SERVER_PID_FILE_NAME).expect("Failed to create test file.");
-        get_or_create_local_config_dir
-            .mock_safe(move || MockResult::Return(Ok(sample_path.clone())));
-        open_pid_file.mock_safe(|_| {
-            MockResult::Return(io::Result::Err(io::Error::from(
-                io::ErrorKind::PermissionDenied,
-            )))
+    fn test_get_currently_running_pids_failure() {
+        read_cluster_state.mock_safe(|_| {
+            MockResult::Return(Err(RrError::State("We failed....".to_string(), None)))
         });
-
-        let file_result = get_or_create_pid_file();
-        assert!(file_result.is_err());
-
-        fs::remove_file(SERVER_PID_FILE_NAME).expect("Failed to remove temporary file.");
+        assert!(get_currently_running_pids().is_err());
     }
 
     #[test]
-    fn test_get_currently_running_pids_success() {
-        let test_path = PathBuf::from("./test/tests.pid");
-        get_or_create_pid_file.mock_safe(move || {
-            MockResult::Return(Ok(
-                fs::File::open(&test_path).expect("Failed to open test file.")
-            ))
+    fn test_get_currently_running_pids_prunes_stale() {
+        holds_state_lock.mock_safe(|| MockResult::Return(true));
+        read_cluster_state.mock_safe(|cluster| {
+            MockResult::Return(Ok(Some(ClusterState::new(
+                cluster,
+                vec![entry(7000, 7), entry(7001, 13), entry(7002, 21)],
+            ))))
+        });
+        process_info.mock_safe(|pid| {
+            MockResult::Return(match pid {
+                7 => Some(ProcessInfo {
+                    exe: Some(PathBuf::from("/usr/bin/bash")),
+                    comm: "bash".to_string(),
+                    cmdline: vec!["bash".to_string()],
+                    start_time: 1,
+                }),
+                13 => Some(redis_info(4242, "redis-server 127.0.0.1:7001 [cluster]")),
+                _ => None,
+            })
+        });
+        write_data_to_pid_file.mock_safe(|pids| {
+            assert_eq!(pids.len(), 2);
+            assert!(pids.iter().all(|entry| entry.pid != 7));
+            MockResult::Return(Ok(()))
         });
 
-        let pids = get_currently_running_pids();
-        assert!(pids.is_ok());
-        assert_eq!(pids.unwrap().len(), 3);
+        let pids = get_currently_running_pids().unwrap();
+        assert_eq!(pids.len(), 2);
+        assert!(pids.contains(&entry(7001, 13)));
     }
 
     #[test]
-    fn test_get_currently_running_pids_failure() {
-        get_or_create_pid_file.mock_safe(|| MockResult::Return(Err("We failed....".to_string())));
+    fn test_check_recorded_process() {
+        let entry = PIDEntry {
+            port: 7000,
+            pid: 10,
+            role: NodeRole::Primary,
+            conf: Some(PathBuf::from("/tmp/rr/7000.conf")),
+            start_time: Some(500),
+            ..Default::default()
+        };
+        let matching = redis_info(500, "redis-server 127.0.0.1:7000 [cluster]");
+        assert!(check_recorded_process(&entry, &matching).is_ok());
+        let untitled = redis_info(500, "redis-server /tmp/rr/7000.conf");
+        assert!(check_recorded_process(&entry, &untitled).is_ok());
+        let restarted = redis_info(900, "redis-server 127.0.0.1:7000 [cluster]");
+        assert!(check_recorded_process(&entry, &restarted).is_err());
+        let other_port = redis_info(500, "redis-server 127.0.0.1:7001 [cluster]");
+        assert!(check_recorded_process(&entry, &other_port).is_err());
+        let unreadable_exe = ProcessInfo {
+            exe: None,
+            comm: "sshd".to_string(),
+            ..matching
+        };
+        assert!(check_recorded_process(&entry, &unreadable_exe).is_err());
+    }
 
-        let pids = get_currently_running_pids();
-        assert!(pids.is_err());
+    #[test]
+    fn test_check_recorded_process_binary() {
+        let entry = PIDEntry {
+            port: 7000,
+            pid: 10,
+            binary: Some(PathBuf::from("/opt/redis-stack/bin/redis-stack-server")),
+            ..Default::default()
+        };
+        let stack = ProcessInfo {
+            exe: Some(PathBuf::from("/opt/redis-stack/bin/redis-stack-server")),
+            comm: "redis-stack-ser".to_string(),
+            ..redis_info(500, "redis-stack-server *:7000 [cluster]")
+        };
+        assert!(check_recorded_process(&entry, &stack).is_ok());
+        let unreadable_exe = ProcessInfo {
+            exe: None,
+            ..stack.clone()
+        };
+        assert!(check_recorded_process(&entry, &unreadable_exe).is_ok());
+        let other_binary = redis_info(500, "redis-server *:7000 [cluster]");
+        assert!(check_recorded_process(&entry, &other_binary).is_err());
     }
 
     #[test]
-    fn test_write_data_to_pid_file_fail_to_create() {
-        let sample_path = PathBuf::from("./testdir");
-        get_or_create_local_config_dir
-            .mock_safe(move || MockResult::Return(Ok(sample_path.clone())));
-        create_pid_file.mock_safe(|_| {
-            MockResult::Return(io::Result::Err(io::Error::from(
-                io::ErrorKind::PermissionDenied,
-            )))
+    fn test_write_data_to_pid_file_keeps_created_at() {
+        read_cluster_state.mock_safe(|cluster| {
+            MockResult::Return(Ok(Some(ClusterState {
+                created_at: 1234,
+                ..ClusterState::new(cluster, vec![entry(7000, 1)])
+            })))
+        });
+        write_cluster_state.mock_safe(|state| {
+            assert_eq!(state.created_at, 1234);
+            assert_eq!(state.nodes, vec![entry(7000, 1), entry(7001, 2)]);
+            MockResult::Return(Ok(()))
         });
 
-        let test_set: HashSet<PIDEntry> = HashSet::new();
-        let write_result = write_data_to_pid_file(&test_set);
-        assert!(write_result.is_err());
+        let pids = HashSet::from([entry(7001, 2), entry(7000, 1)]);
+        write_data_to_pid_file(&pids).unwrap();
     }
 
     #[test]
-    fn test_write_data_pid_file_success() {
-        let sample_path = PathBuf::from(".");
-        get_or_create_local_config_dir
-            .mock_safe(move || MockResult::Return(Ok(sample_path.clone())));
-
-        let mut test_set: HashSet<PIDEntry> = HashSet::new();
-        test_set.insert(PIDEntry {
-            port: "7000".to_string(),
-            pid: 1,
+    fn test_write_data_to_pid_file_nothing_left() {
+        write_cluster_state.mock_safe(|_| panic!("No state should be written."));
+        remove_cluster_state.mock_safe(|cluster| {
+            assert_eq!(cluster, DEFAULT_CLUSTER_NAME);
+            MockResult::Return(Ok(()))
         });
-        test_set.insert(PIDEntry {
-            port: "7001".to_string(),
-            pid: 2,
-        });
-        let write_result = write_data_to_pid_file(&test_set);
-        assert!(write_result.is_ok());
-
-        let pids = get_currently_running_pids();
-        assert!(pids.is_ok());
-        assert_eq!(pids.unwrap().len(), 2);
+        write_data_to_pid_file(&HashSet::new()).unwrap();
     }
 }
@@ -0,0 +1,234 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    thread,
+    time::{Duration, Instant},
+};
+
+use mocktopus::macros::mockable;
+
+use crate::error::RrError;
+
+// Delay between checks while waiting for a process to exit.
+const EXIT_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Check whether a process with the given PID is still running, by
+/// sending it signal 0 which only checks the process can be signalled.
+///
+/// # Arguments
+/// * `pid` - PID of the process to check.
+///
+/// # Examples
+/// ```
+/// if !is_process_alive(entry.pid) {
+///     warn!("Server on port {} is no longer running.", entry.port);
+/// }
+/// ```
+#[mockable]
+pub fn is_process_alive(pid: u32) -> bool {
+    // PID 0 would address the whole process group.
+    let Some(pid) = libc::pid_t::try_from(pid).ok().filter(|pid| *pid > 0) else {
+        return false;
+    };
+    // Safe as signal 0 is never delivered.
+    match unsafe { libc::kill(pid, 0) } {
+        0 => true,
+        // The process exists but belongs to another user.
+        _ => io::Error::last_os_error().raw_os_error() == Some(libc::EPERM),
+    }
+}
+
+/// Wait for a process to exit, checking on it until the deadline passes.
+/// Returns whether the process is gone.
+///
+/// # Arguments
+/// * `pid` - PID of the process to wait for.
+/// * `deadline` - When to give up waiting.
+///
+/// # Examples
+/// ```
+/// if !wait_for_exit(entry.pid, Instant::now() + Duration::from_secs(10)) {
+///     signal_process(entry.pid, "KILL")?;
+/// }
+/// ```
+pub fn wait_for_exit(pid: u32, deadline: Instant) -> bool {
+    loop {
+        if !is_process_alive(pid) {
+            return true;
+        }
+        if Instant::now() >= deadline {
+            return false;
+        }
+        thread::sleep(EXIT_POLL_INTERVAL);
+    }
+}
+
+/// Send a signal to a process.
+///
+/// # Arguments
+/// * `pid` - PID of the process to signal.
+/// * `signal` - Name of the signal to send, TERM or KILL.
+///
+/// # Examples
+/// ```
+/// signal_process(entry.pid, "KILL").expect("Failed to kill server.");
+/// ```
+#[mockable]
+pub fn signal_process(pid: u32, signal: &str) -> Result<(), RrError> {
+    let failed = || format!("Failed to send SIG{} to process {}.", signal, pid);
+    let number = match signal {
+        "TERM" => libc::SIGTERM,
+        "KILL" => libc::SIGKILL,
+        _ => return Err(RrError::Process(failed(), None)),
+    };
+    let target = libc::pid_t::try_from(pid)
+        .ok()
+        .filter(|pid| *pid > 0)
+        .ok_or_else(|| RrError::Process(failed(), None))?;
+    // Safe as kill only takes plain integers.
+    match unsafe { libc::kill(target, number) } {
+        0 => Ok(()),
+        _ => {
+            let err = io::Error::last_os_error();
+            Err(RrError::Process(failed(), Some(Box::new(err))))
+        }
+    }
+}
+
+/// What /proc tells about a running process.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProcessInfo {
+    /// Executable of the process, when it may be read.
+    pub exe: Option<PathBuf>,
+    /// Command name the kernel keeps for the process.
+    pub comm: String,
+    /// Arguments of the process, as it currently shows them.
+    pub cmdline: Vec<String>,
+    /// When the process started, in clock ticks since boot.
+    pub start_time: u64,
+}
+
+impl ProcessInfo {
+    /// Name of the program the process runs, from its executable when it
+    /// may be read and from its command name otherwise.
+    pub fn program(&self) -> String {
+        match self.exe.as_ref().and_then(|exe| exe.file_name()) {
+            Some(name) => name.to_string_lossy().to_string(),
+            None => self.comm.clone(),
+        }
+    }
+}
+
+/// Parse the command name and start time out of /proc/<pid>/stat. The
+/// command name is wrapped in parentheses and may itself contain spaces
+/// and parentheses, so the fields after it are found from the last one.
+fn parse_proc_stat(stat: &str) -> Option<(String, u64)> {
+    let open = stat.find('(')?;
+    let close = stat.rfind(')')?;
+    let comm = stat.get(open + 1..close)?.to_string();
+    // Fields after the command name start with the state, the third
+    // field, and the start time is the 22nd.
+    let start_time = stat[close + 1..].split_whitespace().nth(19)?;
+    Some((comm, start_time.parse().ok()?))
+}
+
+/// Read what /proc tells about a process. Returns None when there is no
+/// such process.
+///
+/// # Arguments
+/// * `pid` - PID of the process to look up.
+///
+/// # Examples
+/// ```
+/// if let Some(info) = process_info(entry.pid) {
+///     info!("Process {} runs {}.", entry.pid, info.program());
+/// }
+/// ```
+#[mockable]
+pub fn process_info(pid: u32) -> Option<ProcessInfo> {
+    let proc_dir = Path::new("/proc").join(pid.to_string());
+    let (comm, start_time) = parse_proc_stat(&fs::read_to_string(proc_dir.join("stat")).ok()?)?;
+    let cmdline = fs::read(proc_dir.join("cmdline"))
+        .map(|raw| {
+            String::from_utf8_lossy(&raw)
+                .split('\0')
+                .filter(|arg| !arg.is_empty())
+                .map(|arg| arg.to_string())
+                .collect()
+        })
+        .unwrap_or_default();
+    Some(ProcessInfo {
+        exe: fs::read_link(proc_dir.join("exe")).ok(),
+        comm,
+        cmdline,
+        start_time,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::process::{self, Command};
+
+    use super::*;
+
+    #[test]
+    fn test_is_process_alive_self() {
+        assert!(is_process_alive(process::id()));
+        assert!(!is_process_alive(0));
+    }
+
+    #[test]
+    fn test_is_process_alive_exited() {
+        let mut child = Command::new("true").spawn().unwrap();
+        let pid = child.id();
+        child.wait().unwrap();
+        assert!(!is_process_alive(pid));
+    }
+
+    #[test]
+    fn test_wait_for_exit() {
+        let mut child = Command::new("true").spawn().unwrap();
+        child.wait().unwrap();
+        assert!(wait_for_exit(child.id(), Instant::now()));
+        assert!(!wait_for_exit(process::id(), Instant::now()));
+    }
+
+    #[test]
+    fn test_signal_process_kill() {
+        let mut child = Command::new("sleep").arg("30").spawn().unwrap();
+        signal_process(child.id(), "KILL").unwrap();
+        assert!(!child.wait().unwrap().success());
+        assert!(signal_process(child.id(), "KILL").is_err());
+        assert!(signal_process(0, "KILL").is_err());
+    }
+
+    #[test]
+    fn test_parse_proc_stat() {
+        let stat = "4242 (redis (server)) S 1 4242 4242 0 -1 4194560 2101 0 0 0 12 9 0 0 20 0 5 0 8812345 190840832 2465 18446744073709551615";
+        assert_eq!(
+            parse_proc_stat(stat),
+            Some(("redis (server)".to_string(), 8812345))
+        );
+        assert_eq!(parse_proc_stat("4242 (redis-server) S 1"), None);
+    }
+
+    #[test]
+    fn test_process_info() {
+        let mut child = Command::new("sleep").arg("30").spawn().unwrap();
+        // The command line stays empty until the child has exec'd.
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let info = loop {
+            let info = process_info(child.id()).unwrap();
+            if !info.cmdline.is_empty() || Instant::now() >= deadline {
+                break info;
+            }
+            thread::sleep(EXIT_POLL_INTERVAL);
+        };
+        child.kill().unwrap();
+        child.wait().unwrap();
+        assert_eq!(info.comm, "sleep");
+        assert_eq!(info.cmdline, vec!["sleep", "30"]);
+        assert_eq!(process_info(child.id()), None);
+    }
+}
@@ -0,0 +1,369 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use mocktopus::macros::mockable;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::RrError,
+    local::{
+        cluster::{get_or_create_cluster_dir, list_clusters, DEFAULT_CLUSTER_NAME},
+        dotdir::get_or_create_local_config_dir,
+        lock::{lock_clusters, StateLock, LOCK_TIMEOUT},
+        pid::{parse_pid_line, PIDEntry},
+    },
+};
+
+// Version of the state documents rr writes. Documents of a newer
+// version are refused rather than misread.
+pub const STATE_VERSION: u32 = 1;
+
+// Name of the state document inside a cluster's directory.
+const STATE_FILE_NAME: &str = "state.json";
+
+// Name of the file older releases recorded running servers in.
+const LEGACY_PID_FILE_NAME: &str = "servers.pid";
+
+/// Everything rr records about a cluster it started.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ClusterState {
+    pub version: u32,
+    pub cluster: String,
+    /// When the cluster's servers were first recorded, in seconds since
+    /// the Unix epoch.
+    pub created_at: u64,
+    pub nodes: Vec<PIDEntry>,
+}
+
+impl ClusterState {
+    /// Create the state of a cluster recorded just now.
+    ///
+    /// # Arguments
+    /// * `cluster` - Name of the cluster.
+    /// * `nodes` - The servers of the cluster.
+    ///
+    /// # Examples
+    /// ```
+    /// let state = ClusterState::new(DEFAULT_CLUSTER_NAME, vec![]);
+    /// ```
+    pub fn new(cluster: &str, nodes: Vec<PIDEntry>) -> Self {
+        ClusterState {
+            version: STATE_VERSION,
+            cluster: cluster.to_string(),
+            created_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|elapsed| elapsed.as_secs())
+                .unwrap_or_default(),
+            nodes,
+        }
+    }
+}
+
+/// Get the path of a cluster's state document, creating the cluster's
+/// directory if needed.
+///
+/// # Arguments
+/// * `cluster` - Name of the cluster.
+///
+/// # Examples
+/// ```
+/// let state_path = state_file_path(DEFAULT_CLUSTER_NAME).expect("Failed to create cluster directory.");
+/// ```
+pub fn state_file_path(cluster: &str) -> Result<PathBuf, RrError> {
+    Ok(get_or_create_cluster_dir(cluster)?.join(STATE_FILE_NAME))
+}
+
+fn parse_state(text: &str, path: &Path) -> Result<ClusterState, RrError> {
+    let state: ClusterState = serde_json::from_str(text).map_err(|err| {
+        RrError::State(
+            format!(
+                "State file {} is corrupt. Stop the servers it lists and delete it.",
+                path.display()
+            ),
+            Some(Box::new(err)),
+        )
+    })?;
+    if state.version > STATE_VERSION {
+        return Err(RrError::State(
+            format!(
+                "State file {} has version {}, but this release of rr only reads up to version {}. Upgrade rr.",
+                path.display(),
+                state.version,
+                STATE_VERSION
+            ),
+            None,
+        ));
+    }
+    Ok(state)
+}
+
+/// Turn the servers.pid file of an older release into the state of the
+/// cluster it described and remove it.
+fn migrate_pid_file(legacy_path: &Path, cluster: &str) -> Result<Option<ClusterState>, RrError> {
+    let text = fs::read_to_string(legacy_path).map_err(|err| {
+        RrError::State(
+            format!("Failed to read {}.", legacy_path.display()),
+            Some(Box::new(err)),
+        )
+    })?;
+    let nodes = text
+        .lines()
+        .filter_map(parse_pid_line)
+        .collect::<Vec<PIDEntry>>();
+    let state = ClusterState::new(cluster, nodes);
+    write_cluster_state(&state)?;
+    if let Err(err) = fs::remove_file(legacy_path) {
+        return Err(RrError::State(
+            format!("Failed to remove {}.", legacy_path.display()),
+            Some(Box::new(err)),
+        ));
+    }
+    info!(
+        "Migrated {} servers from {} to the state of cluster {}.",
+        state.nodes.len(),
+        legacy_path.display(),
+        cluster
+    );
+    Ok(Some(state))
+}
+
+/// Read a cluster's state document. Returns None when no server of the
+/// cluster is recorded. The servers.pid file older releases left for the
+/// default cluster is migrated the first time it is read.
+///
+/// # Arguments
+/// * `cluster` - Name of the cluster.
+///
+/// # Examples
+/// ```
+/// let state = read_cluster_state(DEFAULT_CLUSTER_NAME).expect("Failed to read state.");
+/// ```
+#[mockable]
+pub fn read_cluster_state(cluster: &str) -> Result<Option<ClusterState>, RrError> {
+    let path = state_file_path(cluster)?;
+    if !path.exists() {
+        let legacy_path = get_or_create_local_config_dir()?.join(LEGACY_PID_FILE_NAME);
+        if cluster == DEFAULT_CLUSTER_NAME && legacy_path.exists() {
+            return migrate_pid_file(&legacy_path, cluster);
+        }
+        return Ok(None);
+    }
+    match fs::read_to_string(&path) {
+        Ok(text) => parse_state(&text, &path).map(Some),
+        Err(err) => Err(RrError::State(
+            format!("Failed to read state file {}.", path.display()),
+            Some(Box::new(err)),
+        )),
+    }
+}
+
+/// Write a cluster's state document. The document is written next to the
+/// old one and renamed over it, so it is never left half written.
+///
+/// # Arguments
+/// * `state` - The state to write.
+///
+/// # Examples
+/// ```
+/// write_cluster_state(&ClusterState::new(DEFAULT_CLUSTER_NAME, nodes)).expect("Failed to write state.");
+/// ```
+#[mockable]
+pub fn write_cluster_state(state: &ClusterState) -> Result<(), RrError> {
+    let path = state_file_path(&state.cluster)?;
+    let partial_path = path.with_extension("json.partial");
+    let text = serde_json::to_string_pretty(state).map_err(|err| {
+        RrError::State(
+            "Failed to serialise the cluster state.".to_string(),
+            Some(Box::new(err)),
+        )
+    })?;
+    fs::write(&partial_path, text + "\n")
+        .and_then(|_| fs::rename(&partial_path, &path))
+        .map_err(|err| {
+            RrError::State(
+                format!("Failed to write state file {}.", path.display()),
+                Some(Box::new(err)),
+            )
+        })
+}
+
+/// Remove a cluster's state document once none of its servers is left.
+///
+/// # Arguments
+/// * `cluster` - Name of the cluster.
+///
+/// # Examples
+/// ```
+/// remove_cluster_state(DEFAULT_CLUSTER_NAME).expect("Failed to remove state.");
+/// ```
+#[mockable]
+pub fn remove_cluster_state(cluster: &str) -> Result<(), RrError> {
+    let path = state_file_path(cluster)?;
+    match fs::remove_file(&path) {
+        Ok(_) => Ok(()),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(()),
+        Err(err) => Err(RrError::State(
+            format!("Failed to remove state file {}.", path.display()),
+            Some(Box::new(err)),
+        )),
+    }
+}
+
+/// Check no other cluster has a server recorded on any of the given
+/// ports, so two clusters never fight over a port. The returned lock keeps
+/// other rr processes from claiming ports, and should be held until the
+/// servers on these ports are recorded.
+///
+/// # Arguments
+/// * `cluster` - Name of the cluster the ports are meant for.
+/// * `ports` - The ports to check.
+///
+/// # Examples
+/// ```
+/// let _ports_lock = check_ports_unclaimed(DEFAULT_CLUSTER_NAME, &[7000, 7001]).expect("Port already in use.");
+/// ```
+#[mockable]
+pub fn check_ports_unclaimed(cluster: &str, ports: &[u16]) -> Result<StateLock, RrError> {
+    let lock = lock_clusters(LOCK_TIMEOUT)?;
+    for other in list_clusters()?.iter().filter(|other| *other != cluster) {
+        let state = match read_cluster_state(other)? {
+            Some(state) => state,
+            None => continue,
+        };
+        let mut claimed = state
+            .nodes
+            .iter()
+            .map(|node| node.port)
+            .filter(|port| ports.contains(port))
+            .map(|port| port.to_string())
+            .collect::<Vec<String>>();
+        if !claimed.is_empty() {
+            claimed.sort_unstable();
+            return Err(RrError::Config(
+                format!(
+                    "Cluster \"{}\" already has servers recorded on port {}. Stop it or choose other ports.",
+                    other,
+                    claimed.join(", ")
+                ),
+                None,
+            ));
+        }
+    }
+    Ok(lock)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::env;
+
+    use mocktopus::mocking::{MockResult, Mockable};
+
+    use crate::local::pid::NodeRole;
+
+    use super::*;
+
+    // Point the config directory at a fresh directory of the test's own,
+    // so tests running side by side never share files.
+    fn mock_config_dir(name: &str) -> PathBuf {
+        let config_dir = env::temp_dir().join(format!("rr-state-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&config_dir);
+        fs::create_dir_all(&config_dir).unwrap();
+        let mocked_dir = config_dir.clone();
+        get_or_create_local_config_dir
+            .mock_safe(move || MockResult::Return(Ok(mocked_dir.clone())));
+        config_dir
+    }
+
+    fn node(port: u16, pid: u32) -> PIDEntry {
+        PIDEntry {
+            port,
+            pid,
+            role: NodeRole::Replica,
+            node_id: Some(format!("node-{}", port)),
+            start_time: Some(100),
+            conf: Some(PathBuf::from("/tmp/rr conf/7000.conf")),
+            data_dir: Some(PathBuf::from("/tmp/rr data/7000")),
+            log: Some(PathBuf::from("/tmp/rr logs/7000.log")),
+            binary: Some(PathBuf::from("/usr/bin/redis-server")),
+            version: Some("7.2.4".to_string()),
+        }
+    }
+
+    #[test]
+    fn test_cluster_state_round_trip() {
+        let config_dir = mock_config_dir("round-trip");
+        assert_eq!(read_cluster_state("default").unwrap(), None);
+
+        let state = ClusterState::new("default", vec![node(7000, 10), node(7001, 11)]);
+        write_cluster_state(&state).unwrap();
+        assert_eq!(read_cluster_state("default").unwrap(), Some(state));
+        assert_eq!(read_cluster_state("other").unwrap(), None);
+
+        remove_cluster_state("default").unwrap();
+        remove_cluster_state("default").unwrap();
+        assert_eq!(read_cluster_state("default").unwrap(), None);
+        fs::remove_dir_all(config_dir).unwrap();
+    }
+
+    #[test]
+    fn test_parse_state_newer_version() {
+        let text = r#"{"version": 2, "cluster": "default", "created_at": 0, "nodes": []}"#;
+        let result = parse_state(text, Path::new("default.json"));
+        assert!(matches!(result, Err(RrError::State(..))));
+    }
+
+    #[test]
+    fn test_parse_state_missing_node_fields() {
+        let text = r#"{"version": 1, "cluster": "default", "created_at": 0, "nodes": [{"port": 7000, "pid": 10}]}"#;
+        let state = parse_state(text, Path::new("default.json")).unwrap();
+        assert_eq!(
+            state.nodes,
+            vec![PIDEntry {
+                port: 7000,
+                pid: 10,
+                ..Default::default()
+            }]
+        );
+    }
+
+    #[test]
+    fn test_read_cluster_state_migrates_pid_file() {
+        let config_dir = mock_config_dir("migrate");
+        let legacy_path = config_dir.join(LEGACY_PID_FILE_NAME);
+        fs::copy("./test/tests.pid", &legacy_path).unwrap();
+
+        let state = read_cluster_state(DEFAULT_CLUSTER_NAME).unwrap().unwrap();
+        assert_eq!(state.version, STATE_VERSION);
+        assert_eq!(state.nodes.len(), 3);
+        assert!(!legacy_path.exists());
+        assert_eq!(
+            read_cluster_state(DEFAULT_CLUSTER_NAME).unwrap(),
+            Some(state)
+        );
+        fs::remove_dir_all(config_dir).unwrap();
+    }
+
+    #[test]
+    fn test_check_ports_unclaimed() {
+        let config_dir = mock_config_dir("ports");
+        write_cluster_state(&ClusterState::new(
+            "search",
+            vec![node(7000, 10), node(7001, 11)],
+        ))
+        .unwrap();
+
+        assert!(check_ports_unclaimed("default", &[7002, 7003]).is_ok());
+        assert!(check_ports_unclaimed("search", &[7000, 7001]).is_ok());
+        match check_ports_unclaimed("default", &[7001, 7002, 7000]) {
+            Err(RrError::Config(msg, _)) => {
+                assert!(msg.contains("\"search\"") && msg.contains("7000, 7001"))
+            }
+            other => panic!("Unexpected result: {:?}", other),
+        }
+        fs::remove_dir_all(config_dir).unwrap();
+    }
+}
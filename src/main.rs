@@ -6,14 +6,40 @@ extern crate log;
 #[cfg(test)]
 extern crate mocktopus;
 
-use std::env;
+use std::{env, process};
 
-use cli::cmd::Executable;
+use cli::{cmd::Executable, output::OutputFormat};
 use structopt::StructOpt;
 
 mod cli;
 mod cluster;
+mod error;
 mod local;
+mod resp;
+
+/// Select the cluster named with --cluster, or else the one named in the
+/// project's rr.toml, falling back to the default cluster, along with the
+/// password the rr.toml gives the nodes. A broken rr.toml is only
+/// reported here, as most commands do not use it.
+fn select_cluster(name: &Option<String>, cmd: &cli::ClusterCommand) -> Result<(), error::RrError> {
+    let manifest = match cluster::manifest::project_manifest() {
+        Ok(manifest) => manifest,
+        Err(err) => {
+            warn!("Ignoring the project's rr.toml: {}", err);
+            None
+        }
+    };
+    let name = name
+        .clone()
+        .or_else(|| manifest.as_ref().and_then(|manifest| manifest.name.clone()))
+        .unwrap_or_else(|| local::cluster::DEFAULT_CLUSTER_NAME.to_string());
+    local::cluster::select_cluster(&name)?;
+    resp::client::select_password(manifest.and_then(|manifest| manifest.password))?;
+    if cmd.needs_existing_cluster() {
+        local::cluster::check_cluster_known(&name)?;
+    }
+    Ok(())
+}
 
 fn main() {
     // Set the default log level if not provided
@@ -24,19 +50,41 @@ fn main() {
     pretty_env_logger::init();
 
     let root_args = cli::RootCommand::from_args();
-    let cmd_result = match root_args.cmd {
-        cli::ClusterCommand::Config(config_args) => match config_args {
-            cli::ClusterConfig::Ls(ls_command) => ls_command.execute(),
-        },
-        cli::ClusterCommand::Cluster(cluster_args) => match cluster_args {
-            cli::ClusterRuntime::Start(start_command) => start_command.execute(),
-            cli::ClusterRuntime::Stop(stop_command) => stop_command.execute(),
-            cli::ClusterRuntime::Check(check_command) => check_command.execute(),
-        },
-    };
+    let output = root_args.output;
+    let cmd_result =
+        select_cluster(&root_args.cluster, &root_args.cmd).and_then(|_| match root_args.cmd {
+            cli::ClusterCommand::Config(config_args) => match config_args {
+                cli::ClusterConfig::Ls(ls_command) => ls_command.execute(output),
+                cli::ClusterConfig::Init(init_command) => init_command.execute(output),
+                cli::ClusterConfig::Validate(validate_command) => validate_command.execute(output),
+            },
+            cli::ClusterCommand::Cluster(cluster_args) => match cluster_args {
+                cli::ClusterRuntime::Start(start_command) => start_command.execute(output),
+                cli::ClusterRuntime::Stop(stop_command) => stop_command.execute(output),
+                cli::ClusterRuntime::Check(check_command) => check_command.execute(output),
+                cli::ClusterRuntime::Status(status_command) => status_command.execute(output),
+                cli::ClusterRuntime::Fix(fix_command) => fix_command.execute(output),
+                cli::ClusterRuntime::AddNode(add_command) => add_command.execute(output),
+                cli::ClusterRuntime::RemoveNode(remove_command) => remove_command.execute(output),
+                cli::ClusterRuntime::Reshard(reshard_command) => reshard_command.execute(output),
+                cli::ClusterRuntime::Rebalance(rebalance_command) => {
+                    rebalance_command.execute(output)
+                }
+                cli::ClusterRuntime::Failover(failover_command) => failover_command.execute(output),
+                cli::ClusterRuntime::Ls(list_command) => list_command.execute(output),
+            },
+            cli::ClusterCommand::Logs(logs_command) => logs_command.execute(output),
+        });
 
     if let Err(result_error) = cmd_result {
+        if output == OutputFormat::Json {
+            cli::output::print_json_error(&result_error);
+        }
         error!("{}", result_error);
+        for cause in result_error.causes() {
+            error!("caused by: {}", cause);
+        }
+        process::exit(result_error.exit_code());
     } else {
         info!("Command executed successfully.");
     }
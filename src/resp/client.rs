@@ -0,0 +1,367 @@
+use std::{
+    env,
+    io::{BufReader, Write},
+    net::{TcpStream, ToSocketAddrs},
+    sync::Mutex,
+    time::Duration,
+};
+
+use crate::{
+    error::RrError,
+    resp::reply::{encode_command, read_reply, Reply},
+};
+
+// How long to wait on connects, reads and writes when the caller
+// has no stronger opinion.
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(5);
+
+// Environment variable holding the password for password protected
+// nodes, the same one redis-cli reads.
+pub const AUTH_ENV_VAR: &str = "REDISCLI_AUTH";
+
+// The password the project's rr.toml gives the nodes, chosen once for
+// this invocation.
+static SELECTED_PASSWORD: Mutex<Option<String>> = Mutex::new(None);
+
+/// A blocking connection to a single Redis server.
+#[derive(Debug)]
+pub struct RedisClient {
+    reader: BufReader<TcpStream>,
+    writer: TcpStream,
+    pub endpoint: String,
+}
+
+impl RedisClient {
+    /// Open a connection to the server at host:port. The timeout applies
+    /// to the connect itself and to every read and write afterwards.
+    ///
+    /// # Arguments
+    /// * `host` - Host name or IP of the server.
+    /// * `port` - Port the server listens on.
+    /// * `timeout` - Connect, read and write timeout.
+    ///
+    /// # Examples
+    /// ```
+    /// let client = RedisClient::connect("127.0.0.1", 7000, DEFAULT_TIMEOUT).expect("Failed to connect.");
+    /// ```
+    pub fn connect(host: &str, port: u16, timeout: Duration) -> Result<RedisClient, RrError> {
+        let endpoint = format!("{}:{}", host, port);
+        let addrs = match (host, port).to_socket_addrs() {
+            Ok(addrs) => addrs.collect::<Vec<_>>(),
+            Err(err) => {
+                return Err(RrError::Protocol(
+                    format!("Failed to resolve {}.", endpoint),
+                    Some(Box::new(err)),
+                ))
+            }
+        };
+
+        let mut last_error =
+            RrError::Protocol(format!("No addresses found for {}.", endpoint), None);
+        for addr in addrs.iter() {
+            match TcpStream::connect_timeout(addr, timeout) {
+                Ok(stream) => {
+                    let configured = stream
+                        .set_read_timeout(Some(timeout))
+                        .and_then(|_| stream.set_write_timeout(Some(timeout)))
+                        .and_then(|_| stream.set_nodelay(true))
+                        .and_then(|_| stream.try_clone());
+                    return match configured {
+                        Ok(writer) => Ok(RedisClient {
+                            reader: BufReader::new(stream),
+                            writer,
+                            endpoint,
+                        }),
+                        Err(err) => Err(RrError::Protocol(
+                            format!("Failed to configure {}.", endpoint),
+                            Some(Box::new(err)),
+                        )),
+                    };
+                }
+                Err(err) => {
+                    last_error = RrError::Protocol(
+                        format!("Failed to connect to {}.", endpoint),
+                        Some(Box::new(err)),
+                    )
+                }
+            }
+        }
+        Err(last_error)
+    }
+
+    /// Authenticate the connection. The username is only needed for
+    /// servers using ACL users other than `default`.
+    ///
+    /// # Arguments
+    /// * `username` - Optional ACL user name.
+    /// * `password` - The password, as set by requirepass or the ACL.
+    ///
+    /// # Examples
+    /// ```
+    /// client.auth(None, "secret").expect("Failed to authenticate.");
+    /// ```
+    pub fn auth(&mut self, username: Option<&str>, password: &str) -> Result<(), RrError> {
+        let reply = match username {
+            Some(user) => self.command(&["AUTH", user, password])?,
+            None => self.command(&["AUTH", password])?,
+        };
+        if reply.is_ok() {
+            Ok(())
+        } else {
+            Err(RrError::Protocol(
+                format!("Unexpected AUTH reply from {}: {:?}", self.endpoint, reply),
+                None,
+            ))
+        }
+    }
+
+    /// Send a single command and wait for its reply. Error replies
+    /// from the server come back as an Err.
+    ///
+    /// # Arguments
+    /// * `args` - The command name followed by its arguments.
+    ///
+    /// # Examples
+    /// ```
+    /// let info = client.command(&["CLUSTER", "INFO"]).expect("Command failed.");
+    /// ```
+    pub fn command<S: AsRef<[u8]>>(&mut self, args: &[S]) -> Result<Reply, RrError> {
+        self.send(&encode_command(args))?;
+        self.receive()?.into_result().map_err(|msg| {
+            RrError::Protocol(
+                format!("{} replied with error: {}", self.endpoint, msg),
+                None,
+            )
+        })
+    }
+
+    /// Send several commands in one write and read back all their
+    /// replies in order. Error replies are kept in place so that one
+    /// failing command does not hide the others.
+    ///
+    /// # Arguments
+    /// * `commands` - The commands to send, each a list of arguments.
+    ///
+    /// # Examples
+    /// ```
+    /// let replies = client.pipeline(&[vec!["PING".to_string()], vec!["DBSIZE".to_string()]]);
+    /// ```
+    pub fn pipeline<S: AsRef<[u8]>>(&mut self, commands: &[Vec<S>]) -> Result<Vec<Reply>, RrError> {
+        let payload = commands
+            .iter()
+            .flat_map(|args| encode_command(args))
+            .collect::<Vec<u8>>();
+        self.send(&payload)?;
+        commands.iter().map(|_| self.receive()).collect()
+    }
+
+    fn send(&mut self, payload: &[u8]) -> Result<(), RrError> {
+        self.writer
+            .write_all(payload)
+            .and_then(|_| self.writer.flush())
+            .map_err(|err| {
+                RrError::Protocol(
+                    format!("Failed to send to {}.", self.endpoint),
+                    Some(Box::new(err)),
+                )
+            })
+    }
+
+    fn receive(&mut self) -> Result<Reply, RrError> {
+        loop {
+            match read_reply(&mut self.reader) {
+                // Out-of-band pushes can arrive between replies on RESP3
+                // connections and never answer a request.
+                Ok(Reply::Push(_)) => continue,
+                Ok(reply) => return Ok(reply),
+                Err(msg) => {
+                    return Err(RrError::Protocol(
+                        format!("{}: {}", self.endpoint, msg),
+                        None,
+                    ))
+                }
+            }
+        }
+    }
+}
+
+/// The password for password protected nodes set in $REDISCLI_AUTH.
+///
+/// # Examples
+/// ```
+/// let client = connect_node_with_password("127.0.0.1", 7000, env_password().as_deref())?;
+/// ```
+pub fn env_password() -> Option<String> {
+    env::var(AUTH_ENV_VAR).ok()
+}
+
+/// Choose the password the rest of this invocation authenticates with,
+/// instead of $REDISCLI_AUTH.
+///
+/// # Arguments
+/// * `password` - Password of the nodes, if the manifest sets one.
+///
+/// # Examples
+/// ```
+/// select_password(manifest.password.clone()).expect("Failed to select the password.");
+/// ```
+pub fn select_password(password: Option<String>) -> Result<(), RrError> {
+    match SELECTED_PASSWORD.lock() {
+        Ok(mut selected) => {
+            *selected = password;
+            Ok(())
+        }
+        Err(_) => Err(RrError::State(
+            "Failed to select the password.".to_string(),
+            None,
+        )),
+    }
+}
+
+/// The password of the nodes: the one selected from the project's
+/// rr.toml, or else $REDISCLI_AUTH.
+///
+/// # Examples
+/// ```
+/// let client = connect_node_with_password("127.0.0.1", 7000, node_password().as_deref())?;
+/// ```
+pub fn node_password() -> Option<String> {
+    SELECTED_PASSWORD
+        .lock()
+        .ok()
+        .and_then(|selected| selected.clone())
+        .or_else(env_password)
+}
+
+/// Connect to a cluster node, authenticating with the password of the
+/// nodes when there is one.
+///
+/// # Arguments
+/// * `host` - Host name or IP of the node.
+/// * `port` - Port the node listens on.
+///
+/// # Examples
+/// ```
+/// let client = connect_node("127.0.0.1", 7000).expect("Failed to connect to node.");
+/// ```
+pub fn connect_node(host: &str, port: u16) -> Result<RedisClient, RrError> {
+    connect_node_with_password(host, port, node_password().as_deref())
+}
+
+/// Connect to a cluster node, authenticating with the given password
+/// when there is one.
+///
+/// # Arguments
+/// * `host` - Host name or IP of the node.
+/// * `port` - Port the node listens on.
+/// * `password` - Password of the node, if it needs one.
+///
+/// # Examples
+/// ```
+/// let client = connect_node_with_password("127.0.0.1", 7000, Some("secret")).expect("Failed to connect to node.");
+/// ```
+pub fn connect_node_with_password(
+    host: &str,
+    port: u16,
+    password: Option<&str>,
+) -> Result<RedisClient, RrError> {
+    let mut client = RedisClient::connect(host, port, DEFAULT_TIMEOUT)?;
+    if let Some(password) = password {
+        client.auth(None, password)?;
+    }
+    Ok(client)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::{
+        io::{BufRead, Read},
+        net::TcpListener,
+        thread,
+    };
+
+    use super::*;
+
+    // Start a one-shot server that reads `expected` bytes of request and
+    // answers with `response`, returning the port it listens on.
+    fn serve_once(expected: usize, response: &'static [u8]) -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("Failed to bind test listener.");
+        let port = listener.local_addr().unwrap().port();
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().expect("Failed to accept.");
+            let mut request = vec![0; expected];
+            stream
+                .read_exact(&mut request)
+                .expect("Failed to read request.");
+            stream
+                .write_all(response)
+                .expect("Failed to write response.");
+        });
+        port
+    }
+
+    #[test]
+    fn test_connect_refused() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        drop(listener);
+
+        let client = RedisClient::connect("127.0.0.1", port, Duration::from_millis(200));
+        assert!(client.is_err());
+    }
+
+    #[test]
+    fn test_command_success() {
+        let port = serve_once(encode_command(&["PING"]).len(), b"+PONG\r\n");
+        let mut client = RedisClient::connect("127.0.0.1", port, DEFAULT_TIMEOUT).unwrap();
+
+        let reply = client.command(&["PING"]).unwrap();
+        assert_eq!(reply, Reply::Status("PONG".to_string()));
+    }
+
+    #[test]
+    fn test_command_error_reply() {
+        let port = serve_once(encode_command(&["AUTH", "nope"]).len(), b"-WRONGPASS\r\n");
+        let mut client = RedisClient::connect("127.0.0.1", port, DEFAULT_TIMEOUT).unwrap();
+
+        assert!(client.auth(None, "nope").is_err());
+    }
+
+    #[test]
+    fn test_pipeline_keeps_order() {
+        let commands = vec![vec!["PING"], vec!["GET", "missing"], vec!["DBSIZE"]];
+        let expected = commands.iter().map(|c| encode_command(c).len()).sum();
+        let port = serve_once(expected, b"+PONG\r\n-ERR nope\r\n:3\r\n");
+        let mut client = RedisClient::connect("127.0.0.1", port, DEFAULT_TIMEOUT).unwrap();
+
+        let replies = client.pipeline(&commands).unwrap();
+        assert_eq!(
+            replies,
+            vec![
+                Reply::Status("PONG".to_string()),
+                Reply::Error("ERR nope".to_string()),
+                Reply::Integer(3)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_read_timeout() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let handle = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            // Hold the connection open without ever answering.
+            let mut reader = std::io::BufReader::new(stream);
+            let mut line = String::new();
+            let _ = reader.read_line(&mut line);
+            thread::sleep(Duration::from_millis(400));
+        });
+        let mut client =
+            RedisClient::connect("127.0.0.1", port, Duration::from_millis(100)).unwrap();
+
+        assert!(client.command(&["PING"]).is_err());
+        handle.join().unwrap();
+    }
+}
@@ -0,0 +1,314 @@
+use std::{collections::HashMap, io::BufRead, str::FromStr};
+
+/// A single reply read off the wire. Covers every RESP2 type and the
+/// RESP3 types a server can send once the connection has been upgraded
+/// with HELLO 3.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Reply {
+    Status(String),
+    Error(String),
+    Integer(i64),
+    Bulk(Vec<u8>),
+    Array(Vec<Reply>),
+    Nil,
+    Double(f64),
+    Boolean(bool),
+    BigNumber(String),
+    Verbatim(String, String),
+    Map(Vec<(Reply, Reply)>),
+    Set(Vec<Reply>),
+    Push(Vec<Reply>),
+}
+
+impl Reply {
+    /// Get the reply as a string, if it is one of the string-like types.
+    ///
+    /// # Examples
+    /// ```
+    /// let reply = Reply::Bulk(b"cluster_state:ok".to_vec());
+    /// assert_eq!(reply.as_string(), Some("cluster_state:ok".to_string()));
+    /// ```
+    pub fn as_string(&self) -> Option<String> {
+        match self {
+            Reply::Status(value) | Reply::BigNumber(value) => Some(value.clone()),
+            Reply::Bulk(bytes) => Some(String::from_utf8_lossy(bytes).to_string()),
+            Reply::Verbatim(_, text) => Some(text.clone()),
+            _ => None,
+        }
+    }
+
+    /// Whether the reply is the +OK status most write commands answer with.
+    pub fn is_ok(&self) -> bool {
+        matches!(self, Reply::Status(status) if status == "OK")
+    }
+
+    /// Turn an error reply into an Err carrying the server's message.
+    ///
+    /// # Examples
+    /// ```
+    /// let reply = Reply::Error("ERR unknown command".to_string());
+    /// assert!(reply.into_result().is_err());
+    /// ```
+    pub fn into_result(self) -> Result<Reply, String> {
+        match self {
+            Reply::Error(msg) => Err(msg),
+            other => Ok(other),
+        }
+    }
+}
+
+/// Encode a command as a RESP array of bulk strings, the only form
+/// a Redis server accepts requests in.
+///
+/// # Arguments
+/// * `args` - The command name followed by its arguments.
+///
+/// # Examples
+/// ```
+/// let encoded = encode_command(&["PING"]);
+/// assert_eq!(encoded, b"*1\r\n$4\r\nPING\r\n".to_vec());
+/// ```
+pub fn encode_command<S: AsRef<[u8]>>(args: &[S]) -> Vec<u8> {
+    let mut encoded = format!("*{}\r\n", args.len()).into_bytes();
+    for arg in args.iter() {
+        let bytes = arg.as_ref();
+        encoded.extend_from_slice(format!("${}\r\n", bytes.len()).as_bytes());
+        encoded.extend_from_slice(bytes);
+        encoded.extend_from_slice(b"\r\n");
+    }
+    encoded
+}
+
+fn read_line<R: BufRead>(reader: &mut R) -> Result<String, String> {
+    let mut line = Vec::new();
+    match reader.read_until(b'\n', &mut line) {
+        Ok(0) => Err("Connection closed by server.".to_string()),
+        Ok(_) => {
+            if !line.ends_with(b"\r\n") {
+                return Err("Malformed reply line from server.".to_string());
+            }
+            line.truncate(line.len() - 2);
+            String::from_utf8(line).map_err(|_| "Reply line is not valid UTF-8.".to_string())
+        }
+        Err(err) => Err(format!("Failed to read reply: {}", err)),
+    }
+}
+
+// Streamed aggregates (`?` lengths) are never sent for the commands
+// rr issues, so they are treated as malformed here.
+fn read_length(header: &str) -> Result<Option<usize>, String> {
+    match i64::from_str(header) {
+        Ok(-1) => Ok(None),
+        Ok(len) if len >= 0 => Ok(Some(len as usize)),
+        _ => Err(format!("Invalid length in reply: {}", header)),
+    }
+}
+
+fn read_blob<R: BufRead>(reader: &mut R, len: usize) -> Result<Vec<u8>, String> {
+    let mut blob = vec![0; len + 2];
+    if let Err(err) = reader.read_exact(&mut blob) {
+        return Err(format!("Failed to read reply: {}", err));
+    }
+    if !blob.ends_with(b"\r\n") {
+        return Err("Malformed bulk reply from server.".to_string());
+    }
+    blob.truncate(len);
+    Ok(blob)
+}
+
+fn read_many<R: BufRead>(reader: &mut R, count: usize) -> Result<Vec<Reply>, String> {
+    (0..count).map(|_| read_reply(reader)).collect()
+}
+
+/// Read exactly one reply from the given reader, blocking until it
+/// has fully arrived.
+///
+/// # Arguments
+/// * `reader` - Buffered reader over the server connection.
+///
+/// # Examples
+/// ```
+/// let mut reader = BufReader::new(&b"+PONG\r\n"[..]);
+/// assert_eq!(read_reply(&mut reader), Ok(Reply::Status("PONG".to_string())));
+/// ```
+pub fn read_reply<R: BufRead>(reader: &mut R) -> Result<Reply, String> {
+    let line = read_line(reader)?;
+    if line.is_empty() {
+        return Err("Empty reply line from server.".to_string());
+    }
+    let (kind, body) = line.split_at(1);
+    match kind {
+        "+" => Ok(Reply::Status(body.to_string())),
+        "-" => Ok(Reply::Error(body.to_string())),
+        ":" => i64::from_str(body)
+            .map(Reply::Integer)
+            .map_err(|_| format!("Invalid integer reply: {}", body)),
+        "$" => match read_length(body)? {
+            Some(len) => Ok(Reply::Bulk(read_blob(reader, len)?)),
+            None => Ok(Reply::Nil),
+        },
+        "*" => match read_length(body)? {
+            Some(len) => Ok(Reply::Array(read_many(reader, len)?)),
+            None => Ok(Reply::Nil),
+        },
+        "_" => Ok(Reply::Nil),
+        "," => match body {
+            "inf" => Ok(Reply::Double(f64::INFINITY)),
+            "-inf" => Ok(Reply::Double(f64::NEG_INFINITY)),
+            _ => f64::from_str(body)
+                .map(Reply::Double)
+                .map_err(|_| format!("Invalid double reply: {}", body)),
+        },
+        "#" => match body {
+            "t" => Ok(Reply::Boolean(true)),
+            "f" => Ok(Reply::Boolean(false)),
+            _ => Err(format!("Invalid boolean reply: {}", body)),
+        },
+        "(" => Ok(Reply::BigNumber(body.to_string())),
+        "!" => match read_length(body)? {
+            Some(len) => Ok(Reply::Error(
+                String::from_utf8_lossy(&read_blob(reader, len)?).to_string(),
+            )),
+            None => Ok(Reply::Nil),
+        },
+        "=" => match read_length(body)? {
+            Some(len) => {
+                let blob = read_blob(reader, len)?;
+                let text = String::from_utf8_lossy(&blob).to_string();
+                match text.split_once(':') {
+                    Some((format, content)) => {
+                        Ok(Reply::Verbatim(format.to_string(), content.to_string()))
+                    }
+                    None => Err("Malformed verbatim reply from server.".to_string()),
+                }
+            }
+            None => Ok(Reply::Nil),
+        },
+        "%" => match read_length(body)? {
+            Some(len) => {
+                let mut pairs = Vec::with_capacity(len);
+                for _ in 0..len {
+                    let key = read_reply(reader)?;
+                    let value = read_reply(reader)?;
+                    pairs.push((key, value));
+                }
+                Ok(Reply::Map(pairs))
+            }
+            None => Ok(Reply::Nil),
+        },
+        "~" => match read_length(body)? {
+            Some(len) => Ok(Reply::Set(read_many(reader, len)?)),
+            None => Ok(Reply::Nil),
+        },
+        ">" => match read_length(body)? {
+            Some(len) => Ok(Reply::Push(read_many(reader, len)?)),
+            None => Ok(Reply::Nil),
+        },
+        "|" => match read_length(body)? {
+            Some(len) => {
+                // Attributes are out-of-band metadata attached to the
+                // reply that follows them; rr has no use for them.
+                for _ in 0..len * 2 {
+                    read_reply(reader)?;
+                }
+                read_reply(reader)
+            }
+            None => read_reply(reader),
+        },
+        _ => Err(format!("Unknown reply type from server: {}", kind)),
+    }
+}
+
+/// Split the `field:value` text returned by INFO and CLUSTER INFO
+/// into a map, skipping section headers and blank lines.
+///
+/// # Arguments
+/// * `text` - Raw text of the INFO reply.
+///
+/// # Examples
+/// ```
+/// let fields = parse_info_fields("# Cluster\r\ncluster_state:ok\r\n");
+/// assert_eq!(fields.get("cluster_state"), Some(&"ok".to_string()));
+/// ```
+pub fn parse_info_fields(text: &str) -> HashMap<String, String> {
+    text.lines()
+        .map(|line| line.trim())
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter_map(|line| line.split_once(':'))
+        .map(|(field, value)| (field.to_string(), value.to_string()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::io::BufReader;
+
+    use super::*;
+
+    fn parse(raw: &[u8]) -> Result<Reply, String> {
+        read_reply(&mut BufReader::new(raw))
+    }
+
+    #[test]
+    fn test_encode_command() {
+        let encoded = encode_command(&["CLUSTER", "INFO"]);
+        assert_eq!(encoded, b"*2\r\n$7\r\nCLUSTER\r\n$4\r\nINFO\r\n".to_vec());
+    }
+
+    #[test]
+    fn test_read_reply_resp2_types() {
+        assert_eq!(parse(b"+OK\r\n"), Ok(Reply::Status("OK".to_string())));
+        assert_eq!(
+            parse(b"-ERR bad\r\n"),
+            Ok(Reply::Error("ERR bad".to_string()))
+        );
+        assert_eq!(parse(b":-12\r\n"), Ok(Reply::Integer(-12)));
+        assert_eq!(
+            parse(b"$5\r\na\r\nbc\r\n"),
+            Ok(Reply::Bulk(b"a\r\nbc".to_vec()))
+        );
+        assert_eq!(parse(b"$-1\r\n"), Ok(Reply::Nil));
+        assert_eq!(
+            parse(b"*2\r\n:1\r\n$1\r\nx\r\n"),
+            Ok(Reply::Array(vec![
+                Reply::Integer(1),
+                Reply::Bulk(b"x".to_vec())
+            ]))
+        );
+    }
+
+    #[test]
+    fn test_read_reply_resp3_types() {
+        assert_eq!(parse(b"_\r\n"), Ok(Reply::Nil));
+        assert_eq!(parse(b",1.5\r\n"), Ok(Reply::Double(1.5)));
+        assert_eq!(parse(b"#t\r\n"), Ok(Reply::Boolean(true)));
+        assert_eq!(
+            parse(b"=8\r\ntxt:some\r\n"),
+            Ok(Reply::Verbatim("txt".to_string(), "some".to_string()))
+        );
+        assert_eq!(
+            parse(b"%1\r\n+role\r\n+master\r\n"),
+            Ok(Reply::Map(vec![(
+                Reply::Status("role".to_string()),
+                Reply::Status("master".to_string())
+            )]))
+        );
+        assert_eq!(parse(b"|1\r\n+ttl\r\n:3\r\n:7\r\n"), Ok(Reply::Integer(7)));
+    }
+
+    #[test]
+    fn test_read_reply_malformed() {
+        assert!(parse(b"").is_err());
+        assert!(parse(b"+OK\n").is_err());
+        assert!(parse(b"$3\r\nab\r\n").is_err());
+        assert!(parse(b"@what\r\n").is_err());
+    }
+
+    #[test]
+    fn test_parse_info_fields() {
+        let fields = parse_info_fields("# Server\r\nredis_version:7.0.0\r\n\r\nrole:master\r\n");
+        assert_eq!(fields.len(), 2);
+        assert_eq!(fields.get("role"), Some(&"master".to_string()));
+    }
+}